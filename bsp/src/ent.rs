@@ -0,0 +1,303 @@
+//! Entity lump as text, the same format as ripent `.ent`
+//!
+//! Entity order and key order are kept so an exported file can be diffed against the edited one.
+use std::{
+    ffi::OsStr,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use eyre::eyre;
+
+use crate::types::{Bsp, Entity};
+
+/// Writes entities the same way they are in the entity lump.
+pub fn entities_to_string(entities: &[Entity]) -> String {
+    let mut res = String::new();
+
+    entities.iter().for_each(|entity| {
+        res += "{\n";
+
+        entity
+            .iter()
+            .for_each(|(key, value)| res += format!("\"{}\" \"{}\"\n", key, value).as_str());
+
+        res += "}\n";
+    });
+
+    res
+}
+
+/// Parses entity text and reports where the syntax is wrong.
+///
+/// Duplicate keys in one entity are not allowed because only one of them would be kept.
+pub fn parse_entities_text(text: &str) -> eyre::Result<Vec<Entity>> {
+    let mut res = vec![];
+    let mut current: Option<Entity> = None;
+    // key waiting for its value
+    let mut key: Option<String> = None;
+
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => (),
+            // not part of the format but ripent users like to comment things out
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            '{' => {
+                if current.is_some() {
+                    return Err(eyre!("Line {}: \"{{\" inside an entity.", line));
+                }
+
+                current = Some(Entity::new());
+            }
+            '}' => {
+                let Some(entity) = current.take() else {
+                    return Err(eyre!("Line {}: \"}}\" without \"{{\".", line));
+                };
+
+                if let Some(key) = key.take() {
+                    return Err(eyre!("Line {}: key \"{}\" does not have value.", line, key));
+                }
+
+                res.push(entity);
+            }
+            '"' => {
+                let Some(entity) = current.as_mut() else {
+                    return Err(eyre!("Line {}: text outside of an entity.", line));
+                };
+
+                let start_line = line;
+                let mut s = String::new();
+                let mut closed = false;
+
+                for c in chars.by_ref() {
+                    match c {
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        '\n' => {
+                            return Err(eyre!("Line {}: missing closing quote.", start_line));
+                        }
+                        c => s.push(c),
+                    }
+                }
+
+                if !closed {
+                    return Err(eyre!("Line {}: missing closing quote.", start_line));
+                }
+
+                match key.take() {
+                    None => key = Some(s),
+                    Some(k) => {
+                        if entity.contains_key(&k) {
+                            return Err(eyre!("Line {}: duplicate key \"{}\".", line, k));
+                        }
+
+                        entity.insert(k, s);
+                    }
+                }
+            }
+            c => {
+                return Err(eyre!("Line {}: unexpected character '{}'.", line, c));
+            }
+        }
+    }
+
+    if current.is_some() {
+        return Err(eyre!("Last entity is not closed."));
+    }
+
+    Ok(res)
+}
+
+impl Bsp {
+    /// Checks entities against the BSP.
+    ///
+    /// The first entity must be worldspawn and every brush model reference `*N` must exist.
+    pub fn check_entities(&self, entities: &[Entity]) -> eyre::Result<()> {
+        let is_worldspawn = entities
+            .first()
+            .and_then(|entity| entity.get("classname"))
+            .is_some_and(|classname| classname == "worldspawn");
+
+        if !is_worldspawn {
+            return Err(eyre!("First entity must be worldspawn."));
+        }
+
+        let mut used_models = vec![false; self.models.len()];
+
+        for (entity_index, entity) in entities.iter().enumerate() {
+            if !entity.contains_key("classname") {
+                return Err(eyre!("Entity {} does not have classname.", entity_index));
+            }
+
+            if entity_index != 0
+                && entity
+                    .get("classname")
+                    .is_some_and(|classname| classname == "worldspawn")
+            {
+                return Err(eyre!("Entity {} is another worldspawn.", entity_index));
+            }
+
+            let Some(model) = entity
+                .get("model")
+                .and_then(|model| model.strip_prefix('*'))
+            else {
+                continue;
+            };
+
+            let Ok(model_index) = model.parse::<usize>() else {
+                return Err(eyre!(
+                    "Entity {} has invalid brush model \"*{}\".",
+                    entity_index,
+                    model
+                ));
+            };
+
+            if model_index == 0 || model_index >= self.models.len() {
+                return Err(eyre!(
+                    "Entity {} uses brush model *{} but the BSP has *1 to *{}.",
+                    entity_index,
+                    model_index,
+                    self.models.len() - 1
+                ));
+            }
+
+            if used_models[model_index] {
+                return Err(eyre!(
+                    "Entity {} uses brush model *{} which is already used.",
+                    entity_index,
+                    model_index
+                ));
+            }
+
+            used_models[model_index] = true;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the entity lump as text.
+    pub fn export_entities(&self) -> String {
+        entities_to_string(&self.entities)
+    }
+
+    /// Replaces entities with the ones from text after checking them.
+    pub fn import_entities(&mut self, text: &str) -> eyre::Result<()> {
+        let entities = parse_entities_text(text)?;
+
+        self.check_entities(&entities)?;
+
+        self.entities = entities;
+
+        Ok(())
+    }
+
+    pub fn export_entities_to_file(
+        &self,
+        path: impl AsRef<Path> + Into<PathBuf>,
+    ) -> eyre::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(path)?;
+
+        file.write_all(self.export_entities().as_bytes())?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    pub fn import_entities_from_file(
+        &mut self,
+        path: impl AsRef<Path> + AsRef<OsStr>,
+    ) -> eyre::Result<()> {
+        let text = std::fs::read_to_string(path)?;
+
+        self.import_entities(&text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let text = bsp.export_entities();
+        bsp.import_entities(&text).unwrap();
+
+        assert_eq!(bsp.export_entities(), text);
+
+        // also survives writing the BSP
+        let bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+        assert_eq!(bsp.export_entities(), text);
+    }
+
+    #[test]
+    fn key_order() {
+        let text = "{\n\"zzz\" \"1\"\n\"classname\" \"worldspawn\"\n\"aaa\" \"2\"\n}\n";
+        let entities = parse_entities_text(text).unwrap();
+
+        assert_eq!(
+            entities[0].keys().collect::<Vec<&String>>(),
+            vec!["zzz", "classname", "aaa"]
+        );
+        assert_eq!(entities_to_string(&entities), text);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(parse_entities_text("{\n\"classname\" \"worldspawn\"\n").is_err());
+        assert!(parse_entities_text("\"classname\" \"worldspawn\"").is_err());
+        assert!(parse_entities_text("{\n\"classname\"\n}").is_err());
+        assert!(parse_entities_text("{\n\"classname \"worldspawn\"\n}").is_err());
+        assert!(parse_entities_text("{\n\"a\" \"1\"\n\"a\" \"2\"\n}").is_err());
+        assert!(parse_entities_text("{ { } }").is_err());
+
+        let err = parse_entities_text("{\n\"classname\" \"worldspawn\"\n}\n}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Line 4"));
+    }
+
+    #[test]
+    fn model_references() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let model_count = bsp.models.len();
+
+        let bad = format!(
+            "{{\n\"classname\" \"worldspawn\"\n}}\n{{\n\"classname\" \"func_wall\"\n\"model\" \"*{}\"\n}}\n",
+            model_count
+        );
+        assert!(bsp.import_entities(&bad).is_err());
+
+        assert!(bsp
+            .import_entities("{\n\"classname\" \"info_player_start\"\n}\n")
+            .is_err());
+
+        // nothing changes after failing
+        assert_eq!(
+            bsp.export_entities(),
+            Bsp::from_bytes(file).unwrap().export_entities()
+        );
+    }
+}
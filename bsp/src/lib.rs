@@ -1,12 +1,25 @@
 mod constants;
+mod ent;
+mod lightmap;
+mod limits;
 mod parser;
+#[cfg(test)]
+mod test_utils;
+mod texture;
+mod trace;
 mod types;
 mod utils;
+mod vis;
 
 pub use parser::parse_bsp;
 pub use types::Bsp;
 
+pub use ent::{entities_to_string, parse_entities_text};
+pub use lightmap::{LightmapExtents, LIGHTMAP_SCALE, NO_LIGHT_STYLE, TEX_SPECIAL};
+pub use limits::{LimitUsage, LimitsReport, LIMIT_WARNING_PERCENTAGE};
+pub use trace::{Hull, Trace};
 pub use types::*;
+pub use vis::{decompress_vis_row, Pvs};
 
 pub use glam::Vec3;
 
@@ -31,26 +44,150 @@ mod test {
     #[test]
     fn parse_write() {
         let file = include_bytes!("tests/bsp_compile.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let res = bsp.write_to_bytes();
 
+        assert_eq!(file, res.as_slice());
+    }
+
+    #[test]
+    fn parse_write2() {
+        let file = include_bytes!("tests/normal.bsp");
         let bsp = Bsp::from_bytes(file).unwrap();
 
         let res = bsp.write_to_bytes();
 
-        println!("file {} res {}", file.len(), res.len());
+        assert_eq!(file, res.as_slice());
+    }
+
+    #[test]
+    fn empty_lightmap_lump() {
+        let file = include_bytes!("tests/bsp_compile.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        assert!(bsp.lightmap.is_empty());
+        assert_eq!(bsp.lightmap_lump_length, 1);
 
-        // assert_eq!(file, res.as_slice());
-        bsp.write_to_file("/home/khang/gchimp/bsp/src/tests/bsp_out.bsp")
-            .unwrap()
+        let res = bsp.write_to_bytes();
+        let bsp_again = Bsp::from_bytes(&res).unwrap();
+
+        assert_eq!(bsp_again.lightmap_lump_length, 1);
     }
 
     #[test]
-    fn parse_write2() {
+    fn write_file() {
         let file = include_bytes!("tests/normal.bsp");
         let bsp = Bsp::from_bytes(file).unwrap();
-        bsp.write_to_file("/home/khang/gchimp/bsp/src/tests/normal_out.bsp")
+
+        let path = std::env::temp_dir().join("gchimp_bsp_normal_out.bsp");
+        bsp.write_to_file(&path).unwrap();
+
+        assert_eq!(file, std::fs::read(&path).unwrap().as_slice());
+    }
+
+    #[test]
+    fn keep_lump_order() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.lump_order.reverse();
+
+        let res = bsp.write_to_bytes();
+        let bsp_again = Bsp::from_bytes(&res).unwrap();
+
+        assert_eq!(bsp_again.lump_order, bsp.lump_order);
+        assert_eq!(bsp_again.write_to_bytes(), res);
+    }
+
+    #[test]
+    fn blue_shift() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.variant = BspVariant::BlueShift;
+
+        let res = bsp.write_to_bytes();
+
+        // first lump length is planes length
+        assert_eq!(res[8..12], file[16..20]);
+
+        let bsp = Bsp::from_bytes(&res).unwrap();
+
+        assert_eq!(bsp.variant, BspVariant::BlueShift);
+        assert_eq!(bsp.write_to_bytes(), res);
+
+        let mut bsp = bsp;
+        bsp.variant = BspVariant::GoldSrc;
+
+        assert_eq!(bsp.write_to_bytes(), file);
+    }
+
+    #[test]
+    fn quake() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.variant = BspVariant::Quake;
+        bsp.faces.iter_mut().for_each(|face| {
+            if face.lightmap_offset > 0 {
+                face.lightmap_offset /= 3;
+            }
+        });
+
+        let res = bsp.write_to_bytes();
+        let quake = Bsp::from_bytes(&res).unwrap();
+
+        assert_eq!(quake.variant, BspVariant::Quake);
+        assert_eq!(quake.write_to_bytes(), res);
+
+        assert_eq!(quake.textures.len(), bsp.textures.len());
+        assert_eq!(
+            quake.textures[0].mip_images[0].data.get_bytes(),
+            bsp.textures[0].mip_images[0].data.get_bytes()
+        );
+
+        // same lightmap blocks but grayscale
+        let face_index = (0..bsp.faces.len())
+            .find(|&face_index| bsp.face_has_lightmap(face_index))
+            .unwrap();
+
+        assert_eq!(
+            quake.face_lightmap(face_index).unwrap()[0].len(),
+            bsp.face_lightmap(face_index).unwrap()[0].len()
+        );
+        assert!(quake.lightmap.iter().all(|[r, g, b]| r == g && g == b));
+    }
+
+    #[test]
+    fn quake_bsp29() {
+        let file = include_bytes!("tests/quake.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        assert_eq!(bsp.variant, BspVariant::Quake);
+        assert_eq!(bsp.write_to_bytes(), file);
+
+        assert_eq!(bsp.textures.len(), 1);
+        assert_eq!(bsp.textures[0].texture_name.get_string(), "wall");
+        assert_eq!(bsp.textures[0].width, 16);
+        assert_eq!(
+            bsp.textures[0].mip_images[0].data.get_bytes().len(),
+            16 * 16
+        );
+
+        // 64 units wide faces have 5x5 lightmaps, one byte each
+        assert_eq!(bsp.lightmap.len(), 6 * 25);
+        assert_eq!(bsp.face_lightmap(1).unwrap()[0].len(), 25);
+        assert!(bsp.lightmap.iter().all(|[r, g, b]| r == g && g == b));
+
+        // player start is above the cube
+        let start = test_utils::player_start(&bsp);
+        let trace = bsp
+            .trace_line(start, start - Vec3::new(0., 0., 100.))
             .unwrap();
 
-        // assert_eq!(file, res.as_slice());
+        assert!(trace.hit());
+        assert!((trace.end_pos.z - 32.).abs() < 0.1);
     }
 
     #[test]
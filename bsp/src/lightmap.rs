@@ -0,0 +1,234 @@
+//! Per-face lightmap
+//!
+//! Every face with a lightmap has one block of texels for each light style.
+//! Blocks are next to each other starting from `lightmap_offset`, which is in bytes.
+//! Quake lightmap is grayscale but it is stored as RGB like the others.
+//!
+//! Lightmap dimensions are derived from texinfo the same way as `CalcSurfaceExtents`.
+use eyre::eyre;
+
+use crate::types::{Bsp, LightMap};
+
+/// One lightmap texel covers this many texture pixels.
+pub const LIGHTMAP_SCALE: i32 = 16;
+/// Style slot that is not used.
+pub const NO_LIGHT_STYLE: u8 = 255;
+/// Texinfo flag for faces without lightmap, such as sky and water.
+pub const TEX_SPECIAL: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LightmapExtents {
+    /// Smallest texture coordinate of the face, snapped to [`LIGHTMAP_SCALE`].
+    pub texture_mins: [i32; 2],
+    /// Size of the face in texture space, snapped to [`LIGHTMAP_SCALE`].
+    pub extents: [i32; 2],
+}
+
+impl LightmapExtents {
+    /// Number of texels horizontally.
+    pub fn width(&self) -> usize {
+        (self.extents[0] / LIGHTMAP_SCALE) as usize + 1
+    }
+
+    /// Number of texels vertically.
+    pub fn height(&self) -> usize {
+        (self.extents[1] / LIGHTMAP_SCALE) as usize + 1
+    }
+
+    /// Number of texels of one light style.
+    pub fn texel_count(&self) -> usize {
+        self.width() * self.height()
+    }
+}
+
+impl Bsp {
+    /// Computes the lightmap extents of a face from its texinfo.
+    pub fn face_lightmap_extents(&self, face_index: usize) -> LightmapExtents {
+        let face = &self.faces[face_index];
+        let texinfo = &self.texinfo[face.texinfo as usize];
+
+        let mut mins = [f64::MAX; 2];
+        let mut maxs = [f64::MIN; 2];
+
+        // compilers do this in double
+        let axes = [
+            (texinfo.u.as_dvec3(), texinfo.u_offset as f64),
+            (texinfo.v.as_dvec3(), texinfo.v_offset as f64),
+        ];
+
+        self.face_vertices(face_index)
+            .into_iter()
+            .for_each(|vertex| {
+                let vertex = vertex.as_dvec3();
+
+                axes.iter().enumerate().for_each(|(axis, (vec, offset))| {
+                    let value = vertex.dot(*vec) + offset;
+
+                    mins[axis] = mins[axis].min(value);
+                    maxs[axis] = maxs[axis].max(value);
+                });
+            });
+
+        let mut texture_mins = [0; 2];
+        let mut extents = [0; 2];
+
+        for axis in 0..2 {
+            let bmins = (mins[axis] / LIGHTMAP_SCALE as f64).floor() as i32;
+            let bmaxs = (maxs[axis] / LIGHTMAP_SCALE as f64).ceil() as i32;
+
+            texture_mins[axis] = bmins * LIGHTMAP_SCALE;
+            extents[axis] = (bmaxs - bmins) * LIGHTMAP_SCALE;
+        }
+
+        LightmapExtents {
+            texture_mins,
+            extents,
+        }
+    }
+
+    /// Number of light styles a face has.
+    pub fn face_light_style_count(&self, face_index: usize) -> usize {
+        self.faces[face_index]
+            .styles
+            .iter()
+            .take_while(|&&style| style != NO_LIGHT_STYLE)
+            .count()
+    }
+
+    /// Whether a face has lightmap data.
+    pub fn face_has_lightmap(&self, face_index: usize) -> bool {
+        let face = &self.faces[face_index];
+
+        face.lightmap_offset >= 0
+            && self.face_light_style_count(face_index) > 0
+            && self.texinfo[face.texinfo as usize].flags & TEX_SPECIAL == 0
+    }
+
+    /// Index into `Bsp::lightmap` of the first texel of a light style of a face.
+    fn face_lightmap_start(&self, face_index: usize, style_index: usize) -> usize {
+        let face = &self.faces[face_index];
+        let texel_count = self.face_lightmap_extents(face_index).texel_count();
+
+        face.lightmap_offset as usize / self.variant.lightmap_texel_size()
+            + style_index * texel_count
+    }
+
+    /// Returns the lightmap of every light style of a face.
+    ///
+    /// Texels are row major with width from [`Bsp::face_lightmap_extents`].
+    /// Returns `None` if the face has no lightmap or the data is out of bound.
+    pub fn face_lightmap(&self, face_index: usize) -> Option<Vec<LightMap>> {
+        if !self.face_has_lightmap(face_index) {
+            return None;
+        }
+
+        let texel_count = self.face_lightmap_extents(face_index).texel_count();
+
+        (0..self.face_light_style_count(face_index))
+            .map(|style_index| {
+                let start = self.face_lightmap_start(face_index, style_index);
+
+                self.lightmap
+                    .get(start..start + texel_count)
+                    .map(|texels| texels.to_vec())
+            })
+            .collect()
+    }
+
+    /// Replaces the lightmap of a light style of a face.
+    ///
+    /// The amount of texels must be the same as the original.
+    pub fn set_face_lightmap(
+        &mut self,
+        face_index: usize,
+        style_index: usize,
+        texels: &[[u8; 3]],
+    ) -> eyre::Result<()> {
+        if !self.face_has_lightmap(face_index) {
+            return Err(eyre!("Face {} does not have lightmap.", face_index));
+        }
+
+        if style_index >= self.face_light_style_count(face_index) {
+            return Err(eyre!(
+                "Face {} does not have light style slot {}.",
+                face_index,
+                style_index
+            ));
+        }
+
+        let texel_count = self.face_lightmap_extents(face_index).texel_count();
+
+        if texels.len() != texel_count {
+            return Err(eyre!(
+                "Face {} needs {} texels but got {}.",
+                face_index,
+                texel_count,
+                texels.len()
+            ));
+        }
+
+        let start = self.face_lightmap_start(face_index, style_index);
+
+        let Some(dest) = self.lightmap.get_mut(start..start + texel_count) else {
+            return Err(eyre!("Face {} lightmap is out of bound.", face_index));
+        };
+
+        dest.copy_from_slice(texels);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lightmap_fits_lump() {
+        let file = include_bytes!("tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let lit_faces = (0..bsp.faces.len())
+            .filter(|&face_index| bsp.face_has_lightmap(face_index))
+            .collect::<Vec<usize>>();
+
+        assert!(!lit_faces.is_empty());
+
+        let total_texels: usize = lit_faces
+            .iter()
+            .map(|&face_index| {
+                let lightmap = bsp.face_lightmap(face_index).unwrap();
+
+                assert_eq!(lightmap.len(), bsp.face_light_style_count(face_index));
+
+                lightmap.iter().map(|style| style.len()).sum::<usize>()
+            })
+            .sum();
+
+        // compiler might leave some extra data at the end
+        assert!(total_texels <= bsp.lightmap.len());
+    }
+
+    #[test]
+    fn set_lightmap() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let face_index = (0..bsp.faces.len())
+            .find(|&face_index| bsp.face_has_lightmap(face_index))
+            .unwrap();
+
+        let texel_count = bsp.face_lightmap_extents(face_index).texel_count();
+        let red = vec![[255, 0, 0]; texel_count];
+
+        assert!(bsp.set_face_lightmap(face_index, 0, &red[1..]).is_err());
+        assert!(bsp.set_face_lightmap(face_index, 4, &red).is_err());
+
+        bsp.set_face_lightmap(face_index, 0, &red).unwrap();
+
+        let bytes = bsp.write_to_bytes();
+        let bsp = Bsp::from_bytes(&bytes).unwrap();
+
+        assert_eq!(bsp.face_lightmap(face_index).unwrap()[0], red);
+    }
+}
@@ -0,0 +1,291 @@
+//! Lump usage against GoldSrc limits
+//!
+//! Limits are the stock compiler and engine limits. Some compilers raise a few of them but the engine
+//! does not always agree, so anything over is reported.
+use byte_writer::ByteWriter;
+
+use crate::{
+    constants::{
+        MAX_MAP_CLIPNODES, MAX_MAP_EDGES, MAX_MAP_ENTITIES, MAX_MAP_ENTSTRING, MAX_MAP_FACES,
+        MAX_MAP_LEAFS, MAX_MAP_LIGHTING, MAX_MAP_MARKSURFACES, MAX_MAP_MIPTEX, MAX_MAP_MODELS,
+        MAX_MAP_NODES, MAX_MAP_PLANES, MAX_MAP_SURFEDGES, MAX_MAP_TEXINFO, MAX_MAP_TEXTURES,
+        MAX_MAP_VERTS, MAX_MAP_VISIBILITY,
+    },
+    ent::entities_to_string,
+    types::Bsp,
+};
+
+/// Usage at or above this percentage gets a warning.
+pub const LIMIT_WARNING_PERCENTAGE: f64 = 90.;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitUsage {
+    pub name: &'static str,
+    pub count: usize,
+    pub max: usize,
+    /// Whether count is in bytes rather than elements
+    pub is_bytes: bool,
+}
+
+impl LimitUsage {
+    pub fn percentage(&self) -> f64 {
+        self.count as f64 / self.max as f64 * 100.
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.count > self.max
+    }
+
+    pub fn is_near(&self) -> bool {
+        !self.is_over() && self.percentage() >= LIMIT_WARNING_PERCENTAGE
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitsReport {
+    pub usages: Vec<LimitUsage>,
+    /// Clipnode count of each hull, hull 0 does not have clipnodes so it is always 0.
+    pub hull_clipnodes: [usize; 4],
+}
+
+impl LimitsReport {
+    /// Human readable warnings for limits that are close or exceeded.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut res = self
+            .usages
+            .iter()
+            .filter_map(|usage| {
+                if usage.is_over() {
+                    Some(format!(
+                        "{} exceeds limit: {} / {}",
+                        usage.name, usage.count, usage.max
+                    ))
+                } else if usage.is_near() {
+                    Some(format!(
+                        "{} is close to limit: {} / {} ({:.1}%)",
+                        usage.name,
+                        usage.count,
+                        usage.max,
+                        usage.percentage()
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<String>>();
+
+        // clipnode children are i16 so going over wraps into leaf contents
+        if self
+            .usages
+            .iter()
+            .any(|usage| usage.name == "clipnodes" && usage.is_over())
+        {
+            res.push(format!(
+                "Clipnodes per hull: {}, {}, {}. Simplify brushes or use CLIP/NULL on detail.",
+                self.hull_clipnodes[1], self.hull_clipnodes[2], self.hull_clipnodes[3]
+            ));
+        }
+
+        res
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.usages.iter().any(|usage| usage.is_over())
+    }
+}
+
+impl std::fmt::Display for LimitsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<16}{:>10}{:>10}{:>9}", "", "used", "max", "%")?;
+
+        for usage in &self.usages {
+            let name = if usage.is_bytes {
+                format!("{} (bytes)", usage.name)
+            } else {
+                usage.name.to_string()
+            };
+
+            writeln!(
+                f,
+                "{:<16}{:>10}{:>10}{:>8.1}%{}",
+                name,
+                usage.count,
+                usage.max,
+                usage.percentage(),
+                if usage.is_over() {
+                    " !!!"
+                } else if usage.is_near() {
+                    " !"
+                } else {
+                    ""
+                }
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Bsp {
+    /// Size of the texture lump in bytes.
+    fn miptex_lump_size(&self) -> usize {
+        let textures_size: usize = self
+            .textures
+            .iter()
+            .map(|texture| {
+                let mut writer = ByteWriter::new();
+                texture.write(&mut writer);
+                writer.data.len()
+            })
+            .sum();
+
+        // texture count and offsets
+        4 + 4 * self.textures.len() + textures_size
+    }
+
+    /// Number of clipnodes reachable from the head node of a hull, counted over every model.
+    fn hull_clipnode_count(&self, hull: usize) -> usize {
+        let mut visited = vec![false; self.clipnodes.len()];
+        let mut stack = self
+            .models
+            .iter()
+            .map(|model| model.head_nodes[hull])
+            .collect::<Vec<i32>>();
+
+        while let Some(node) = stack.pop() {
+            // negative is contents
+            if node < 0 {
+                continue;
+            }
+
+            let Some(clipnode) = self.clipnodes.get(node as usize) else {
+                continue;
+            };
+
+            if visited[node as usize] {
+                continue;
+            }
+
+            visited[node as usize] = true;
+
+            stack.extend(clipnode.children.iter().map(|&child| child as i32));
+        }
+
+        visited.into_iter().filter(|&visited| visited).count()
+    }
+
+    /// Counts everything against GoldSrc limits.
+    pub fn limits_report(&self) -> LimitsReport {
+        let usage = |name, count, max| LimitUsage {
+            name,
+            count,
+            max,
+            is_bytes: false,
+        };
+        let bytes_usage = |name, count, max| LimitUsage {
+            name,
+            count,
+            max,
+            is_bytes: true,
+        };
+
+        let usages = vec![
+            usage("models", self.models.len(), MAX_MAP_MODELS),
+            usage("entities", self.entities.len(), MAX_MAP_ENTITIES),
+            // null terminated
+            bytes_usage(
+                "entdata",
+                entities_to_string(&self.entities).len() + 1,
+                MAX_MAP_ENTSTRING,
+            ),
+            usage("planes", self.planes.len(), MAX_MAP_PLANES),
+            usage("textures", self.textures.len(), MAX_MAP_TEXTURES),
+            bytes_usage("miptex", self.miptex_lump_size(), MAX_MAP_MIPTEX),
+            usage("vertices", self.vertices.len(), MAX_MAP_VERTS),
+            bytes_usage("visdata", self.visibility.len(), MAX_MAP_VISIBILITY),
+            usage("nodes", self.nodes.len(), MAX_MAP_NODES),
+            usage("texinfo", self.texinfo.len(), MAX_MAP_TEXINFO),
+            usage("faces", self.faces.len(), MAX_MAP_FACES),
+            bytes_usage(
+                "lightdata",
+                self.lightmap.len() * self.variant.lightmap_texel_size(),
+                MAX_MAP_LIGHTING,
+            ),
+            usage("clipnodes", self.clipnodes.len(), MAX_MAP_CLIPNODES),
+            usage("leaves", self.leaves.len(), MAX_MAP_LEAFS),
+            usage(
+                "marksurfaces",
+                self.mark_surfaces.len(),
+                MAX_MAP_MARKSURFACES,
+            ),
+            usage("edges", self.edges.len(), MAX_MAP_EDGES),
+            usage("surfedges", self.surf_edges.len(), MAX_MAP_SURFEDGES),
+        ];
+
+        LimitsReport {
+            usages,
+            hull_clipnodes: [
+                0,
+                self.hull_clipnode_count(1),
+                self.hull_clipnode_count(2),
+                self.hull_clipnode_count(3),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normal_map() {
+        let file = include_bytes!("tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let report = bsp.limits_report();
+
+        assert!(!report.has_errors());
+        assert!(report.warnings().is_empty());
+
+        let hull_total: usize = report.hull_clipnodes.iter().sum();
+        assert!(hull_total <= bsp.clipnodes.len());
+
+        // every lump is in the table
+        assert_eq!(report.to_string().lines().count(), report.usages.len() + 1);
+    }
+
+    #[test]
+    fn warnings() {
+        let report = LimitsReport {
+            usages: vec![
+                LimitUsage {
+                    name: "clipnodes",
+                    count: MAX_MAP_CLIPNODES + 1,
+                    max: MAX_MAP_CLIPNODES,
+                    is_bytes: false,
+                },
+                LimitUsage {
+                    name: "planes",
+                    count: MAX_MAP_PLANES - 1,
+                    max: MAX_MAP_PLANES,
+                    is_bytes: false,
+                },
+                LimitUsage {
+                    name: "models",
+                    count: 1,
+                    max: MAX_MAP_MODELS,
+                    is_bytes: false,
+                },
+            ],
+            hull_clipnodes: [0, 1, 2, 3],
+        };
+
+        let warnings = report.warnings();
+
+        assert!(report.has_errors());
+        // clipnodes over, clipnodes per hull, planes near
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains("clipnodes exceeds"));
+    }
+}
@@ -9,17 +9,18 @@ use nom::{
     number::complete::{le_f32, le_i16, le_i32, le_u16, le_u32, le_u8},
     sequence::{delimited, tuple},
 };
-use wad::parse_miptex;
+use wad::{parse_miptex, parse_quake_miptex};
 
 use crate::{
     constants::{
         BSP_VERSION, HEADER_LUMPS, LUMP_CLIPNODES, LUMP_EDGES, LUMP_ENTITIES, LUMP_FACES,
         LUMP_LEAVES, LUMP_LIGHTING, LUMP_MARKSURFACES, LUMP_MODELS, LUMP_NODES, LUMP_PLANES,
         LUMP_SURFEDGES, LUMP_TEXINFO, LUMP_TEXTURES, LUMP_VERTICES, LUMP_VISIBILITY, MAX_MAP_HULLS,
+        QUAKE_BSP_VERSION,
     },
     types::{
-        Bsp, ClipNode, Edge, Entity, Face, IResult, Leaf, LightMap, LumpHeader, MarkSurface, Model,
-        Node, Plane, SResult, SurfEdge, TexInfo, Texture, Vertex,
+        Bsp, BspVariant, ClipNode, Edge, Entity, Face, IResult, Leaf, LightMap, LumpHeader,
+        MarkSurface, Model, Node, Plane, SResult, SurfEdge, TexInfo, Texture, Vertex,
     },
     utils::{between_braces, quoted_text},
 };
@@ -81,14 +82,18 @@ fn parse_planes(i: &[u8]) -> IResult<Vec<Plane>> {
     all_consuming(many0(parse_plane))(i)
 }
 
-fn parse_textures(i: &[u8]) -> IResult<Vec<Texture>> {
+fn parse_textures(i: &[u8], variant: BspVariant) -> IResult<Vec<Texture>> {
     let (header, tex_count) = le_u32(i)?;
     let (_, offsets) = count(le_i32, tex_count as usize)(header)?;
 
     let mut miptexes: Vec<Texture> = vec![];
 
     for offset in offsets {
-        let (_, res) = parse_miptex(&i[(offset as usize)..])?;
+        let (_, res) = if variant == BspVariant::Quake {
+            parse_quake_miptex(&i[(offset as usize)..])?
+        } else {
+            parse_miptex(&i[(offset as usize)..])?
+        };
 
         miptexes.push(res);
     }
@@ -180,12 +185,16 @@ fn parse_faces(i: &[u8]) -> IResult<Vec<Face>> {
     all_consuming(many0(parse_face))(i)
 }
 
-fn parse_lightmap(i: &[u8]) -> IResult<LightMap> {
+fn parse_lightmap(i: &[u8], variant: BspVariant) -> IResult<LightMap> {
     // map with zero lightmap will have lump with size of 1
     if i.len() == 1 {
         return Ok((&[], vec![]));
     }
 
+    if variant == BspVariant::Quake {
+        return all_consuming(many0(map(le_u8, |x| [x, x, x])))(i);
+    }
+
     all_consuming(many0(map(count(le_u8, 3), |lightmap| {
         [lightmap[0], lightmap[1], lightmap[2]]
     })))(i)
@@ -284,17 +293,40 @@ fn parse_models(i: &[u8]) -> IResult<Vec<Model>> {
     all_consuming(many0(parse_model))(i)
 }
 
+/// Blue Shift has planes where entities should be.
+///
+/// Entity lump is text starting with "{" while planes lump is a bunch of floats.
+fn is_blue_shift(i: &[u8], lumps: &[LumpHeader]) -> bool {
+    let starts_with_brace = |lump: &LumpHeader| {
+        i.get((lump.offset as usize)..((lump.offset + lump.length) as usize))
+            .and_then(|section| section.iter().find(|x| !x.is_ascii_whitespace()))
+            .is_some_and(|&x| x == b'{')
+    };
+
+    !starts_with_brace(&lumps[LUMP_ENTITIES])
+        && lumps[LUMP_ENTITIES].length % 20 == 0
+        && starts_with_brace(&lumps[LUMP_PLANES])
+}
+
 pub fn parse_bsp(i: &[u8]) -> IResult<Bsp> {
     let (beginning, version) = le_i32(i)?;
 
-    if version != BSP_VERSION {
-        return context(
-            format!("Bsp Version is not 30: {}", BSP_VERSION).leak(),
-            fail,
-        )(i);
-    }
-
-    let (_, lumps) = count(parse_lump_header, HEADER_LUMPS)(beginning)?;
+    let (_, mut lumps) = count(parse_lump_header, HEADER_LUMPS)(beginning)?;
+
+    let variant = match version {
+        BSP_VERSION if is_blue_shift(i, &lumps) => {
+            lumps.swap(LUMP_ENTITIES, LUMP_PLANES);
+            BspVariant::BlueShift
+        }
+        BSP_VERSION => BspVariant::GoldSrc,
+        QUAKE_BSP_VERSION => BspVariant::Quake,
+        _ => {
+            return context(
+                format!("Bsp Version is not 29 or 30: {}", version).leak(),
+                fail,
+            )(i);
+        }
+    };
 
     let lump_section = |idx: usize| {
         &i[(lumps[idx].offset as usize)..((lumps[idx].offset + lumps[idx].length) as usize)]
@@ -302,14 +334,14 @@ pub fn parse_bsp(i: &[u8]) -> IResult<Bsp> {
 
     let (_, entities) = parse_entities(lump_section(LUMP_ENTITIES))?;
     let (_, planes) = parse_planes(lump_section(LUMP_PLANES))?;
-    let (_, textures) = parse_textures(lump_section(LUMP_TEXTURES))?;
+    let (_, textures) = parse_textures(lump_section(LUMP_TEXTURES), variant)?;
     let (_, vertices) = parse_vertices(lump_section(LUMP_VERTICES))?;
     // TODO
     let (_, visibility) = rest(lump_section(LUMP_VISIBILITY))?;
     let (_, nodes) = parse_nodes(lump_section(LUMP_NODES))?;
     let (_, texinfo) = parse_texinfo(lump_section(LUMP_TEXINFO))?;
     let (_, faces) = parse_faces(lump_section(LUMP_FACES))?;
-    let (_, lightmap) = parse_lightmap(lump_section(LUMP_LIGHTING))?;
+    let (_, lightmap) = parse_lightmap(lump_section(LUMP_LIGHTING), variant)?;
     let (_, clipnodes) = parse_clipnodes(lump_section(LUMP_CLIPNODES))?;
     let (_, leaves) = parse_leaves(lump_section(LUMP_LEAVES))?;
     let (_, mark_surfaces) = parse_mark_surfaces(lump_section(LUMP_MARKSURFACES))?;
@@ -317,6 +349,10 @@ pub fn parse_bsp(i: &[u8]) -> IResult<Bsp> {
     let (_, surf_edges) = parse_surf_edges(lump_section(LUMP_SURFEDGES))?;
     let (_, models) = parse_models(lump_section(LUMP_MODELS))?;
 
+    // empty lumps share offset with the next lump so they go first
+    let mut lump_order: [usize; HEADER_LUMPS] = std::array::from_fn(|idx| idx);
+    lump_order.sort_by_key(|&idx| (lumps[idx].offset, lumps[idx].length));
+
     Ok((
         &[],
         Bsp {
@@ -329,12 +365,15 @@ pub fn parse_bsp(i: &[u8]) -> IResult<Bsp> {
             texinfo,
             faces,
             lightmap,
+            lightmap_lump_length: lumps[LUMP_LIGHTING].length as usize,
             clipnodes,
             leaves,
             mark_surfaces,
             edges,
             surf_edges,
             models,
+            lump_order,
+            variant,
         },
     ))
 }
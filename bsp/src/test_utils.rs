@@ -0,0 +1,25 @@
+//! Helpers shared by the tests
+use glam::Vec3;
+
+use crate::Bsp;
+
+/// Origin of the first info_player_start.
+pub fn player_start(bsp: &Bsp) -> Vec3 {
+    bsp.entities
+        .iter()
+        .find(|entity| {
+            entity
+                .get("classname")
+                .is_some_and(|classname| classname == "info_player_start")
+        })
+        .and_then(|entity| entity.get("origin"))
+        .map(|origin| {
+            let origin = origin
+                .split_ascii_whitespace()
+                .map(|v| v.parse::<f32>().unwrap())
+                .collect::<Vec<f32>>();
+
+            Vec3::new(origin[0], origin[1], origin[2])
+        })
+        .unwrap()
+}
@@ -0,0 +1,260 @@
+//! Editing textures in the texture lump
+//!
+//! Faces refer to textures through `TexInfo::texture_index`, so removing a texture has to shift those indices.
+//! Textures without image data are external, the engine looks for them in the WAD files from worldspawn.
+use eyre::eyre;
+use wad::types::MipTex;
+
+use crate::types::{Bsp, Texture};
+
+impl Bsp {
+    /// Returns the index of a texture by name. Case insensitive like the engine.
+    pub fn find_texture(&self, texture_name: &str) -> Option<usize> {
+        self.textures.iter().position(|texture| {
+            texture
+                .texture_name
+                .get_string()
+                .eq_ignore_ascii_case(texture_name)
+        })
+    }
+
+    /// Whether any face uses the texture.
+    pub fn is_texture_used(&self, texture_index: usize) -> bool {
+        self.texinfo
+            .iter()
+            .any(|texinfo| texinfo.texture_index as usize == texture_index)
+    }
+
+    /// Adds a new texture and returns its index.
+    pub fn add_texture(&mut self, texture: Texture) -> eyre::Result<usize> {
+        let texture_name = texture.texture_name.get_string();
+
+        if self.find_texture(&texture_name).is_some() {
+            return Err(eyre!("Texture {} already exists.", texture_name));
+        }
+
+        self.textures.push(texture);
+
+        Ok(self.textures.len() - 1)
+    }
+
+    /// Replaces a texture but keeps its name so faces still refer to the same texture.
+    pub fn replace_texture(
+        &mut self,
+        texture_index: usize,
+        mut texture: Texture,
+    ) -> eyre::Result<()> {
+        let Some(old) = self.textures.get(texture_index) else {
+            return Err(eyre!("Texture index {} out of bound.", texture_index));
+        };
+
+        texture.texture_name = old.texture_name.clone();
+        self.textures[texture_index] = texture;
+
+        Ok(())
+    }
+
+    pub fn rename_texture(&mut self, texture_index: usize, texture_name: &str) -> eyre::Result<()> {
+        if texture_index >= self.textures.len() {
+            return Err(eyre!("Texture index {} out of bound.", texture_index));
+        }
+
+        if self
+            .find_texture(texture_name)
+            .is_some_and(|index| index != texture_index)
+        {
+            return Err(eyre!("Texture {} already exists.", texture_name));
+        }
+
+        self.textures[texture_index]
+            .texture_name
+            .set_name(texture_name)
+    }
+
+    /// Removes a texture that no face uses.
+    ///
+    /// Texinfo of later textures are shifted accordingly.
+    pub fn remove_texture(&mut self, texture_index: usize) -> eyre::Result<Texture> {
+        if texture_index >= self.textures.len() {
+            return Err(eyre!("Texture index {} out of bound.", texture_index));
+        }
+
+        if self.is_texture_used(texture_index) {
+            return Err(eyre!(
+                "Texture {} is used by faces and cannot be removed.",
+                self.textures[texture_index].texture_name.get_string()
+            ));
+        }
+
+        self.texinfo.iter_mut().for_each(|texinfo| {
+            if texinfo.texture_index as usize > texture_index {
+                texinfo.texture_index -= 1;
+            }
+        });
+
+        Ok(self.textures.remove(texture_index))
+    }
+
+    /// Takes out image data of every embedded texture and returns them.
+    ///
+    /// Textures stay in the BSP with only their names and dimensions.
+    pub fn externalize_textures(&mut self) -> Vec<Texture> {
+        self.textures
+            .iter_mut()
+            .filter(|texture| !texture.is_external())
+            .map(|texture| {
+                let external = MipTex::new_external(
+                    texture.texture_name.get_string(),
+                    (texture.width, texture.height),
+                );
+
+                std::mem::replace(texture, external)
+            })
+            .collect()
+    }
+
+    /// Embeds image data into an external texture.
+    ///
+    /// The texture keeps its name.
+    pub fn internalize_texture(
+        &mut self,
+        texture_index: usize,
+        texture: Texture,
+    ) -> eyre::Result<()> {
+        if texture.is_external() {
+            return Err(eyre!(
+                "Texture {} does not have image data.",
+                texture.texture_name.get_string()
+            ));
+        }
+
+        self.replace_texture(texture_index, texture)
+    }
+
+    /// Embeds every external texture that can be found in `textures` by name.
+    ///
+    /// Returns the names of textures that are not found.
+    pub fn internalize_textures<'a>(
+        &mut self,
+        textures: impl IntoIterator<Item = &'a Texture> + Clone,
+    ) -> Vec<String> {
+        let mut missing = vec![];
+
+        for texture_index in 0..self.textures.len() {
+            if !self.textures[texture_index].is_external() {
+                continue;
+            }
+
+            let texture_name = self.textures[texture_index].texture_name.get_string();
+
+            let found = textures.clone().into_iter().find(|texture| {
+                !texture.is_external()
+                    && texture
+                        .texture_name
+                        .get_string()
+                        .eq_ignore_ascii_case(&texture_name)
+            });
+
+            match found {
+                Some(found) => {
+                    // cannot fail because it has image data
+                    let _ = self.internalize_texture(texture_index, found.clone());
+                }
+                None => missing.push(texture_name),
+            }
+        }
+
+        missing
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn solid_texture(name: &str) -> MipTex {
+        let (width, height) = (16, 16);
+        let mip0 = vec![0u8; 16 * 16];
+        let mip1 = vec![0u8; 8 * 8];
+        let mip2 = vec![0u8; 4 * 4];
+        let mip3 = vec![0u8; 2 * 2];
+
+        MipTex::new(
+            name,
+            (width, height),
+            &[&mip0, &mip1, &mip2, &mip3],
+            vec![[255, 0, 255]; 256],
+        )
+    }
+
+    #[test]
+    fn add_rename_remove() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let texture_count = bsp.textures.len();
+        let used_index = bsp.texinfo[0].texture_index as usize;
+
+        let index = bsp.add_texture(solid_texture("new_texture")).unwrap();
+        assert!(bsp.add_texture(solid_texture("NEW_TEXTURE")).is_err());
+
+        bsp.rename_texture(index, "renamed").unwrap();
+        assert_eq!(bsp.find_texture("RENAMED"), Some(index));
+
+        assert!(bsp.remove_texture(used_index).is_err());
+
+        let bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+        assert_eq!(bsp.textures.len(), texture_count + 1);
+        assert_eq!(bsp.find_texture("renamed"), Some(index));
+
+        let mut bsp = bsp;
+        bsp.remove_texture(index).unwrap();
+        assert_eq!(bsp.textures.len(), texture_count);
+    }
+
+    #[test]
+    fn remove_shifts_texinfo() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        // unused texture at the front pushes everything back by one
+        bsp.textures.insert(0, solid_texture("unused"));
+        bsp.texinfo
+            .iter_mut()
+            .for_each(|texinfo| texinfo.texture_index += 1);
+
+        bsp.remove_texture(0).unwrap();
+
+        let original = Bsp::from_bytes(file).unwrap();
+
+        bsp.texinfo
+            .iter()
+            .zip(original.texinfo.iter())
+            .for_each(|(a, b)| assert_eq!(a.texture_index, b.texture_index));
+    }
+
+    #[test]
+    fn externalize_internalize() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.add_texture(solid_texture("embedded")).unwrap();
+
+        let embedded = bsp.externalize_textures();
+
+        assert!(!embedded.is_empty());
+        assert!(bsp.textures.iter().all(|texture| texture.is_external()));
+
+        // external textures survive writing
+        let mut bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+        assert!(bsp.textures.iter().all(|texture| texture.is_external()));
+
+        let missing = bsp.internalize_textures(embedded.iter());
+
+        assert!(missing.len() < bsp.textures.len());
+        assert!(!bsp.textures[bsp.find_texture("embedded").unwrap()].is_external());
+
+        let bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+        assert!(!bsp.textures[bsp.find_texture("embedded").unwrap()].is_external());
+    }
+}
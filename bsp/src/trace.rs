@@ -0,0 +1,461 @@
+//! Point contents and traces through the BSP hulls
+//!
+//! Hull 0 is the drawing tree made of `nodes` and `leaves`. Hull 1 to 3 are made of `clipnodes`.
+//!
+//! Based of Quake `SV_RecursiveHullCheck`.
+use glam::Vec3;
+
+use crate::types::{Bsp, LeafContent};
+
+/// Offsets the intersection a bit so the end position stays on the side it came from.
+const DIST_EPSILON: f32 = 0.03125;
+
+/// Collision hulls compiled into the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hull {
+    /// Hull 0, point size
+    Point = 0,
+    /// Hull 1, standing player
+    Standing = 1,
+    /// Hull 2, big monsters
+    Large = 2,
+    /// Hull 3, crouching player
+    Crouching = 3,
+}
+
+impl Hull {
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn mins(&self) -> Vec3 {
+        match self {
+            Hull::Point => Vec3::ZERO,
+            Hull::Standing => Vec3::new(-16., -16., -36.),
+            Hull::Large => Vec3::new(-32., -32., -32.),
+            Hull::Crouching => Vec3::new(-16., -16., -18.),
+        }
+    }
+
+    pub fn maxs(&self) -> Vec3 {
+        match self {
+            Hull::Point => Vec3::ZERO,
+            Hull::Standing => Vec3::new(16., 16., 36.),
+            Hull::Large => Vec3::new(32., 32., 32.),
+            Hull::Crouching => Vec3::new(16., 16., 18.),
+        }
+    }
+
+    /// Picks the hull for a box the same way the engine does.
+    pub fn from_box_size(mins: Vec3, maxs: Vec3) -> Self {
+        let size = maxs - mins;
+
+        if size.x <= 8. {
+            Hull::Point
+        } else if size.x <= 36. {
+            if size.z <= 36. {
+                Hull::Crouching
+            } else {
+                Hull::Standing
+            }
+        } else {
+            Hull::Large
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trace {
+    /// The whole trace is inside solid
+    pub all_solid: bool,
+    /// The trace starts inside solid
+    pub start_solid: bool,
+    /// The trace passes through empty space
+    pub in_open: bool,
+    /// The trace passes through liquid or anything not empty and not solid
+    pub in_water: bool,
+    /// 1.0 means the trace does not hit anything
+    pub fraction: f32,
+    pub end_pos: Vec3,
+    /// Normal of the hit plane, facing toward the start of the trace
+    pub plane_normal: Vec3,
+    pub plane_distance: f32,
+}
+
+impl Trace {
+    fn new(end: Vec3) -> Self {
+        Self {
+            all_solid: true,
+            start_solid: false,
+            in_open: false,
+            in_water: false,
+            fraction: 1.,
+            end_pos: end,
+            plane_normal: Vec3::ZERO,
+            plane_distance: 0.,
+        }
+    }
+
+    /// Whether the trace is stopped by something
+    pub fn hit(&self) -> bool {
+        self.fraction < 1. || self.start_solid
+    }
+}
+
+impl Bsp {
+    /// Returns the plane index and the children of a hull node.
+    ///
+    /// Negative children are contents instead of node index, same as clipnodes.
+    /// Hull 0 leaves are turned into their contents.
+    fn hull_node(&self, hull: Hull, node_index: i32) -> (usize, [i32; 2]) {
+        if hull == Hull::Point {
+            let node = &self.nodes[node_index as usize];
+
+            let child = |child: i16| {
+                if child >= 0 {
+                    child as i32
+                } else {
+                    self.leaves
+                        .get((-(child as i32) - 1) as usize)
+                        .map(|leaf| leaf.contents as i32)
+                        .unwrap_or(LeafContent::ContentsSolid as i32)
+                }
+            };
+
+            (
+                node.plane as usize,
+                [child(node.children[0]), child(node.children[1])],
+            )
+        } else {
+            let clipnode = &self.clipnodes[node_index as usize];
+
+            (
+                clipnode.plane as usize,
+                [clipnode.children[0] as i32, clipnode.children[1] as i32],
+            )
+        }
+    }
+
+    fn hull_node_point_contents(&self, hull: Hull, mut node_index: i32, point: Vec3) -> i32 {
+        while node_index >= 0 {
+            let (plane, children) = self.hull_node(hull, node_index);
+            let plane = &self.planes[plane];
+
+            node_index = if plane.normal.dot(point) - plane.distance < 0. {
+                children[1]
+            } else {
+                children[0]
+            };
+        }
+
+        node_index
+    }
+
+    /// Returns the contents at a point of a hull in a brush model.
+    ///
+    /// Model 0 is worldspawn. Returns None if the model does not exist.
+    pub fn point_contents(
+        &self,
+        model_index: usize,
+        hull: Hull,
+        point: Vec3,
+    ) -> Option<LeafContent> {
+        let head_node = self.models.get(model_index)?.head_nodes[hull.index()];
+        let contents = self.hull_node_point_contents(hull, head_node, point);
+
+        Some(contents.try_into().unwrap_or(LeafContent::ContentsSolid))
+    }
+
+    /// Traces a line through a hull of a brush model.
+    ///
+    /// `start` and `end` are where the hull origin is. Model 0 is worldspawn.
+    /// Returns None if the model does not exist.
+    pub fn trace_hull(
+        &self,
+        model_index: usize,
+        hull: Hull,
+        start: Vec3,
+        end: Vec3,
+    ) -> Option<Trace> {
+        let head_node = self.models.get(model_index)?.head_nodes[hull.index()];
+
+        let mut trace = Trace::new(end);
+
+        self.recursive_hull_check(hull, head_node, head_node, 0., 1., start, end, &mut trace);
+
+        // in case the trace fraction is not updated
+        if trace.fraction == 1. {
+            trace.end_pos = end;
+        }
+
+        Some(trace)
+    }
+
+    /// Traces a point through worldspawn
+    pub fn trace_line(&self, start: Vec3, end: Vec3) -> Option<Trace> {
+        self.trace_hull(0, Hull::Point, start, end)
+    }
+
+    /// Traces a box through a brush model.
+    ///
+    /// The hull is chosen by the box size like the engine does.
+    /// `mins` and `maxs` are relative to `start` and `end`.
+    pub fn trace_box(
+        &self,
+        model_index: usize,
+        start: Vec3,
+        end: Vec3,
+        mins: Vec3,
+        maxs: Vec3,
+    ) -> Option<Trace> {
+        let hull = Hull::from_box_size(mins, maxs);
+
+        // the hull is not exactly the box, offset it so the bottom aligns
+        let offset = hull.mins() - mins;
+
+        let mut trace = self.trace_hull(model_index, hull, start - offset, end - offset)?;
+
+        trace.end_pos += offset;
+
+        Some(trace)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn recursive_hull_check(
+        &self,
+        hull: Hull,
+        head_node: i32,
+        node_index: i32,
+        p1f: f32,
+        p2f: f32,
+        p1: Vec3,
+        p2: Vec3,
+        trace: &mut Trace,
+    ) -> bool {
+        // reached contents
+        if node_index < 0 {
+            if node_index != LeafContent::ContentsSolid as i32 {
+                trace.all_solid = false;
+
+                if node_index == LeafContent::ContentsEmpty as i32 {
+                    trace.in_open = true;
+                } else {
+                    trace.in_water = true;
+                }
+            } else {
+                trace.start_solid = true;
+            }
+
+            return true;
+        }
+
+        let (plane_index, children) = self.hull_node(hull, node_index);
+        let plane = &self.planes[plane_index];
+
+        let t1 = plane.normal.dot(p1) - plane.distance;
+        let t2 = plane.normal.dot(p2) - plane.distance;
+
+        if t1 >= 0. && t2 >= 0. {
+            return self.recursive_hull_check(
+                hull,
+                head_node,
+                children[0],
+                p1f,
+                p2f,
+                p1,
+                p2,
+                trace,
+            );
+        }
+
+        if t1 < 0. && t2 < 0. {
+            return self.recursive_hull_check(
+                hull,
+                head_node,
+                children[1],
+                p1f,
+                p2f,
+                p1,
+                p2,
+                trace,
+            );
+        }
+
+        // put the crosspoint DIST_EPSILON pixels on the near side
+        let frac = if t1 < 0. {
+            (t1 + DIST_EPSILON) / (t1 - t2)
+        } else {
+            (t1 - DIST_EPSILON) / (t1 - t2)
+        };
+        let mut frac = frac.clamp(0., 1.);
+
+        let mut midf = p1f + (p2f - p1f) * frac;
+        let mut mid = p1 + (p2 - p1) * frac;
+
+        let side = (t1 < 0.) as usize;
+
+        // move up to the node
+        if !self.recursive_hull_check(hull, head_node, children[side], p1f, midf, p1, mid, trace) {
+            return false;
+        }
+
+        // go past the node
+        if self.hull_node_point_contents(hull, children[side ^ 1], mid)
+            != LeafContent::ContentsSolid as i32
+        {
+            return self.recursive_hull_check(
+                hull,
+                head_node,
+                children[side ^ 1],
+                midf,
+                p2f,
+                mid,
+                p2,
+                trace,
+            );
+        }
+
+        // never got out of the solid area
+        if trace.all_solid {
+            return false;
+        }
+
+        // the other side of the node is solid, this is the impact point
+        if side == 0 {
+            trace.plane_normal = plane.normal;
+            trace.plane_distance = plane.distance;
+        } else {
+            trace.plane_normal = -plane.normal;
+            trace.plane_distance = -plane.distance;
+        }
+
+        // shouldn't really happen, but does occasionally
+        while self.hull_node_point_contents(hull, head_node, mid)
+            == LeafContent::ContentsSolid as i32
+        {
+            frac -= 0.1;
+
+            if frac < 0. {
+                trace.fraction = midf;
+                trace.end_pos = mid;
+                return false;
+            }
+
+            midf = p1f + (p2f - p1f) * frac;
+            mid = p1 + (p2 - p1) * frac;
+        }
+
+        trace.fraction = midf;
+        trace.end_pos = mid;
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::player_start;
+
+    use super::*;
+
+    #[test]
+    fn contents() {
+        let file = include_bytes!("tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let origin = player_start(&bsp);
+
+        for hull in [Hull::Point, Hull::Standing, Hull::Crouching] {
+            assert!(matches!(
+                bsp.point_contents(0, hull, origin),
+                Some(LeafContent::ContentsEmpty)
+            ));
+        }
+
+        assert!(matches!(
+            bsp.point_contents(0, Hull::Point, Vec3::splat(100000.)),
+            Some(LeafContent::ContentsSolid)
+        ));
+
+        // model does not exist
+        assert!(bsp
+            .point_contents(bsp.models.len(), Hull::Point, origin)
+            .is_none());
+    }
+
+    #[test]
+    fn trace_to_floor() {
+        let file = include_bytes!("tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let start = player_start(&bsp);
+        let end = start - Vec3::Z * 4096.;
+
+        for hull in [Hull::Point, Hull::Standing] {
+            let trace = bsp.trace_hull(0, hull, start, end).unwrap();
+
+            assert!(!trace.start_solid);
+            assert!(!trace.all_solid);
+            assert!(trace.hit());
+            assert!(trace.plane_normal.z > 0.7);
+            assert!(trace.end_pos.z > end.z);
+
+            // end position is not in solid
+            assert!(matches!(
+                bsp.point_contents(0, hull, trace.end_pos),
+                Some(LeafContent::ContentsEmpty)
+            ));
+        }
+
+        // monster box has its origin at the bottom so it stops on the floor
+        let feet = start - Vec3::Z * 36.;
+        let monster = bsp
+            .trace_box(
+                0,
+                feet,
+                feet - Vec3::Z * 4096.,
+                Vec3::new(-16., -16., 0.),
+                Vec3::new(16., 16., 72.),
+            )
+            .unwrap();
+        let floor = bsp.trace_line(start, end).unwrap().end_pos.z;
+
+        assert!(monster.hit());
+        assert!((monster.end_pos.z - floor).abs() < 1.);
+
+        // standing hull stops before point hull
+        let point = bsp.trace_line(start, end).unwrap();
+        let standing = bsp.trace_hull(0, Hull::Standing, start, end).unwrap();
+
+        assert!(standing.end_pos.z > point.end_pos.z);
+    }
+
+    #[test]
+    fn trace_nothing() {
+        let file = include_bytes!("tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let start = player_start(&bsp);
+        let trace = bsp.trace_line(start, start + Vec3::Z).unwrap();
+
+        assert!(!trace.hit());
+        assert_eq!(trace.end_pos, start + Vec3::Z);
+    }
+
+    #[test]
+    fn box_hull() {
+        assert_eq!(Hull::from_box_size(Vec3::ZERO, Vec3::ZERO), Hull::Point);
+        assert_eq!(
+            Hull::from_box_size(Hull::Standing.mins(), Hull::Standing.maxs()),
+            Hull::Standing
+        );
+        assert_eq!(
+            Hull::from_box_size(Hull::Crouching.mins(), Hull::Crouching.maxs()),
+            Hull::Crouching
+        );
+        assert_eq!(
+            Hull::from_box_size(Hull::Large.mins(), Hull::Large.maxs()),
+            Hull::Large
+        );
+    }
+}
@@ -1,5 +1,4 @@
 use std::{
-    collections::HashMap,
     ffi::OsStr,
     fs::OpenOptions,
     io::Write,
@@ -8,14 +7,17 @@ use std::{
 
 use byte_writer::ByteWriter;
 use glam::Vec3;
+use indexmap::IndexMap;
 use wad::types::MipTex;
 
 use crate::{
     constants::{
-        HEADER_LUMPS, HEADER_LUMP_SIZE, LUMP_CLIPNODES, LUMP_EDGES, LUMP_ENTITIES, LUMP_FACES,
-        LUMP_LEAVES, LUMP_LIGHTING, LUMP_MARKSURFACES, LUMP_MODELS, LUMP_NODES, LUMP_PLANES,
-        LUMP_SURFEDGES, LUMP_TEXINFO, LUMP_TEXTURES, LUMP_VERTICES, LUMP_VISIBILITY, MAX_MAP_HULLS,
+        BSP_VERSION, HEADER_LUMPS, HEADER_LUMP_SIZE, LUMP_CLIPNODES, LUMP_EDGES, LUMP_ENTITIES,
+        LUMP_FACES, LUMP_LEAVES, LUMP_LIGHTING, LUMP_MARKSURFACES, LUMP_MODELS, LUMP_NODES,
+        LUMP_PLANES, LUMP_SURFEDGES, LUMP_TEXINFO, LUMP_TEXTURES, LUMP_VERTICES, LUMP_VISIBILITY,
+        MAX_MAP_HULLS, QUAKE_BSP_VERSION,
     },
+    ent::entities_to_string,
     parse_bsp,
 };
 
@@ -26,13 +28,58 @@ pub type SResult<'a, T> = _IResult<&'a str, T>;
 
 use eyre::eyre;
 
+/// Which game the BSP is for.
+///
+/// Every variant is parsed into the same types and written back as the same variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BspVariant {
+    /// Half-Life, version 30.
+    #[default]
+    GoldSrc,
+    /// Half-Life: Blue Shift, version 30 but entities and planes are swapped in the header.
+    BlueShift,
+    /// Quake, version 29.
+    ///
+    /// Textures use the Quake palette and lightmap is grayscale, one byte per texel.
+    Quake,
+}
+
+impl BspVariant {
+    pub fn version(&self) -> i32 {
+        match self {
+            Self::GoldSrc | Self::BlueShift => BSP_VERSION,
+            Self::Quake => QUAKE_BSP_VERSION,
+        }
+    }
+
+    /// Where a lump is in the header.
+    pub fn header_index(&self, lump: usize) -> usize {
+        match (self, lump) {
+            (Self::BlueShift, LUMP_ENTITIES) => LUMP_PLANES,
+            (Self::BlueShift, LUMP_PLANES) => LUMP_ENTITIES,
+            _ => lump,
+        }
+    }
+
+    /// Size of one lightmap texel in the lighting lump.
+    ///
+    /// `Face::lightmap_offset` is in bytes so it has to be divided by this.
+    pub fn lightmap_texel_size(&self) -> usize {
+        match self {
+            Self::GoldSrc | Self::BlueShift => 3,
+            Self::Quake => 1,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct LumpHeader {
     pub offset: i32,
     pub length: i32,
 }
 
-pub type Entity = HashMap<String, String>;
+/// Keys are kept in the same order as the entity lump.
+pub type Entity = IndexMap<String, String>;
 
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -105,7 +152,6 @@ impl Plane {
 
 pub type Texture = MipTex;
 pub type Vertex = Vec3;
-// pub type BspVis = todo!();
 
 #[derive(Debug)]
 pub struct Node {
@@ -227,18 +273,28 @@ pub struct Bsp {
     pub planes: Vec<Plane>,
     pub textures: Vec<Texture>,
     pub vertices: Vec<Vertex>,
-    // TODO vis
+    /// Compressed visibility data. Use [`Bsp::leaf_pvs`] to decompress.
     pub visibility: Vec<u8>,
     pub nodes: Vec<Node>,
     pub texinfo: Vec<TexInfo>,
     pub faces: Vec<Face>,
     pub lightmap: LightMap,
+    /// Length of the lighting lump when parsed.
+    ///
+    /// Maps without lightmap still have a lighting lump of 1 byte,
+    /// so the lump is padded with zeros up to this length when written.
+    pub lightmap_lump_length: usize,
     pub clipnodes: Vec<ClipNode>,
     pub leaves: Vec<Leaf>,
     pub mark_surfaces: Vec<MarkSurface>,
     pub edges: Vec<Edge>,
     pub surf_edges: Vec<SurfEdge>,
     pub models: Vec<Model>,
+    /// Order of lumps in the file, which is not the same as the order in the header.
+    ///
+    /// Kept so writing an unchanged BSP gives back the same bytes.
+    pub lump_order: [usize; HEADER_LUMPS],
+    pub variant: BspVariant,
 }
 
 impl Bsp {
@@ -254,6 +310,26 @@ impl Bsp {
         Self::from_bytes(&bytes)
     }
 
+    /// Returns the vertices of a face in winding order.
+    pub fn face_vertices(&self, face_index: usize) -> Vec<Vec3> {
+        let face = &self.faces[face_index];
+
+        (0..face.edge_count as i32)
+            .map(|edge_index| {
+                let surf_edge = self.surf_edges[(face.first_edge + edge_index) as usize];
+
+                // negative surfedge means the edge is walked backward
+                let vertex_index = if surf_edge >= 0 {
+                    self.edges[surf_edge as usize][0]
+                } else {
+                    self.edges[(-surf_edge) as usize][1]
+                };
+
+                self.vertices[vertex_index as usize]
+            })
+            .collect()
+    }
+
     pub fn write_to_file(&self, path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<()> {
         let bytes = self.write_to_bytes();
 
@@ -271,391 +347,267 @@ impl Bsp {
     }
 
     pub fn write_to_bytes(&self) -> Vec<u8> {
-        // for most compilers, there's 7 bytes of trailing null at the end of the file
-        // not sure which lump it belongs to but entity lump is at the end if that clears up anything
-
         let mut writer = ByteWriter::new();
 
-        writer.append_i32(30); // version
+        writer.append_i32(self.variant.version());
 
         // will be writing the offset later on
         let lump_headers_offset = writer.get_offset();
         let lump_headers_padding = vec![0u8; HEADER_LUMP_SIZE * HEADER_LUMPS];
         writer.append_u8_slice(&lump_headers_padding);
 
-        // just writes all the lumps like normal then we go back to the lump header again
-        // this means if we have weird lump header order, this could be changed easily
-        // by changing the numbers in constants.rs
-
-        // write entities
-        {
-            let offset = writer.get_offset();
-            let mut entity_str = String::new();
-
-            self.entities.iter().for_each(|entity| {
-                // start with "{" then "\n"
-                // "\n" at the end of key-value pair
-                // " " to separate between key and value
-                // ends "}" and no need for "\n" because it is from previous pair
-                entity_str += "{\n";
-
-                entity.iter().for_each(|(key, value)| {
-                    entity_str += format!("\"{}\" \"{}\"\n", key, value).as_str()
-                });
-
-                // "\n" will separate entity
-                entity_str += "}\n";
-            });
-
-            // null at the end for some reasons
-            writer.append_string(entity_str.as_str());
-            writer.append_u8(0);
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_ENTITIES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write planes
-        {
-            let offset = writer.get_offset();
-
-            self.planes.iter().for_each(|plane| {
-                writer.append_f32(plane.normal.x);
-                writer.append_f32(plane.normal.y);
-                writer.append_f32(plane.normal.z);
-
-                writer.append_f32(plane.distance);
-                writer.append_i32(plane.type_ as i32);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_PLANES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write textures
-        {
-            let offset = writer.get_offset();
-
-            // texture count
-            writer.append_u32(self.textures.len() as u32);
-
-            // pad offset
-            let offsets_start = writer.get_offset();
-            (0..self.textures.len()).for_each(|_| {
-                writer.append_u32(0); // dummy
-            });
-
-            self.textures.iter().enumerate().for_each(|(idx, texture)| {
-                let texture_offset = writer.get_offset();
-
-                // texture offset is relative to where the lump starts
-                writer.replace_with_u32(offsets_start + idx * 4, (texture_offset - offset) as u32);
+        // lumps are written in the same order as they were read and then we go back to the lump header
+        self.lump_order.iter().for_each(|&lump| {
+            // compilers align every lump to 4 bytes
+            writer.append_u8_slice(&vec![0u8; (4 - writer.get_offset() % 4) % 4]);
 
-                texture.write(&mut writer);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_TEXTURES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write vertices
-        {
             let offset = writer.get_offset();
 
-            self.vertices.iter().for_each(|vertex| {
-                writer.append_f32(vertex.x);
-                writer.append_f32(vertex.y);
-                writer.append_f32(vertex.z);
-            });
+            self.write_lump(&mut writer, lump);
 
             let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_VERTICES * HEADER_LUMP_SIZE;
+            let header = lump_headers_offset + self.variant.header_index(lump) * HEADER_LUMP_SIZE;
 
             writer.replace_with_i32(header, offset as i32);
             writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write visibility
-        {
-            let offset = writer.get_offset();
+        });
 
-            // TODO
-            writer.append_u8_slice(&self.visibility);
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_VISIBILITY * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write nodes
-        {
-            let offset = writer.get_offset();
-
-            self.nodes.iter().for_each(|node| {
-                writer.append_u32(node.plane);
-                writer.append_i16(node.children[0]);
-                writer.append_i16(node.children[1]);
+        writer.data
+    }
 
-                node.mins.iter().for_each(|&x| {
-                    writer.append_i16(x);
-                });
-                node.maxs.iter().for_each(|&x| {
-                    writer.append_i16(x);
+    fn write_lump(&self, writer: &mut ByteWriter, lump: usize) {
+        match lump {
+            LUMP_ENTITIES => {
+                let entity_str = entities_to_string(&self.entities);
+
+                // null at the end for some reasons
+                writer.append_string(entity_str.as_str());
+                writer.append_u8(0);
+            }
+            LUMP_PLANES => {
+                self.planes.iter().for_each(|plane| {
+                    writer.append_f32(plane.normal.x);
+                    writer.append_f32(plane.normal.y);
+                    writer.append_f32(plane.normal.z);
+
+                    writer.append_f32(plane.distance);
+                    writer.append_i32(plane.type_ as i32);
                 });
+            }
+            LUMP_TEXTURES => {
+                let offset = writer.get_offset();
 
-                writer.append_u16(node.first_face);
-                writer.append_u16(node.face_count);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_NODES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write texinfo
-        {
-            let offset = writer.get_offset();
+                // texture count
+                writer.append_u32(self.textures.len() as u32);
 
-            self.texinfo.iter().for_each(
-                |TexInfo {
-                     u,
-                     u_offset,
-                     v,
-                     v_offset,
-                     texture_index,
-                     flags,
-                 }| {
-                    writer.append_f32(u.x);
-                    writer.append_f32(u.y);
-                    writer.append_f32(u.z);
-                    writer.append_f32(*u_offset);
-
-                    writer.append_f32(v.x);
-                    writer.append_f32(v.y);
-                    writer.append_f32(v.z);
-                    writer.append_f32(*v_offset);
-
-                    writer.append_u32(*texture_index);
-                    writer.append_u32(*flags);
-                },
-            );
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_TEXINFO * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write faces
-        {
-            let offset = writer.get_offset();
-
-            self.faces.iter().for_each(
-                |Face {
-                     plane,
-                     side,
-                     first_edge,
-                     edge_count,
-                     texinfo,
-                     styles,
-                     lightmap_offset,
-                 }| {
-                    writer.append_u16(*plane);
-                    writer.append_u16(*side);
-                    writer.append_i32(*first_edge);
-                    writer.append_u16(*edge_count);
-                    writer.append_u16(*texinfo);
-
-                    styles.iter().for_each(|&v| {
-                        writer.append_u8(v);
-                    });
-
-                    writer.append_i32(*lightmap_offset);
-                },
-            );
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_FACES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write lightmap
-        {
-            let offset = writer.get_offset();
-
-            self.lightmap.iter().for_each(|lightmap| {
-                writer.append_u8_slice(lightmap);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_LIGHTING * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write clipnodes
-        {
-            let offset = writer.get_offset();
-
-            self.clipnodes
-                .iter()
-                .for_each(|ClipNode { plane, children }| {
-                    writer.append_i32(*plane);
-                    writer.append_i16(children[0]);
-                    writer.append_i16(children[1]);
+                // pad offset
+                let offsets_start = writer.get_offset();
+                (0..self.textures.len()).for_each(|_| {
+                    writer.append_u32(0); // dummy
                 });
 
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_CLIPNODES * HEADER_LUMP_SIZE;
+                self.textures.iter().enumerate().for_each(|(idx, texture)| {
+                    let texture_offset = writer.get_offset();
 
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
+                    // texture offset is relative to where the lump starts
+                    writer.replace_with_u32(
+                        offsets_start + idx * 4,
+                        (texture_offset - offset) as u32,
+                    );
 
-        // write leaves
-        {
-            let offset = writer.get_offset();
+                    if self.variant == BspVariant::Quake {
+                        texture.write_without_palette(writer);
+                    } else {
+                        texture.write(writer);
+                    }
 
-            self.leaves.iter().for_each(
-                |Leaf {
-                     contents,
-                     vis_offset,
-                     mins,
-                     maxs,
-                     first_mark_surface,
-                     mark_surface_count,
-                     ambient_levels,
-                 }| {
-                    writer.append_i32(*contents as i32);
-                    writer.append_i32(*vis_offset);
-
-                    mins.iter().for_each(|&v| {
-                        writer.append_i16(v);
-                    });
-                    maxs.iter().for_each(|&v| {
-                        writer.append_i16(v);
+                    // every texture is aligned to 4 bytes
+                    writer.append_u8_slice(&vec![0u8; (4 - writer.get_offset() % 4) % 4]);
+                });
+            }
+            LUMP_VERTICES => {
+                self.vertices.iter().for_each(|vertex| {
+                    writer.append_f32(vertex.x);
+                    writer.append_f32(vertex.y);
+                    writer.append_f32(vertex.z);
+                });
+            }
+            LUMP_VISIBILITY => {
+                // TODO
+                writer.append_u8_slice(&self.visibility);
+            }
+            LUMP_NODES => {
+                self.nodes.iter().for_each(|node| {
+                    writer.append_u32(node.plane);
+                    writer.append_i16(node.children[0]);
+                    writer.append_i16(node.children[1]);
+
+                    node.mins.iter().for_each(|&x| {
+                        writer.append_i16(x);
                     });
-
-                    writer.append_u16(*first_mark_surface);
-                    writer.append_u16(*mark_surface_count);
-
-                    ambient_levels.iter().for_each(|&v| {
-                        writer.append_u8(v);
+                    node.maxs.iter().for_each(|&x| {
+                        writer.append_i16(x);
                     });
-                },
-            );
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_LEAVES * HEADER_LUMP_SIZE;
 
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write mark surfaces
-        {
-            let offset = writer.get_offset();
-
-            self.mark_surfaces.iter().for_each(|&v| {
-                writer.append_u16(v);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_MARKSURFACES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write edges
-        {
-            let offset = writer.get_offset();
-
-            self.edges.iter().for_each(|&[p1, p2]| {
-                writer.append_u16(p1);
-                writer.append_u16(p2);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_EDGES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write surf edges
-        {
-            let offset = writer.get_offset();
-
-            self.surf_edges.iter().for_each(|&v| {
-                writer.append_i32(v);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_SURFEDGES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write models
-        {
-            let offset = writer.get_offset();
+                    writer.append_u16(node.first_face);
+                    writer.append_u16(node.face_count);
+                });
+            }
+            LUMP_TEXINFO => {
+                self.texinfo.iter().for_each(
+                    |TexInfo {
+                         u,
+                         u_offset,
+                         v,
+                         v_offset,
+                         texture_index,
+                         flags,
+                     }| {
+                        writer.append_f32(u.x);
+                        writer.append_f32(u.y);
+                        writer.append_f32(u.z);
+                        writer.append_f32(*u_offset);
+
+                        writer.append_f32(v.x);
+                        writer.append_f32(v.y);
+                        writer.append_f32(v.z);
+                        writer.append_f32(*v_offset);
+
+                        writer.append_u32(*texture_index);
+                        writer.append_u32(*flags);
+                    },
+                );
+            }
+            LUMP_FACES => {
+                self.faces.iter().for_each(
+                    |Face {
+                         plane,
+                         side,
+                         first_edge,
+                         edge_count,
+                         texinfo,
+                         styles,
+                         lightmap_offset,
+                     }| {
+                        writer.append_u16(*plane);
+                        writer.append_u16(*side);
+                        writer.append_i32(*first_edge);
+                        writer.append_u16(*edge_count);
+                        writer.append_u16(*texinfo);
+
+                        styles.iter().for_each(|&v| {
+                            writer.append_u8(v);
+                        });
+
+                        writer.append_i32(*lightmap_offset);
+                    },
+                );
+            }
+            LUMP_LIGHTING => {
+                let offset = writer.get_offset();
+
+                self.lightmap.iter().for_each(|lightmap| {
+                    if self.variant == BspVariant::Quake {
+                        let [r, g, b] = lightmap.map(|x| x as u32);
+                        writer.append_u8(((r + g + b) / 3) as u8);
+                    } else {
+                        writer.append_u8_slice(lightmap);
+                    }
+                });
 
-            self.models.iter().for_each(
-                |Model {
-                     mins,
-                     maxs,
-                     origin,
-                     head_nodes,
-                     vis_leaves_count,
-                     first_face,
-                     face_count,
-                 }| {
-                    writer.append_f32(mins.x);
-                    writer.append_f32(mins.y);
-                    writer.append_f32(mins.z);
-                    writer.append_f32(maxs.x);
-                    writer.append_f32(maxs.y);
-                    writer.append_f32(maxs.z);
-                    writer.append_f32(origin.x);
-                    writer.append_f32(origin.y);
-                    writer.append_f32(origin.z);
-
-                    head_nodes.iter().for_each(|&v| {
-                        writer.append_i32(v);
+                let length = writer.get_offset() - offset;
+
+                if length < self.lightmap_lump_length {
+                    writer.append_u8_slice(&vec![0u8; self.lightmap_lump_length - length]);
+                }
+            }
+            LUMP_CLIPNODES => {
+                self.clipnodes
+                    .iter()
+                    .for_each(|ClipNode { plane, children }| {
+                        writer.append_i32(*plane);
+                        writer.append_i16(children[0]);
+                        writer.append_i16(children[1]);
                     });
-
-                    writer.append_i32(*vis_leaves_count);
-                    writer.append_i32(*first_face);
-                    writer.append_i32(*face_count);
-                },
-            );
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_MODELS * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
+            }
+            LUMP_LEAVES => {
+                self.leaves.iter().for_each(
+                    |Leaf {
+                         contents,
+                         vis_offset,
+                         mins,
+                         maxs,
+                         first_mark_surface,
+                         mark_surface_count,
+                         ambient_levels,
+                     }| {
+                        writer.append_i32(*contents as i32);
+                        writer.append_i32(*vis_offset);
+
+                        mins.iter().for_each(|&v| {
+                            writer.append_i16(v);
+                        });
+                        maxs.iter().for_each(|&v| {
+                            writer.append_i16(v);
+                        });
+
+                        writer.append_u16(*first_mark_surface);
+                        writer.append_u16(*mark_surface_count);
+
+                        ambient_levels.iter().for_each(|&v| {
+                            writer.append_u8(v);
+                        });
+                    },
+                );
+            }
+            LUMP_MARKSURFACES => {
+                self.mark_surfaces.iter().for_each(|&v| {
+                    writer.append_u16(v);
+                });
+            }
+            LUMP_EDGES => {
+                self.edges.iter().for_each(|&[p1, p2]| {
+                    writer.append_u16(p1);
+                    writer.append_u16(p2);
+                });
+            }
+            LUMP_SURFEDGES => {
+                self.surf_edges.iter().for_each(|&v| {
+                    writer.append_i32(v);
+                });
+            }
+            LUMP_MODELS => {
+                self.models.iter().for_each(
+                    |Model {
+                         mins,
+                         maxs,
+                         origin,
+                         head_nodes,
+                         vis_leaves_count,
+                         first_face,
+                         face_count,
+                     }| {
+                        writer.append_f32(mins.x);
+                        writer.append_f32(mins.y);
+                        writer.append_f32(mins.z);
+                        writer.append_f32(maxs.x);
+                        writer.append_f32(maxs.y);
+                        writer.append_f32(maxs.z);
+                        writer.append_f32(origin.x);
+                        writer.append_f32(origin.y);
+                        writer.append_f32(origin.z);
+
+                        head_nodes.iter().for_each(|&v| {
+                            writer.append_i32(v);
+                        });
+
+                        writer.append_i32(*vis_leaves_count);
+                        writer.append_i32(*first_face);
+                        writer.append_i32(*face_count);
+                    },
+                );
+            }
+            _ => unreachable!("lump {} does not exist", lump),
         }
-
-        writer.data
     }
 }
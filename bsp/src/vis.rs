@@ -0,0 +1,257 @@
+//! Potentially Visible Set
+//!
+//! Visibility lump is run-length encoded. Only zero bytes are compressed, followed by the count of zero bytes.
+//!
+//! Each leaf (except leaf 0, the shared solid leaf) points to its own row with `vis_offset`.
+//! Bit N in a decompressed row means leaf N + 1 is visible.
+use glam::Vec3;
+
+use crate::types::Bsp;
+
+/// Decompressed visibility row of a leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pvs(Vec<u8>);
+
+impl Pvs {
+    /// Creates a row where every leaf is visible.
+    ///
+    /// This is the case when the map is not compiled with VIS or the leaf has negative `vis_offset`.
+    pub fn all_visible(leaf_count: usize) -> Self {
+        let mut res = vec![0xffu8; leaf_count.div_ceil(8)];
+
+        // clear the bits past the last leaf so counting is correct
+        let extra_bits = leaf_count % 8;
+
+        if extra_bits > 0 {
+            if let Some(last) = res.last_mut() {
+                *last = (1u8 << extra_bits) - 1;
+            }
+        }
+
+        Self(res)
+    }
+
+    /// Whether leaf at `leaf_index` is visible.
+    ///
+    /// `leaf_index` is the index into `Bsp::leaves`. Leaf 0 is never visible.
+    pub fn is_visible(&self, leaf_index: usize) -> bool {
+        if leaf_index == 0 {
+            return false;
+        }
+
+        let bit = leaf_index - 1;
+
+        self.0
+            .get(bit >> 3)
+            .map(|byte| byte & (1 << (bit & 7)) != 0)
+            .unwrap_or(false)
+    }
+
+    /// Returns the indices of visible leaves. Indices are for `Bsp::leaves`.
+    pub fn visible_leaves(&self) -> Vec<usize> {
+        self.0
+            .iter()
+            .enumerate()
+            .flat_map(|(byte_index, byte)| {
+                (0..8)
+                    .filter(move |bit| byte & (1 << bit) != 0)
+                    .map(move |bit| byte_index * 8 + bit + 1)
+            })
+            .collect()
+    }
+
+    pub fn get_bytes(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+/// Decompresses one row of visibility data starting from `i`.
+///
+/// `row_length` is the amount of bytes to decompress, which is `(leaf_count + 7) / 8`.
+pub fn decompress_vis_row(i: &[u8], row_length: usize) -> Pvs {
+    let mut res = Vec::with_capacity(row_length);
+    let mut index = 0;
+
+    while res.len() < row_length {
+        let Some(&byte) = i.get(index) else {
+            // data is cut short, the rest is not visible
+            break;
+        };
+
+        if byte != 0 {
+            res.push(byte);
+            index += 1;
+            continue;
+        }
+
+        let zero_count = i.get(index + 1).copied().unwrap_or(0) as usize;
+        let zero_count = zero_count.min(row_length - res.len());
+
+        res.resize(res.len() + zero_count, 0);
+        index += 2;
+    }
+
+    res.resize(row_length, 0);
+
+    Pvs(res)
+}
+
+impl Bsp {
+    /// Number of leaves that have visibility data.
+    ///
+    /// It is the `vis_leaves_count` of worldspawn model, which does not count leaf 0.
+    pub fn vis_leaf_count(&self) -> usize {
+        self.models
+            .first()
+            .map(|model| model.vis_leaves_count.max(0) as usize)
+            .unwrap_or(self.leaves.len().saturating_sub(1))
+    }
+
+    /// Returns the decompressed PVS of a leaf.
+    ///
+    /// Returns `None` if the leaf does not exist.
+    pub fn leaf_pvs(&self, leaf_index: usize) -> Option<Pvs> {
+        let leaf = self.leaves.get(leaf_index)?;
+        let leaf_count = self.vis_leaf_count();
+
+        // solid leaf sees nothing
+        if leaf_index == 0 {
+            return Some(Pvs(vec![0u8; leaf_count.div_ceil(8)]));
+        }
+
+        if leaf.vis_offset < 0
+            || self.visibility.is_empty()
+            || leaf.vis_offset as usize >= self.visibility.len()
+        {
+            return Some(Pvs::all_visible(leaf_count));
+        }
+
+        Some(decompress_vis_row(
+            &self.visibility[(leaf.vis_offset as usize)..],
+            leaf_count.div_ceil(8),
+        ))
+    }
+
+    /// Whether leaf `to` is potentially visible from leaf `from`.
+    pub fn is_leaf_visible(&self, from: usize, to: usize) -> bool {
+        if from == to && from != 0 {
+            return true;
+        }
+
+        self.leaf_pvs(from)
+            .map(|pvs| pvs.is_visible(to))
+            .unwrap_or(false)
+    }
+
+    /// Returns the index of the leaf containing the point.
+    ///
+    /// Walks down the BSP tree of worldspawn.
+    pub fn find_leaf(&self, point: Vec3) -> usize {
+        let Some(world) = self.models.first() else {
+            return 0;
+        };
+
+        let mut node_index = world.head_nodes[0];
+
+        while node_index >= 0 {
+            let Some(node) = self.nodes.get(node_index as usize) else {
+                return 0;
+            };
+
+            let plane = &self.planes[node.plane as usize];
+            let distance = plane.normal.dot(point) - plane.distance;
+
+            node_index = if distance >= 0. {
+                node.children[0] as i32
+            } else {
+                node.children[1] as i32
+            };
+        }
+
+        // negative children are leaves, -1 is leaf 0
+        (-node_index - 1) as usize
+    }
+
+    /// Returns all leaves potentially visible from the point.
+    ///
+    /// Does not include the leaf containing the point.
+    pub fn visible_leaves_from_point(&self, point: Vec3) -> Vec<usize> {
+        let leaf_index = self.find_leaf(point);
+
+        self.leaf_pvs(leaf_index)
+            .map(|pvs| pvs.visible_leaves())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&leaf| leaf != leaf_index)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::player_start;
+
+    use super::*;
+
+    #[test]
+    fn decompress_row() {
+        // 0b0000_0101, then 3 zero bytes, then 0b1000_0000
+        let row = decompress_vis_row(&[0x05, 0x00, 0x03, 0x80], 5);
+
+        assert_eq!(row.get_bytes(), &vec![0x05, 0, 0, 0, 0x80]);
+        assert!(row.is_visible(1));
+        assert!(!row.is_visible(2));
+        assert!(row.is_visible(3));
+        assert!(row.is_visible(40));
+        assert_eq!(row.visible_leaves(), vec![1, 3, 40]);
+    }
+
+    #[test]
+    fn all_visible() {
+        let row = Pvs::all_visible(10);
+
+        assert_eq!(row.get_bytes(), &vec![0xff, 0x03]);
+        assert_eq!(row.visible_leaves().len(), 10);
+    }
+
+    #[test]
+    fn leaf_visibility() {
+        let file = include_bytes!("tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        assert!(bsp.leaf_pvs(bsp.leaves.len()).is_none());
+
+        for leaf_index in 1..bsp.leaves.len() {
+            let pvs = bsp.leaf_pvs(leaf_index).unwrap();
+
+            assert_eq!(pvs.get_bytes().len(), bsp.vis_leaf_count().div_ceil(8));
+
+            // visible leaves are all within visleaf range
+            assert!(pvs
+                .visible_leaves()
+                .iter()
+                .all(|&leaf| leaf <= bsp.vis_leaf_count()));
+        }
+    }
+
+    #[test]
+    fn point_in_leaf() {
+        let file = include_bytes!("tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        // far away from everything is the solid leaf
+        assert_eq!(bsp.find_leaf(Vec3::splat(100000.)), 0);
+
+        // the player start is somewhere empty
+        let origin = player_start(&bsp);
+
+        let leaf_index = bsp.find_leaf(origin);
+
+        assert_ne!(leaf_index, 0);
+        assert!(matches!(
+            bsp.leaves[leaf_index].contents,
+            crate::LeafContent::ContentsEmpty
+        ));
+        assert!(bsp.is_leaf_visible(leaf_index, leaf_index));
+    }
+}
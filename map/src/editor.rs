@@ -0,0 +1,577 @@
+//! Shared parts of Hammer RMF and J.A.C.K. JMF.
+//!
+//! Both formats keep groups and visgroups outside of entities. In [`Map`], they are TrenchBroom groups and layers.
+use glam::DVec3;
+use nom::{
+    bytes::complete::take, combinator::map, number::complete::le_f32, sequence::tuple,
+    IResult as _IResult,
+};
+
+use crate::{Attributes, Brush, Entity, Map};
+
+pub(crate) type IResult<'a, T> = _IResult<&'a [u8], T>;
+
+/// Color of objects written by us. Editors use it to draw brushes in 2D views.
+pub(crate) const DEFAULT_OBJECT_COLOR: [u8; 3] = [0, 192, 255];
+/// Color of visgroups that come from TrenchBroom layers.
+pub(crate) const DEFAULT_VISGROUP_COLOR: [u8; 3] = [255, 128, 0];
+
+const TB_TYPE: &str = "_tb_type";
+const TB_TYPE_LAYER: &str = "_tb_layer";
+const TB_TYPE_GROUP: &str = "_tb_group";
+const TB_NAME: &str = "_tb_name";
+const TB_ID: &str = "_tb_id";
+const TB_LAYER: &str = "_tb_layer";
+const TB_GROUP: &str = "_tb_group";
+const TB_LAYER_SORT: &str = "_tb_layer_sort";
+const TB_LAYER_HIDDEN: &str = "_tb_layer_hidden";
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Visgroup {
+    pub id: i32,
+    pub name: String,
+    pub color: [u8; 3],
+    pub visible: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Group {
+    pub id: i32,
+    pub parent: Option<i32>,
+    pub visgroup: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EditorBrush {
+    pub brush: Brush,
+    /// Only used for worldspawn brushes
+    pub group: Option<i32>,
+    /// Only used for worldspawn brushes
+    pub visgroup: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EditorEntity {
+    /// Has classname.
+    pub attributes: Attributes,
+    pub brushes: Vec<EditorBrush>,
+    pub group: Option<i32>,
+    pub visgroup: Option<i32>,
+}
+
+impl EditorEntity {
+    pub fn classname(&self) -> &str {
+        self.attributes
+            .get("classname")
+            .map(|classname| classname.as_str())
+            .unwrap_or_default()
+    }
+
+    pub fn is_point_entity(&self) -> bool {
+        self.brushes.is_empty() && self.classname() != "worldspawn"
+    }
+
+    /// Attributes that are stored in the entity itself rather than as key values.
+    pub fn is_special_key(&self, key: &str) -> bool {
+        key == "classname" || key == "spawnflags" || (key == "origin" && self.is_point_entity())
+    }
+
+    pub fn spawnflags(&self) -> i32 {
+        self.attributes
+            .get("spawnflags")
+            .and_then(|spawnflags| spawnflags.parse().ok())
+            .unwrap_or(0)
+    }
+
+    pub fn origin(&self) -> DVec3 {
+        self.attributes
+            .get("origin")
+            .and_then(|origin| {
+                let numbers = origin
+                    .split_whitespace()
+                    .map(|number| number.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .ok()?;
+
+                (numbers.len() == 3).then(|| DVec3::from_slice(&numbers))
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Map in the way Hammer and J.A.C.K. store it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EditorMap {
+    pub visgroups: Vec<Visgroup>,
+    /// Parents come before children.
+    pub groups: Vec<Group>,
+    /// First entity is worldspawn.
+    pub entities: Vec<EditorEntity>,
+}
+
+impl EditorMap {
+    pub fn new(worldspawn: Attributes) -> Self {
+        Self {
+            visgroups: vec![],
+            groups: vec![],
+            entities: vec![EditorEntity {
+                attributes: worldspawn,
+                brushes: vec![],
+                group: None,
+                visgroup: None,
+            }],
+        }
+    }
+
+    /// Entity from the key values of the file.
+    ///
+    /// Point entities in these formats store their origin separately.
+    pub fn entity_attributes(
+        classname: String,
+        key_values: Vec<(String, String)>,
+        spawnflags: i32,
+        origin: Option<DVec3>,
+    ) -> Attributes {
+        let mut attributes = Attributes::from([("classname".to_string(), classname)]);
+
+        attributes.extend(key_values);
+
+        if spawnflags != 0 {
+            attributes.insert("spawnflags".to_string(), spawnflags.to_string());
+        }
+
+        if let Some(origin) = origin {
+            attributes
+                .entry("origin".to_string())
+                .or_insert_with(|| format!("{} {} {}", origin.x, origin.y, origin.z));
+        }
+
+        attributes
+    }
+
+    /// Groups become TrenchBroom groups and visgroups become TrenchBroom layers.
+    pub fn to_map(&self) -> Map {
+        let visgroup_exists = |visgroup: Option<i32>| {
+            visgroup.filter(|&visgroup| self.visgroups.iter().any(|v| v.id == visgroup))
+        };
+
+        // TrenchBroom layers and groups share ids
+        let first_group_id = self
+            .visgroups
+            .iter()
+            .map(|visgroup| visgroup.id)
+            .max()
+            .unwrap_or(0)
+            .max(0)
+            + 1;
+        let group_tb_id = |group: i32| {
+            self.groups
+                .iter()
+                .position(|g| g.id == group)
+                .map(|index| first_group_id + index as i32)
+        };
+
+        let mut worldspawn = Entity {
+            attributes: self.entities[0].attributes.clone(),
+            brushes: Some(vec![]),
+            comments: None,
+            trailing_comments: vec![],
+        };
+
+        worldspawn.attributes.shift_remove("mapversion");
+
+        let mut layers = self
+            .visgroups
+            .iter()
+            .enumerate()
+            .map(|(index, visgroup)| {
+                let mut attributes = Attributes::from([
+                    ("classname".to_string(), "func_group".to_string()),
+                    (TB_TYPE.to_string(), TB_TYPE_LAYER.to_string()),
+                    (TB_NAME.to_string(), visgroup.name.clone()),
+                    (TB_ID.to_string(), visgroup.id.to_string()),
+                    (TB_LAYER_SORT.to_string(), index.to_string()),
+                ]);
+
+                if !visgroup.visible {
+                    attributes.insert(TB_LAYER_HIDDEN.to_string(), "1".to_string());
+                }
+
+                Entity {
+                    attributes,
+                    brushes: Some(vec![]),
+                    comments: None,
+                    trailing_comments: vec![],
+                }
+            })
+            .collect::<Vec<Entity>>();
+
+        let mut groups = self
+            .groups
+            .iter()
+            .enumerate()
+            .map(|(index, group)| {
+                let mut attributes = Attributes::from([
+                    ("classname".to_string(), "func_group".to_string()),
+                    (TB_TYPE.to_string(), TB_TYPE_GROUP.to_string()),
+                    (TB_NAME.to_string(), "Group".to_string()),
+                    (
+                        TB_ID.to_string(),
+                        (first_group_id + index as i32).to_string(),
+                    ),
+                ]);
+
+                // TrenchBroom only takes the layer from the outermost group
+                if let Some(parent) = group.parent.and_then(group_tb_id) {
+                    attributes.insert(TB_GROUP.to_string(), parent.to_string());
+                } else if let Some(visgroup) = visgroup_exists(group.visgroup) {
+                    attributes.insert(TB_LAYER.to_string(), visgroup.to_string());
+                }
+
+                Entity {
+                    attributes,
+                    brushes: Some(vec![]),
+                    comments: None,
+                    trailing_comments: vec![],
+                }
+            })
+            .collect::<Vec<Entity>>();
+
+        for brush in &self.entities[0].brushes {
+            let entity = if let Some(group) = brush
+                .group
+                .and_then(|group| self.groups.iter().position(|g| g.id == group))
+            {
+                &mut groups[group]
+            } else if let Some(layer) = visgroup_exists(brush.visgroup)
+                .and_then(|visgroup| self.visgroups.iter().position(|v| v.id == visgroup))
+            {
+                &mut layers[layer]
+            } else {
+                &mut worldspawn
+            };
+
+            entity
+                .brushes
+                .get_or_insert_with(Vec::new)
+                .push(brush.brush.clone());
+        }
+
+        let entities = self.entities.iter().skip(1).map(|entity| {
+            let mut attributes = entity.attributes.clone();
+
+            if let Some(group) = entity.group.and_then(group_tb_id) {
+                attributes.insert(TB_GROUP.to_string(), group.to_string());
+            } else if let Some(visgroup) = visgroup_exists(entity.visgroup) {
+                attributes.insert(TB_LAYER.to_string(), visgroup.to_string());
+            }
+
+            Entity {
+                attributes,
+                brushes: if entity.is_point_entity() {
+                    None
+                } else {
+                    Some(
+                        entity
+                            .brushes
+                            .iter()
+                            .map(|brush| brush.brush.clone())
+                            .collect(),
+                    )
+                },
+                comments: None,
+                trailing_comments: vec![],
+            }
+        });
+
+        let entities = std::iter::once(worldspawn)
+            .chain(layers)
+            .chain(groups)
+            .chain(entities)
+            .collect();
+
+        let mut res = Map {
+            tb_header: None,
+            entities,
+            trailing_comments: vec![],
+        };
+
+        res.mark_valve220();
+
+        res
+    }
+
+    /// TrenchBroom groups become groups and TrenchBroom layers become visgroups.
+    pub fn from_map(map: &Map) -> eyre::Result<Self> {
+        let worldspawn = map
+            .entities
+            .iter()
+            .find(|entity| {
+                entity
+                    .attributes
+                    .get("classname")
+                    .is_some_and(|classname| classname == "worldspawn")
+            })
+            .ok_or_else(|| eyre::eyre!("Map does not have worldspawn."))?;
+
+        let mut worldspawn_attributes = worldspawn.attributes.clone();
+        worldspawn_attributes.shift_remove("mapversion");
+
+        let mut res = Self::new(worldspawn_attributes);
+
+        let tb_type = |entity: &Entity| {
+            entity
+                .attributes
+                .get(TB_TYPE)
+                .map(|tb_type| tb_type.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let number = |entity: &Entity, key: &str| {
+            entity
+                .attributes
+                .get(key)
+                .and_then(|number| number.parse::<i32>().ok())
+        };
+
+        // all groups first so members know their layer
+        for entity in &map.entities {
+            match tb_type(entity).as_str() {
+                TB_TYPE_LAYER => res.visgroups.push(Visgroup {
+                    id: number(entity, TB_ID)
+                        .ok_or_else(|| eyre::eyre!("TrenchBroom layer does not have an id."))?,
+                    name: entity.attributes.get(TB_NAME).cloned().unwrap_or_default(),
+                    color: DEFAULT_VISGROUP_COLOR,
+                    visible: entity
+                        .attributes
+                        .get(TB_LAYER_HIDDEN)
+                        .is_none_or(|hidden| hidden != "1"),
+                }),
+                TB_TYPE_GROUP => res.groups.push(Group {
+                    id: number(entity, TB_ID)
+                        .ok_or_else(|| eyre::eyre!("TrenchBroom group does not have an id."))?,
+                    parent: number(entity, TB_GROUP),
+                    visgroup: number(entity, TB_LAYER),
+                }),
+                _ => (),
+            }
+        }
+
+        res.sort_groups();
+
+        // members of a group are on the layer of the outermost group
+        let layer_of_group = |mut group: i32| {
+            for _ in 0..res.groups.len() {
+                let current = res.groups.iter().find(|g| g.id == group)?;
+
+                match current.parent {
+                    Some(parent) => group = parent,
+                    None => return current.visgroup,
+                }
+            }
+
+            None
+        };
+
+        let group_layers = res
+            .groups
+            .iter()
+            .map(|group| layer_of_group(group.id))
+            .collect::<Vec<Option<i32>>>();
+
+        res.groups
+            .iter_mut()
+            .zip(group_layers)
+            .for_each(|(group, visgroup)| group.visgroup = visgroup);
+
+        let group_of = |entity: &Entity| {
+            number(entity, TB_GROUP).filter(|&group| res.groups.iter().any(|g| g.id == group))
+        };
+        let layer_of = |entity: &Entity| match group_of(entity) {
+            Some(group) => res
+                .groups
+                .iter()
+                .find(|g| g.id == group)
+                .and_then(|group| group.visgroup),
+            None => number(entity, TB_LAYER),
+        };
+
+        let mut world_brushes = vec![];
+        let mut entities = vec![];
+
+        for entity in &map.entities {
+            let brushes = entity.brushes.iter().flatten();
+
+            match tb_type(entity).as_str() {
+                TB_TYPE_LAYER => {
+                    let visgroup = number(entity, TB_ID);
+
+                    world_brushes.extend(brushes.map(|brush| EditorBrush {
+                        brush: brush.clone(),
+                        group: None,
+                        visgroup,
+                    }));
+                }
+                TB_TYPE_GROUP => {
+                    let group = number(entity, TB_ID);
+                    let visgroup = group.and_then(|group| {
+                        res.groups
+                            .iter()
+                            .find(|g| g.id == group)
+                            .and_then(|group| group.visgroup)
+                    });
+
+                    world_brushes.extend(brushes.map(|brush| EditorBrush {
+                        brush: brush.clone(),
+                        group,
+                        visgroup,
+                    }));
+                }
+                _ if std::ptr::eq(entity, worldspawn) => {
+                    world_brushes.extend(brushes.map(|brush| EditorBrush {
+                        brush: brush.clone(),
+                        group: None,
+                        visgroup: None,
+                    }));
+                }
+                _ => {
+                    let group = group_of(entity);
+                    let visgroup = layer_of(entity);
+
+                    let mut attributes = entity.attributes.clone();
+                    attributes.shift_remove(TB_GROUP);
+                    attributes.shift_remove(TB_LAYER);
+
+                    entities.push(EditorEntity {
+                        attributes,
+                        brushes: brushes
+                            .map(|brush| EditorBrush {
+                                brush: brush.clone(),
+                                group,
+                                visgroup,
+                            })
+                            .collect(),
+                        group,
+                        visgroup,
+                    });
+                }
+            }
+        }
+
+        res.entities[0].brushes = world_brushes;
+        res.entities.extend(entities);
+
+        Ok(res)
+    }
+
+    /// Puts parents before children and removes parents that do not exist or loop.
+    pub fn sort_groups(&mut self) {
+        let mut sorted: Vec<Group> = vec![];
+        let mut remaining = std::mem::take(&mut self.groups);
+
+        for group in remaining.iter_mut() {
+            if group.parent.is_some_and(|parent| parent == group.id) {
+                group.parent = None;
+            }
+        }
+
+        while !remaining.is_empty() {
+            let (ready, not_ready): (Vec<Group>, Vec<Group>) =
+                remaining.into_iter().partition(|group| {
+                    group
+                        .parent
+                        .is_none_or(|parent| sorted.iter().any(|g| g.id == parent))
+                });
+
+            if ready.is_empty() {
+                // the rest is either in a loop or has a missing parent
+                sorted.extend(not_ready.into_iter().map(|group| Group {
+                    parent: None,
+                    ..group
+                }));
+                break;
+            }
+
+            sorted.extend(ready);
+            remaining = not_ready;
+        }
+
+        self.groups = sorted;
+    }
+
+    /// The outermost group that this group is in.
+    pub fn root_group(&self, mut group: i32) -> i32 {
+        for _ in 0..self.groups.len() {
+            match self
+                .groups
+                .iter()
+                .find(|g| g.id == group)
+                .and_then(|g| g.parent)
+            {
+                Some(parent) => group = parent,
+                None => break,
+            }
+        }
+
+        group
+    }
+}
+
+/// Turns `0.1f32` into `0.1` instead of `0.10000000149011612`.
+pub(crate) fn f32_to_f64(number: f32) -> f64 {
+    number.to_string().parse().unwrap_or(number as f64)
+}
+
+pub(crate) fn parse_vec3(i: &[u8]) -> IResult<DVec3> {
+    map(tuple((le_f32, le_f32, le_f32)), |(x, y, z)| {
+        DVec3::new(f32_to_f64(x), f32_to_f64(y), f32_to_f64(z))
+    })(i)
+}
+
+pub(crate) fn parse_color(i: &[u8]) -> IResult<[u8; 3]> {
+    map(take(3usize), |color: &[u8]| [color[0], color[1], color[2]])(i)
+}
+
+/// Null terminated string in a fixed size array.
+pub(crate) fn parse_fixed_string(length: usize) -> impl Fn(&[u8]) -> IResult<String> {
+    move |i| map(take(length), bytes_to_string)(i)
+}
+
+/// Stops at the first null.
+pub(crate) fn bytes_to_string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+pub(crate) fn write_vec3(writer: &mut byte_writer::ByteWriter, v: DVec3) {
+    // no negative zero
+    let v = v + DVec3::ZERO;
+
+    writer.append_f32(v.x as f32);
+    writer.append_f32(v.y as f32);
+    writer.append_f32(v.z as f32);
+}
+
+/// Fails when the string does not fit with its null.
+pub(crate) fn write_fixed_string(
+    writer: &mut byte_writer::ByteWriter,
+    s: &str,
+    length: usize,
+) -> eyre::Result<()> {
+    if s.len() >= length {
+        return Err(eyre::eyre!(
+            "\"{}\" is longer than {} characters.",
+            s,
+            length - 1
+        ));
+    }
+
+    writer.append_string(s);
+    writer.append_u8_slice(&vec![0; length - s.len()]);
+
+    Ok(())
+}
@@ -0,0 +1,622 @@
+//! J.A.C.K. JMF version 121 and 122
+//!
+//! Faces only store vertices so plane points come from the vertices.
+use byte_writer::ByteWriter;
+use glam::DVec3;
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::{map, verify},
+    multi::{count, length_count, length_data, many0},
+    number::complete::{le_f32, le_f64, le_i32, le_u32, le_u8},
+    sequence::tuple,
+};
+
+use crate::{
+    editor::{
+        bytes_to_string, f32_to_f64, parse_fixed_string, parse_vec3, write_fixed_string,
+        write_vec3, EditorBrush, EditorEntity, EditorMap, Group, IResult, Visgroup,
+        DEFAULT_OBJECT_COLOR,
+    },
+    winding::{brush_windings, plane_equation, plane_points_from_winding},
+    Attributes, Brush, BrushPlane,
+};
+
+const JMF_VERSION: i32 = 121;
+const JMF_VERSION_WITH_BACKGROUND_IMAGES: i32 = 122;
+const JMF_TEXTURE_NAME_LENGTH: usize = 64;
+/// Entities have a list of strings that are not used.
+const JMF_ENTITY_SPECIAL_STRING_COUNT: usize = 13;
+/// Angles, render properties and more that are also in the key values.
+const JMF_ENTITY_PROPERTIES_LENGTH: usize = 76;
+/// Texture axes, shifts, scales, rotation and texture name of a face or a patch.
+const JMF_SURFACE_PROPERTIES_LENGTH: usize = 124;
+/// Patches always store 32x32 control points no matter how many are used.
+const JMF_PATCH_GRID_SIZE: usize = 32;
+/// Position, normal, texture coordinates and selection of a patch control point.
+const JMF_PATCH_POINT_LENGTH: usize = 40;
+
+struct JmfGroup {
+    id: i32,
+    parent: i32,
+}
+
+struct JmfBrush {
+    group: i32,
+    visgroups: Vec<i32>,
+    brush: Brush,
+}
+
+struct JmfEntity {
+    classname: String,
+    origin: DVec3,
+    group: i32,
+    spawnflags: i32,
+    key_values: Vec<(String, String)>,
+    visgroups: Vec<i32>,
+    brushes: Vec<JmfBrush>,
+    /// Brushes with patches are left out.
+    patch_brush_count: usize,
+}
+
+struct Jmf {
+    groups: Vec<JmfGroup>,
+    visgroups: Vec<Visgroup>,
+    entities: Vec<JmfEntity>,
+}
+
+/// String with its length in front and no null.
+fn parse_string(i: &[u8]) -> IResult<String> {
+    map(length_data(le_u32), bytes_to_string)(i)
+}
+
+fn parse_key_values(i: &[u8]) -> IResult<Vec<(String, String)>> {
+    length_count(le_u32, tuple((parse_string, parse_string)))(i)
+}
+
+fn parse_rgba(i: &[u8]) -> IResult<[u8; 3]> {
+    map(take(4usize), |color: &[u8]| [color[0], color[1], color[2]])(i)
+}
+
+fn parse_visgroup_ids(i: &[u8]) -> IResult<Vec<i32>> {
+    length_count(le_u32, le_i32)(i)
+}
+
+fn parse_background_image(i: &[u8]) -> IResult<()> {
+    map(tuple((parse_string, le_f64, count(le_i32, 7))), |_| ())(i)
+}
+
+fn parse_group(i: &[u8]) -> IResult<JmfGroup> {
+    map(
+        tuple((le_i32, le_i32, le_i32, le_i32, parse_rgba)),
+        |(id, parent, _flags, _count, _color)| JmfGroup { id, parent },
+    )(i)
+}
+
+fn parse_visgroup(i: &[u8]) -> IResult<Visgroup> {
+    map(
+        tuple((parse_string, le_i32, parse_rgba, le_u8)),
+        |(name, id, color, visible)| Visgroup {
+            id,
+            name,
+            color,
+            visible: visible != 0,
+        },
+    )(i)
+}
+
+fn parse_camera(i: &[u8]) -> IResult<()> {
+    map(tuple((parse_vec3, parse_vec3, le_i32, parse_rgba)), |_| ())(i)
+}
+
+fn parse_path_node(i: &[u8]) -> IResult<()> {
+    map(
+        tuple((
+            parse_string,
+            parse_string,
+            parse_vec3,
+            parse_vec3,
+            le_i32,
+            parse_rgba,
+            parse_key_values,
+        )),
+        |_| (),
+    )(i)
+}
+
+// Paths are also entities in the file so they are skipped.
+fn parse_path(i: &[u8]) -> IResult<()> {
+    map(
+        tuple((
+            parse_string,
+            parse_string,
+            le_i32,
+            take(4usize),
+            parse_rgba,
+            length_count(le_u32, parse_path_node),
+        )),
+        |_| (),
+    )(i)
+}
+
+fn parse_vertex(i: &[u8]) -> IResult<DVec3> {
+    map(
+        tuple((parse_vec3, le_f32, le_f32, le_i32)),
+        |(vertex, ..)| vertex,
+    )(i)
+}
+
+/// Faces without enough vertices are clipped away and not needed.
+fn parse_face(i: &[u8]) -> IResult<Option<BrushPlane>> {
+    let (i, (_render_flags, vertex_count)) = tuple((le_i32, le_u32))(i)?;
+
+    map(
+        tuple((
+            parse_vec3,
+            le_f32,
+            parse_vec3,
+            le_f32,
+            le_f32,
+            le_f32,
+            le_f32,
+            take(16usize),
+            parse_fixed_string(JMF_TEXTURE_NAME_LENGTH),
+            parse_vec3,
+            le_f32,
+            take(4usize),
+            count(parse_vertex, vertex_count as usize),
+        )),
+        |(
+            u,
+            u_offset,
+            v,
+            v_offset,
+            u_scale,
+            v_scale,
+            rotation,
+            _,
+            texture_name,
+            normal,
+            _distance,
+            _,
+            vertices,
+        )| {
+            let (p1, p2, p3) = plane_points_from_winding(&vertices, normal)?;
+
+            Some(BrushPlane {
+                p1,
+                p2,
+                p3,
+                texture_name,
+                u: u.extend(f32_to_f64(u_offset)),
+                v: v.extend(f32_to_f64(v_offset)),
+                rotation: f32_to_f64(rotation),
+                u_scale: f32_to_f64(u_scale),
+                v_scale: f32_to_f64(v_scale),
+            })
+        },
+    )(i)
+}
+
+fn parse_patch(i: &[u8]) -> IResult<()> {
+    map(
+        tuple((
+            le_i32,
+            le_i32,
+            take(JMF_SURFACE_PROPERTIES_LENGTH),
+            le_i32,
+            take(JMF_PATCH_POINT_LENGTH * JMF_PATCH_GRID_SIZE * JMF_PATCH_GRID_SIZE),
+        )),
+        |_| (),
+    )(i)
+}
+
+/// Brushes with patches are `None` because GoldSrc does not have curves.
+fn parse_brush(i: &[u8]) -> IResult<Option<JmfBrush>> {
+    let (i, (patch_count, _flags, group, _root_group, _color, visgroups, faces)) = tuple((
+        le_u32,
+        le_i32,
+        le_i32,
+        le_i32,
+        parse_rgba,
+        parse_visgroup_ids,
+        length_count(le_u32, parse_face),
+    ))(i)?;
+
+    let (i, _) = count(parse_patch, patch_count as usize)(i)?;
+
+    if patch_count != 0 {
+        return Ok((i, None));
+    }
+
+    Ok((
+        i,
+        Some(JmfBrush {
+            group,
+            visgroups,
+            brush: Brush {
+                planes: faces.into_iter().flatten().collect(),
+                comments: None,
+            },
+        }),
+    ))
+}
+
+fn parse_entity(i: &[u8]) -> IResult<JmfEntity> {
+    map(
+        tuple((
+            parse_string,
+            parse_vec3,
+            le_i32,
+            le_i32,
+            le_i32,
+            parse_rgba,
+            count(parse_string, JMF_ENTITY_SPECIAL_STRING_COUNT),
+            le_i32,
+            take(JMF_ENTITY_PROPERTIES_LENGTH),
+            parse_key_values,
+            parse_visgroup_ids,
+            length_count(le_u32, parse_brush),
+        )),
+        |(
+            classname,
+            origin,
+            _flags,
+            group,
+            _root_group,
+            _color,
+            _,
+            spawnflags,
+            _,
+            key_values,
+            visgroups,
+            brushes,
+        )| JmfEntity {
+            classname,
+            origin,
+            group,
+            spawnflags,
+            key_values,
+            visgroups,
+            patch_brush_count: brushes.iter().filter(|brush| brush.is_none()).count(),
+            brushes: brushes.into_iter().flatten().collect(),
+        },
+    )(i)
+}
+
+fn parse_jmf(i: &[u8]) -> IResult<Jmf> {
+    let (i, (_, version)) = tuple((
+        tag("JHMF"),
+        verify(le_i32, |version| {
+            *version == JMF_VERSION || *version == JMF_VERSION_WITH_BACKGROUND_IMAGES
+        }),
+    ))(i)?;
+
+    let (i, _export_paths) = length_count(le_u32, parse_string)(i)?;
+
+    let (i, _) = if version == JMF_VERSION_WITH_BACKGROUND_IMAGES {
+        count(parse_background_image, 3)(i)?
+    } else {
+        (i, vec![])
+    };
+
+    map(
+        tuple((
+            length_count(le_u32, parse_group),
+            length_count(le_u32, parse_visgroup),
+            // cordon
+            parse_vec3,
+            parse_vec3,
+            length_count(le_u32, parse_camera),
+            length_count(le_u32, parse_path),
+            many0(parse_entity),
+        )),
+        |(groups, visgroups, _, _, _, _, entities)| Jmf {
+            groups,
+            visgroups,
+            entities,
+        },
+    )(i)
+}
+
+fn id(id: i32) -> Option<i32> {
+    (id > 0).then_some(id)
+}
+
+/// Also returns how many brushes with patches were left out.
+pub(crate) fn jmf_to_editor_map(bytes: &[u8]) -> eyre::Result<(EditorMap, usize)> {
+    let (rest, jmf) =
+        parse_jmf(bytes).map_err(|err| eyre::eyre!("Cannot parse JMF: {}", err.to_string()))?;
+
+    if !rest.is_empty() {
+        return Err(eyre::eyre!(
+            "Cannot parse JMF: {} bytes at the end are not entities.",
+            rest.len()
+        ));
+    }
+
+    let mut res = EditorMap::new(Attributes::from([(
+        "classname".to_string(),
+        "worldspawn".to_string(),
+    )]));
+
+    res.visgroups = jmf.visgroups;
+    res.groups = jmf
+        .groups
+        .iter()
+        .map(|group| Group {
+            id: group.id,
+            parent: id(group.parent),
+            visgroup: None,
+        })
+        .collect();
+
+    res.sort_groups();
+
+    let group_exists =
+        |group: i32| id(group).filter(|&group| res.groups.iter().any(|g| g.id == group));
+
+    let patch_brush_count = jmf
+        .entities
+        .iter()
+        .map(|entity| entity.patch_brush_count)
+        .sum::<usize>();
+
+    let mut entities = vec![];
+
+    for entity in jmf.entities {
+        if entity.classname == "worldspawn" {
+            res.entities[0].attributes = EditorMap::entity_attributes(
+                entity.classname,
+                entity.key_values,
+                entity.spawnflags,
+                None,
+            );
+            res.entities[0].brushes = entity
+                .brushes
+                .into_iter()
+                .map(|brush| EditorBrush {
+                    brush: brush.brush,
+                    group: group_exists(brush.group),
+                    visgroup: brush.visgroups.first().copied().and_then(id),
+                })
+                .collect();
+
+            continue;
+        }
+
+        let group = group_exists(entity.group);
+        let visgroup = entity.visgroups.first().copied().and_then(id);
+
+        let brushes = entity
+            .brushes
+            .into_iter()
+            .map(|brush| EditorBrush {
+                brush: brush.brush,
+                group,
+                visgroup,
+            })
+            .collect::<Vec<EditorBrush>>();
+
+        entities.push(EditorEntity {
+            attributes: EditorMap::entity_attributes(
+                entity.classname,
+                entity.key_values,
+                entity.spawnflags,
+                brushes.is_empty().then_some(entity.origin),
+            ),
+            brushes,
+            group,
+            visgroup,
+        });
+    }
+
+    res.entities.extend(entities);
+
+    // groups do not have visgroups so they take one from their members
+    for index in 0..res.groups.len() {
+        let group = Some(res.groups[index].id);
+
+        res.groups[index].visgroup = res.entities[0]
+            .brushes
+            .iter()
+            .filter(|brush| brush.group == group)
+            .map(|brush| brush.visgroup)
+            .chain(
+                res.entities[1..]
+                    .iter()
+                    .filter(|entity| entity.group == group)
+                    .map(|entity| entity.visgroup),
+            )
+            .find(|visgroup| visgroup.is_some())
+            .flatten();
+    }
+
+    Ok((res, patch_brush_count))
+}
+
+fn write_string(writer: &mut ByteWriter, s: &str) {
+    writer.append_u32(s.len() as u32);
+    writer.append_string(s);
+}
+
+fn write_rgba(writer: &mut ByteWriter, color: [u8; 3]) {
+    writer.append_u8_slice(&color);
+    writer.append_u8(255);
+}
+
+fn write_visgroup_ids(writer: &mut ByteWriter, visgroup: Option<i32>) {
+    match visgroup {
+        Some(visgroup) => {
+            writer.append_i32(1);
+            writer.append_i32(visgroup);
+        }
+        None => writer.append_i32(0),
+    }
+}
+
+fn write_brush(
+    writer: &mut ByteWriter,
+    editor: &EditorMap,
+    brush: &Brush,
+    group: Option<i32>,
+    visgroup: Option<i32>,
+) -> eyre::Result<()> {
+    // no curves
+    writer.append_i32(0);
+    writer.append_i32(0);
+    writer.append_i32(group.unwrap_or(0));
+    writer.append_i32(group.map(|group| editor.root_group(group)).unwrap_or(0));
+    write_rgba(writer, DEFAULT_OBJECT_COLOR);
+    write_visgroup_ids(writer, visgroup);
+
+    writer.append_i32(brush.planes.len() as i32);
+
+    for (plane, winding) in brush.planes.iter().zip(brush_windings(brush)) {
+        let (normal, distance) = plane_equation(plane);
+
+        writer.append_i32(0);
+        writer.append_i32(winding.len() as i32);
+        write_vec3(writer, plane.u.truncate());
+        writer.append_f32(plane.u.w as f32);
+        write_vec3(writer, plane.v.truncate());
+        writer.append_f32(plane.v.w as f32);
+        writer.append_f32(plane.u_scale as f32);
+        writer.append_f32(plane.v_scale as f32);
+        writer.append_f32(plane.rotation as f32);
+        writer.append_u8_slice(&[0; 16]);
+        write_fixed_string(writer, &plane.texture_name, JMF_TEXTURE_NAME_LENGTH)?;
+        write_vec3(writer, normal);
+        writer.append_f32(distance as f32);
+        writer.append_u8_slice(&[0; 4]);
+
+        for vertex in winding {
+            write_vec3(writer, vertex);
+            // texture coordinates are made again by the editor
+            writer.append_f32(0.);
+            writer.append_f32(0.);
+            writer.append_i32(0);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_entity(
+    writer: &mut ByteWriter,
+    editor: &EditorMap,
+    entity: &EditorEntity,
+) -> eyre::Result<()> {
+    write_string(writer, entity.classname());
+    write_vec3(
+        writer,
+        if entity.is_point_entity() {
+            entity.origin()
+        } else {
+            DVec3::ZERO
+        },
+    );
+    writer.append_i32(0);
+    writer.append_i32(entity.group.unwrap_or(0));
+    writer.append_i32(
+        entity
+            .group
+            .map(|group| editor.root_group(group))
+            .unwrap_or(0),
+    );
+    write_rgba(writer, DEFAULT_OBJECT_COLOR);
+
+    for _ in 0..JMF_ENTITY_SPECIAL_STRING_COUNT {
+        write_string(writer, "");
+    }
+
+    writer.append_i32(entity.spawnflags());
+    writer.append_u8_slice(&[0; JMF_ENTITY_PROPERTIES_LENGTH]);
+
+    let key_values = entity
+        .attributes
+        .iter()
+        .filter(|(key, _)| !entity.is_special_key(key))
+        .collect::<Vec<_>>();
+
+    writer.append_i32(key_values.len() as i32);
+
+    for (key, value) in key_values {
+        write_string(writer, key);
+        write_string(writer, value);
+    }
+
+    write_visgroup_ids(writer, entity.visgroup);
+
+    writer.append_i32(entity.brushes.len() as i32);
+
+    for brush in &entity.brushes {
+        // worldspawn brushes have their own groups
+        let (group, visgroup) = if entity.classname() == "worldspawn" {
+            (brush.group, brush.visgroup)
+        } else {
+            (entity.group, entity.visgroup)
+        };
+
+        write_brush(writer, editor, &brush.brush, group, visgroup)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn editor_map_to_jmf(editor: &EditorMap) -> eyre::Result<Vec<u8>> {
+    let mut writer = ByteWriter::new();
+
+    writer.append_string("JHMF");
+    writer.append_i32(JMF_VERSION);
+
+    // no export paths
+    writer.append_i32(0);
+
+    writer.append_i32(editor.groups.len() as i32);
+
+    for group in &editor.groups {
+        let member_count = editor.entities[0]
+            .brushes
+            .iter()
+            .filter(|brush| brush.group == Some(group.id))
+            .count()
+            + editor.entities[1..]
+                .iter()
+                .filter(|entity| entity.group == Some(group.id))
+                .count()
+            + editor
+                .groups
+                .iter()
+                .filter(|g| g.parent == Some(group.id))
+                .count();
+
+        writer.append_i32(group.id);
+        writer.append_i32(group.parent.unwrap_or(0));
+        writer.append_i32(0);
+        writer.append_i32(member_count as i32);
+        write_rgba(&mut writer, DEFAULT_OBJECT_COLOR);
+    }
+
+    writer.append_i32(editor.visgroups.len() as i32);
+
+    for visgroup in &editor.visgroups {
+        write_string(&mut writer, &visgroup.name);
+        writer.append_i32(visgroup.id);
+        write_rgba(&mut writer, visgroup.color);
+        writer.append_u8(visgroup.visible as u8);
+    }
+
+    // cordon
+    write_vec3(&mut writer, DVec3::ZERO);
+    write_vec3(&mut writer, DVec3::ZERO);
+
+    // no cameras and no paths
+    writer.append_i32(0);
+    writer.append_i32(0);
+
+    for entity in &editor.entities {
+        write_entity(&mut writer, editor, entity)?;
+    }
+
+    Ok(writer.data)
+}
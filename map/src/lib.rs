@@ -1,23 +1,63 @@
 use std::{
-    collections::HashMap,
     fs::OpenOptions,
     io::{self, BufWriter, Write},
     path::{Path, PathBuf},
 };
 
 use glam::{DVec3, DVec4};
+use indexmap::IndexMap;
 use nom::{
+    branch::alt,
     bytes::complete::{tag, take_till},
     character::complete::{multispace0, space0},
-    combinator::{all_consuming, map, opt, recognize},
+    combinator::{all_consuming, map, opt, recognize, verify},
     multi::{fold_many1, many0, many1, many_m_n},
     number::complete::double as _double,
-    sequence::{delimited, preceded, terminated, tuple},
+    sequence::{preceded, terminated, tuple},
     IResult as _IResult,
 };
 
 use eyre::eyre;
 
+mod editor;
+mod jmf;
+mod rmf;
+mod standard;
+mod winding;
+
+use editor::EditorMap;
+pub use standard::{base_texture_axes, StandardTexture};
+pub use winding::{brush_plane_winding, brush_windings, plane_equation, ON_PLANE_EPSILON};
+
+/// How texture alignment is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapFormat {
+    /// Texture axes are written. Also known as Valve220.
+    #[default]
+    Valve220,
+    /// Quake and Hammer 3.x format with only offset, rotation and scale
+    Standard,
+}
+
+/// Binary formats of level editors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditorFormat {
+    Rmf,
+    Jmf,
+}
+
+impl EditorFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+
+        match extension.as_str() {
+            "rmf" => Some(Self::Rmf),
+            "jmf" => Some(Self::Jmf),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct BrushPlane {
     pub p1: DVec3,
@@ -47,6 +87,10 @@ impl TryFrom<&str> for BrushPlane {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Brush {
     pub planes: Vec<BrushPlane>,
+    /// Comment lines before the brush without `//`.
+    ///
+    /// `None` writes "// brush N" like TrenchBroom.
+    pub comments: Option<Vec<String>>,
 }
 
 impl TryFrom<&str> for Brush {
@@ -54,20 +98,26 @@ impl TryFrom<&str> for Brush {
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match parse_brush(value) {
-            Ok((_, res)) => Ok(res),
+            Ok((_, (res, _))) => Ok(res),
             Err(err) => Err(err.to_string().leak()),
         }
     }
 }
 
-// #[derive(Debug, Clone, PartialEq)]
-pub type Attributes = HashMap<String, String>;
+/// Keys stay in the order they are read and inserted.
+pub type Attributes = IndexMap<String, String>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Entity {
     // All entities have attributes.
     pub attributes: Attributes,
     pub brushes: Option<Vec<Brush>>,
+    /// Comment lines before the entity without `//`.
+    ///
+    /// `None` writes "// entity N" like TrenchBroom.
+    pub comments: Option<Vec<String>>,
+    /// Comment lines after the last brush, or after the attributes without brushes, without `//`.
+    pub trailing_comments: Vec<String>,
 }
 
 impl TryFrom<&str> for Entity {
@@ -75,7 +125,7 @@ impl TryFrom<&str> for Entity {
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match parse_entity(value) {
-            Ok((_, res)) => Ok(res),
+            Ok((_, (res, _))) => Ok(res),
             Err(err) => Err(err.to_string().leak()),
         }
     }
@@ -85,6 +135,8 @@ impl TryFrom<&str> for Entity {
 pub struct Map {
     pub tb_header: Option<Vec<String>>,
     pub entities: Vec<Entity>,
+    /// Comment lines after the last entity without `//`.
+    pub trailing_comments: Vec<String>,
 }
 
 impl Default for Map {
@@ -98,23 +150,131 @@ impl Map {
         Self {
             tb_header: None,
             entities: vec![],
+            trailing_comments: vec![],
         }
     }
 
+    /// Parses Valve220 and standard format. Standard format planes are converted to Valve220.
     pub fn from_text(text: &str) -> eyre::Result<Self> {
         match parse_map(text) {
-            Ok((_, res)) => Ok(res),
+            Ok((_, (mut res, format))) => {
+                if format == MapFormat::Standard {
+                    res.mark_valve220();
+                }
+
+                Ok(res)
+            }
             Err(err) => Err(eyre!("Cannot parse text: {}", err.to_string())),
         }
     }
 
+    /// Tells compilers and TrenchBroom that brush planes were converted to Valve220.
+    fn mark_valve220(&mut self) {
+        if let Some(worldspawn) = self.entities.first_mut() {
+            if worldspawn.brushes.is_some() && !worldspawn.attributes.contains_key("mapversion") {
+                worldspawn
+                    .attributes
+                    .insert("mapversion".to_string(), "220".to_string());
+            }
+        }
+
+        if let Some(tb_header) = &mut self.tb_header {
+            tb_header.iter_mut().for_each(|line| {
+                if line.trim() == "Format: Standard" {
+                    *line = " Format: Valve".to_string();
+                }
+            });
+        }
+    }
+
+    /// Reads .map, .rmf or .jmf depending on the extension.
     pub fn from_file(path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<Self> {
-        let text = std::fs::read_to_string(path)?;
+        Self::from_file_with_warnings(path).map(|(res, _)| res)
+    }
 
-        Self::from_text(&text)
+    /// Same as [`Self::from_file`] and also returns what was left out, such as JMF brushes with patches.
+    pub fn from_file_with_warnings(
+        path: impl AsRef<Path> + Into<PathBuf>,
+    ) -> eyre::Result<(Self, Vec<String>)> {
+        match EditorFormat::from_path(path.as_ref()) {
+            Some(EditorFormat::Rmf) => Ok((Self::from_rmf_bytes(&std::fs::read(path)?)?, vec![])),
+            Some(EditorFormat::Jmf) => Self::from_jmf_bytes_with_warnings(&std::fs::read(path)?),
+            None => {
+                let text = std::fs::read_to_string(path)?;
+
+                Ok((Self::from_text(&text)?, vec![]))
+            }
+        }
     }
 
+    /// Writes .map, .rmf or .jmf depending on the extension.
     pub fn write(&self, path: impl AsRef<Path> + Into<PathBuf>) -> io::Result<()> {
+        let bytes = match EditorFormat::from_path(path.as_ref()) {
+            Some(EditorFormat::Rmf) => self.to_rmf_bytes(),
+            Some(EditorFormat::Jmf) => self.to_jmf_bytes(),
+            None => return self.write_as(path, MapFormat::Valve220),
+        };
+
+        let bytes =
+            bytes.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+        std::fs::write(path, bytes)
+    }
+
+    /// Reads Hammer RMF version 2.2.
+    ///
+    /// Visgroups become TrenchBroom layers and groups become TrenchBroom groups.
+    /// Paths become entities like when Hammer exports .map.
+    pub fn from_rmf_bytes(bytes: &[u8]) -> eyre::Result<Self> {
+        Ok(rmf::rmf_to_editor_map(bytes)?.to_map())
+    }
+
+    /// Writes Hammer RMF version 2.2.
+    ///
+    /// TrenchBroom layers become visgroups and TrenchBroom groups become groups.
+    pub fn to_rmf_bytes(&self) -> eyre::Result<Vec<u8>> {
+        rmf::editor_map_to_rmf(&EditorMap::from_map(self)?)
+    }
+
+    /// Reads J.A.C.K. JMF version 121 or 122.
+    ///
+    /// Visgroups become TrenchBroom layers and groups become TrenchBroom groups.
+    /// Brushes with patches are left out because GoldSrc does not have curves.
+    pub fn from_jmf_bytes(bytes: &[u8]) -> eyre::Result<Self> {
+        Self::from_jmf_bytes_with_warnings(bytes).map(|(res, _)| res)
+    }
+
+    /// Same as [`Self::from_jmf_bytes`] and also returns what was left out.
+    pub fn from_jmf_bytes_with_warnings(bytes: &[u8]) -> eyre::Result<(Self, Vec<String>)> {
+        let (editor_map, patch_brush_count) = jmf::jmf_to_editor_map(bytes)?;
+
+        let warnings = if patch_brush_count == 0 {
+            vec![]
+        } else {
+            vec![format!(
+                "Skipped {} brushes with patches because GoldSrc does not have curves",
+                patch_brush_count
+            )]
+        };
+
+        Ok((editor_map.to_map(), warnings))
+    }
+
+    /// Writes J.A.C.K. JMF version 121.
+    ///
+    /// TrenchBroom layers become visgroups and TrenchBroom groups become groups.
+    pub fn to_jmf_bytes(&self) -> eyre::Result<Vec<u8>> {
+        jmf::editor_map_to_jmf(&EditorMap::from_map(self)?)
+    }
+
+    /// Writes in Valve220 or standard format.
+    ///
+    /// Standard format cannot have every texture alignment of Valve220, see [`BrushPlane::to_standard`].
+    pub fn write_as(
+        &self,
+        path: impl AsRef<Path> + Into<PathBuf>,
+        format: MapFormat,
+    ) -> io::Result<()> {
         let file = OpenOptions::new()
             .create(true)
             .write(true)
@@ -125,6 +285,11 @@ impl Map {
 
         if let Some(tb_header) = &self.tb_header {
             for s in tb_header {
+                let s = match format {
+                    MapFormat::Standard if s.trim() == "Format: Valve" => " Format: Standard",
+                    _ => s,
+                };
+
                 file.write_all("//".as_bytes())?;
                 file.write_all(s.as_bytes())?;
                 file.write_all("\n".as_bytes())?;
@@ -132,44 +297,157 @@ impl Map {
         }
 
         for (entity_index, entities) in self.entities.iter().enumerate() {
-            file.write_all(format!("// entity {}\n", entity_index).as_bytes())?;
+            write_comments(
+                &mut file,
+                entities.comments.as_deref(),
+                "entity",
+                entity_index,
+            )?;
 
             file.write_all("{\n".as_bytes())?;
 
             for (key, value) in &entities.attributes {
+                if format == MapFormat::Standard && key == "mapversion" {
+                    continue;
+                }
+
                 file.write_all(format!("\"{}\" \"{}\"\n", key, value).as_bytes())?;
             }
 
             if let Some(brushes) = &entities.brushes {
                 for (brush_entity, brush) in brushes.iter().enumerate() {
-                    file.write_all(format!("// brush {}\n", brush_entity).as_bytes())?;
+                    write_comments(&mut file, brush.comments.as_deref(), "brush", brush_entity)?;
                     file.write_all("{\n".as_bytes())?;
 
                     for plane in &brush.planes {
-                        file.write_all(format!("( {} {} {} ) ( {} {} {} ) ( {} {} {} ) {} [ {} {} {} {} ] [ {} {} {} {} ] {} {} {}\n", 
-                    plane.p1.x,plane.p1.y,plane.p1.z,
-                    plane.p2.x,plane.p2.y,plane.p2.z,
-                    plane.p3.x,plane.p3.y,plane.p3.z,
-                    plane.texture_name,
-                    plane.u.x,plane.u.y,plane.u.z,plane.u.w,
-                    plane.v.x,plane.v.y,plane.v.z,plane.v.w,
-                    plane.rotation, plane.u_scale, plane.v_scale,
-
-                ).as_bytes())?;
+                        let points = format!(
+                            "( {} {} {} ) ( {} {} {} ) ( {} {} {} ) {}",
+                            format_number(plane.p1.x),
+                            format_number(plane.p1.y),
+                            format_number(plane.p1.z),
+                            format_number(plane.p2.x),
+                            format_number(plane.p2.y),
+                            format_number(plane.p2.z),
+                            format_number(plane.p3.x),
+                            format_number(plane.p3.y),
+                            format_number(plane.p3.z),
+                            plane.texture_name,
+                        );
+
+                        let texture = match format {
+                            MapFormat::Valve220 => format!(
+                                "[ {} {} {} {} ] [ {} {} {} {} ] {} {} {}",
+                                format_number(plane.u.x),
+                                format_number(plane.u.y),
+                                format_number(plane.u.z),
+                                format_number(plane.u.w),
+                                format_number(plane.v.x),
+                                format_number(plane.v.y),
+                                format_number(plane.v.z),
+                                format_number(plane.v.w),
+                                format_number(plane.rotation),
+                                format_number(plane.u_scale),
+                                format_number(plane.v_scale),
+                            ),
+                            MapFormat::Standard => {
+                                let StandardTexture {
+                                    x_offset,
+                                    y_offset,
+                                    rotation,
+                                    x_scale,
+                                    y_scale,
+                                } = plane.to_standard();
+
+                                format!(
+                                    "{} {} {} {} {}",
+                                    format_number(x_offset),
+                                    format_number(y_offset),
+                                    format_number(rotation),
+                                    format_number(x_scale),
+                                    format_number(y_scale)
+                                )
+                            }
+                        };
+
+                        file.write_all(format!("{} {}\n", points, texture).as_bytes())?;
                     }
                     file.write_all("}\n".as_bytes())?;
                 }
             }
 
+            write_trailing_comments(&mut file, &entities.trailing_comments)?;
             file.write_all("}\n".as_bytes())?;
         }
 
+        write_trailing_comments(&mut file, &self.trailing_comments)?;
+
         file.flush()?;
 
         Ok(())
     }
 }
 
+/// Writes comments before an entity or a brush.
+///
+/// "entity N" and "brush N" comments are numbered again in case things are added or removed.
+fn write_comments(
+    file: &mut impl Write,
+    comments: Option<&[String]>,
+    kind: &str,
+    index: usize,
+) -> io::Result<()> {
+    let Some(comments) = comments else {
+        return file.write_all(format!("// {} {}\n", kind, index).as_bytes());
+    };
+
+    for comment in comments {
+        if is_numbering_comment(comment, kind) {
+            file.write_all(format!("// {} {}\n", kind, index).as_bytes())?;
+        } else {
+            file.write_all(format!("//{}\n", comment).as_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes comments after the last brush or entity as they are.
+fn write_trailing_comments(file: &mut impl Write, comments: &[String]) -> io::Result<()> {
+    for comment in comments {
+        file.write_all(format!("//{}\n", comment).as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// " entity 3" or " brush 12"
+fn is_numbering_comment(comment: &str, kind: &str) -> bool {
+    comment
+        .trim()
+        .strip_prefix(kind)
+        .and_then(|rest| rest.strip_prefix(' '))
+        .is_some_and(|number| !number.is_empty() && number.bytes().all(|c| c.is_ascii_digit()))
+}
+
+/// Same as C `%g` with enough digits, so numbers look the same as TrenchBroom writes them.
+fn format_number(number: f64) -> String {
+    if number != 0. && (number.abs() < 1e-4 || number.abs() >= 1e16) {
+        // exponent has a sign and at least two digits
+        let res = format!("{:e}", number);
+        let (mantissa, exponent) = res.split_once('e').unwrap();
+        let exponent = exponent.parse::<i32>().unwrap();
+
+        format!(
+            "{}e{}{:02}",
+            mantissa,
+            if exponent < 0 { '-' } else { '+' },
+            exponent.abs()
+        )
+    } else {
+        number.to_string()
+    }
+}
+
 type IResult<'a, T> = _IResult<&'a str, T>;
 
 fn take_comment_line(i: &str) -> IResult<&str> {
@@ -180,13 +458,25 @@ fn take_comment_line(i: &str) -> IResult<&str> {
 }
 
 fn take_tb_header(i: &str) -> IResult<Vec<String>> {
-    many_m_n(0, 2, map(take_comment_line, |i| i.to_string()))(i)
+    many_m_n(
+        0,
+        2,
+        map(
+            verify(take_comment_line, |comment: &str| {
+                let comment = comment.trim_start();
+
+                comment.starts_with("Game:") || comment.starts_with("Format:")
+            }),
+            |i| i.to_string(),
+        ),
+    )(i)
 }
 
-// TODO: make it not discard
 // Many 0 because it doesn't necessary have it every time.
-fn discard_comment_lines(i: &str) -> IResult<&str> {
-    map(many0(take_comment_line), |_| "")(i)
+fn take_comment_lines(i: &str) -> IResult<Vec<String>> {
+    many0(map(take_comment_line, |comment| {
+        comment.trim_end_matches('\r').to_string()
+    }))(i)
 }
 
 fn signed_double(i: &str) -> IResult<f64> {
@@ -240,15 +530,47 @@ fn parse_plane_uv(i: &str) -> IResult<DVec4> {
     )(i)
 }
 
+/// Standard if any of the parts was in standard format.
+fn merge_formats(formats: impl IntoIterator<Item = MapFormat>) -> MapFormat {
+    if formats
+        .into_iter()
+        .any(|format| format == MapFormat::Standard)
+    {
+        MapFormat::Standard
+    } else {
+        MapFormat::Valve220
+    }
+}
+
+/// Brush plane and the format it was written in.
+fn parse_brush_plane_with_format(i: &str) -> IResult<(BrushPlane, MapFormat)> {
+    alt((
+        map(parse_valve220_brush_plane, |plane| {
+            (plane, MapFormat::Valve220)
+        }),
+        map(parse_standard_brush_plane, |plane| {
+            (plane, MapFormat::Standard)
+        }),
+    ))(i)
+}
+
 fn parse_brush_plane(i: &str) -> IResult<BrushPlane> {
+    map(parse_brush_plane_with_format, |(plane, _)| plane)(i)
+}
+
+fn parse_plane_texture_name(i: &str) -> IResult<String> {
+    map(terminated(take_till(|c| c == ' '), space0), |s: &str| {
+        s.to_string()
+    })(i)
+}
+
+fn parse_valve220_brush_plane(i: &str) -> IResult<BrushPlane> {
     map(
         tuple((
             parse_plane_coordinate,
             parse_plane_coordinate,
             parse_plane_coordinate,
-            map(terminated(take_till(|c| c == ' '), space0), |s: &str| {
-                s.to_string()
-            }),
+            parse_plane_texture_name,
             parse_plane_uv,
             parse_plane_uv,
             double,
@@ -269,19 +591,75 @@ fn parse_brush_plane(i: &str) -> IResult<BrushPlane> {
     )(i)
 }
 
-fn parse_brush(i: &str) -> IResult<Brush> {
+/// Converted to Valve220 right away
+fn parse_standard_brush_plane(i: &str) -> IResult<BrushPlane> {
     map(
-        many1(terminated(parse_brush_plane, multispace0)),
-        |planes| Brush { planes },
+        tuple((
+            parse_plane_coordinate,
+            parse_plane_coordinate,
+            parse_plane_coordinate,
+            parse_plane_texture_name,
+            double,
+            double,
+            double,
+            double,
+            double,
+        )),
+        |(p1, p2, p3, texture_name, x_offset, y_offset, rotation, x_scale, y_scale)| {
+            BrushPlane::from_standard(
+                (p1, p2, p3),
+                texture_name,
+                StandardTexture {
+                    x_offset,
+                    y_offset,
+                    rotation,
+                    x_scale,
+                    y_scale,
+                },
+            )
+        },
     )(i)
 }
 
-fn parse_brushes(i: &str) -> IResult<Vec<Brush>> {
-    many1(delimited(
-        discard_comment_lines,
-        between_line_bracket(parse_brush),
-        discard_comment_lines,
-    ))(i)
+// Parsers from here on also return whether any plane was in standard format.
+fn parse_brush(i: &str) -> IResult<(Brush, MapFormat)> {
+    map(
+        many1(terminated(parse_brush_plane_with_format, multispace0)),
+        |planes| {
+            let (planes, formats): (Vec<BrushPlane>, Vec<MapFormat>) = planes.into_iter().unzip();
+
+            (
+                Brush {
+                    planes,
+                    comments: None,
+                },
+                merge_formats(formats),
+            )
+        },
+    )(i)
+}
+
+// Comments after the last brush are left for the entity.
+fn parse_brushes(i: &str) -> IResult<(Vec<Brush>, MapFormat)> {
+    map(
+        many1(map(
+            tuple((take_comment_lines, between_line_bracket(parse_brush))),
+            |(comments, (brush, format))| {
+                (
+                    Brush {
+                        comments: Some(comments),
+                        ..brush
+                    },
+                    format,
+                )
+            },
+        )),
+        |brushes| {
+            let (brushes, formats): (Vec<Brush>, Vec<MapFormat>) = brushes.into_iter().unzip();
+
+            (brushes, merge_formats(formats))
+        },
+    )(i)
 }
 
 // For attributes
@@ -301,30 +679,67 @@ fn parse_attributes(i: &str) -> IResult<Attributes> {
 }
 
 // For map
-fn parse_entity(i: &str) -> IResult<Entity> {
+fn parse_entity(i: &str) -> IResult<(Entity, MapFormat)> {
     map(
-        tuple((parse_attributes, opt(parse_brushes))),
-        |(attributes, brushes)| Entity {
-            attributes,
-            brushes,
+        tuple((parse_attributes, opt(parse_brushes), take_comment_lines)),
+        |(attributes, brushes, trailing_comments)| {
+            let (brushes, format) = match brushes {
+                Some((brushes, format)) => (Some(brushes), format),
+                None => (None, MapFormat::Valve220),
+            };
+
+            (
+                Entity {
+                    attributes,
+                    brushes,
+                    comments: None,
+                    trailing_comments,
+                },
+                format,
+            )
         },
     )(i)
 }
 
-fn parse_entities(i: &str) -> IResult<Vec<Entity>> {
-    many1(delimited(
-        discard_comment_lines,
-        between_line_bracket(parse_entity),
-        discard_comment_lines,
-    ))(i)
+// Comments after the last entity are left for the map.
+fn parse_entities(i: &str) -> IResult<(Vec<Entity>, MapFormat)> {
+    map(
+        many1(map(
+            tuple((take_comment_lines, between_line_bracket(parse_entity))),
+            |(comments, (entity, format))| {
+                (
+                    Entity {
+                        comments: Some(comments),
+                        ..entity
+                    },
+                    format,
+                )
+            },
+        )),
+        |entities| {
+            let (entities, formats): (Vec<Entity>, Vec<MapFormat>) = entities.into_iter().unzip();
+
+            (entities, merge_formats(formats))
+        },
+    )(i)
 }
 
-fn parse_map(i: &str) -> IResult<Map> {
+fn parse_map(i: &str) -> IResult<(Map, MapFormat)> {
     map(
-        all_consuming(tuple((opt(take_tb_header), parse_entities))),
-        |(tb_header, entities)| Map {
-            tb_header,
-            entities,
+        all_consuming(tuple((
+            opt(take_tb_header),
+            parse_entities,
+            take_comment_lines,
+        ))),
+        |(tb_header, (entities, format), trailing_comments)| {
+            (
+                Map {
+                    tb_header,
+                    entities,
+                    trailing_comments,
+                },
+                format,
+            )
         },
     )(i)
 }
@@ -377,7 +792,7 @@ a
 }
 ";
 
-        let (_, a) = parse_brushes(i).unwrap();
+        let (_, (a, _)) = parse_brushes(i).unwrap();
         assert_eq!(a.len(), 2);
         assert_eq!(a[0].planes[0].p1, DVec3::new(-120., -136., 144.));
         assert_eq!(a[0].planes[0].texture_name, "NULL");
@@ -394,7 +809,7 @@ a
 \"origin\" \"-80 -88 60\"
 }";
 
-        let (rest, a) = parse_entities(i).unwrap();
+        let (rest, (a, _)) = parse_entities(i).unwrap();
         assert_eq!(rest, "");
         assert_eq!(a.len(), 1);
 
@@ -405,6 +820,63 @@ a
         assert_eq!(ent.attributes.get("origin").unwrap(), "-80 -88 60");
     }
 
+    #[test]
+    fn number_format() {
+        assert_eq!(format_number(0.), "0");
+        assert_eq!(format_number(-16.5), "-16.5");
+        assert_eq!(format_number(1e-5), "1e-05");
+        assert_eq!(format_number(-2.5e-12), "-2.5e-12");
+        assert_eq!(format_number(1e16), "1e+16");
+        assert_eq!(format_number(1.5e200), "1.5e+200");
+    }
+
+    #[test]
+    fn trailing_comments() {
+        let i = "\
+// entity 0
+{
+\"classname\" \"worldspawn\"
+// brush 0
+{
+( -64 -64 -16 ) ( -64 -63 -16 ) ( -64 -64 -15 ) rock [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -64 -64 -15 ) ( -63 -64 -16 ) rock [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) rock [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 16 ) ( 64 65 16 ) ( 65 64 16 ) rock [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 16 ) ( 65 64 16 ) ( 64 64 17 ) rock [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 64 64 16 ) ( 64 64 17 ) ( 64 65 16 ) rock [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+}
+// after the last brush
+}
+// entity 1
+{
+\"classname\" \"info_player_start\"
+// after the attributes
+}
+// after the last entity
+// end
+";
+
+        let map = Map::from_text(i).unwrap();
+
+        assert_eq!(
+            map.entities[0].trailing_comments,
+            vec![" after the last brush"]
+        );
+        assert_eq!(
+            map.entities[1].trailing_comments,
+            vec![" after the attributes"]
+        );
+        assert_eq!(
+            map.trailing_comments,
+            vec![" after the last entity", " end"]
+        );
+
+        let out_path = std::env::temp_dir().join("gchimp_trailing_comments_out.map");
+        map.write(&out_path).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&out_path).unwrap(), i);
+    }
+
     #[test]
     fn comment_line_parse() {
         let i = "\
@@ -413,8 +885,9 @@ a
 // {} 
 // \"\"";
 
-        let (rest, _) = discard_comment_lines(i).unwrap();
+        let (rest, comments) = take_comment_lines(i).unwrap();
         assert!(rest.is_empty());
+        assert_eq!(comments.len(), 4);
     }
 
     #[test]
@@ -441,9 +914,10 @@ a
 
 ";
 
-        let (rest, a) = parse_map(i).unwrap();
+        let (rest, (a, format)) = parse_map(i).unwrap();
 
         assert!(rest.is_empty());
+        assert_eq!(format, MapFormat::Valve220);
         assert_eq!(a.entities.len(), 1);
 
         let ent = &a.entities[0];
@@ -483,6 +957,354 @@ a
         assert_eq!(i, j);
     }
 
+    #[test]
+    fn standard_format() {
+        let i = "\
+// Game: Quake
+// Format: Standard
+// entity 0
+{
+\"classname\" \"worldspawn\"
+{
+( -64 -64 -16 ) ( -64 -63 -16 ) ( -64 -64 -15 ) rock 0 0 0 1 1
+( -64 -64 -16 ) ( -64 -64 -15 ) ( -63 -64 -16 ) rock 0 0 0 1 1
+( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) rock 16 -8 90 0.5 0.5
+( 64 64 16 ) ( 64 65 16 ) ( 65 64 16 ) rock 0 0 0 1 1
+( 64 64 16 ) ( 65 64 16 ) ( 64 64 17 ) rock 0 0 0 1 1
+( 64 64 16 ) ( 64 64 17 ) ( 64 65 16 ) rock 0 0 0 1 1
+}
+}
+";
+
+        let map = Map::from_text(i).unwrap();
+
+        let worldspawn = &map.entities[0];
+        assert_eq!(worldspawn.attributes.get("mapversion").unwrap(), "220");
+        assert_eq!(map.tb_header.as_ref().unwrap()[1], " Format: Valve");
+
+        let plane = &worldspawn.brushes.as_ref().unwrap()[0].planes[2];
+        assert_eq!(plane.u, DVec4::new(0., 1., 0., 16.));
+        assert_eq!(plane.v, DVec4::new(1., 0., 0., -8.));
+        assert_eq!(plane.u_scale, 0.5);
+
+        let out_path = std::env::temp_dir().join("gchimp_standard_out.map");
+
+        map.write_as(&out_path, MapFormat::Standard).unwrap();
+
+        let text = std::fs::read_to_string(&out_path).unwrap();
+        assert!(text.contains("// Format: Standard"));
+        assert!(text.contains("rock 16 -8 90 0.5 0.5"));
+        assert!(!text.contains("mapversion"));
+        assert!(!text.contains('['));
+
+        let standard = Map::from_file(&out_path).unwrap();
+        assert_eq!(map, standard);
+    }
+
+    #[test]
+    fn valve220_without_mapversion() {
+        let i = "\
+{
+\"classname\" \"worldspawn\"
+{
+( -64 -64 -16 ) ( -64 -63 -16 ) ( -64 -64 -15 ) rock [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -64 -64 -15 ) ( -63 -64 -16 ) rock [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) rock [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 16 ) ( 64 65 16 ) ( 65 64 16 ) rock [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 16 ) ( 65 64 16 ) ( 64 64 17 ) rock [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 64 64 16 ) ( 64 64 17 ) ( 64 65 16 ) rock [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+}
+}
+";
+
+        // nothing was converted so there is nothing to mark
+        let map = Map::from_text(i).unwrap();
+
+        assert!(!map.entities[0].attributes.contains_key("mapversion"));
+    }
+
+    #[test]
+    fn trenchbroom_round_trip() {
+        let i = "\
+// Game: Half-Life
+// Format: Valve
+// entity 0
+{
+\"mapversion\" \"220\"
+\"wad\" \"halflife.wad\"
+\"classname\" \"worldspawn\"
+\"_tb_textures\" \"textures\"
+// brush 0
+{
+( -64 -64 -16 ) ( -64 -63 -16 ) ( -64 -64 -15 ) rock [ 0 -1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -64 -64 -15 ) ( -63 -64 -16 ) rock [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) rock [ -1 0 0 2.220446049250313e-16 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 16 ) ( 64 65 16 ) ( 65 64 16 ) rock [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 16 ) ( 65 64 16 ) ( 64 64 17 ) rock [ -1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 64 64 16 ) ( 64 64 17 ) ( 64 65 16 ) rock [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 0.5 0.5
+}
+}
+// entity 1
+// the second layer
+{
+\"classname\" \"func_group\"
+\"_tb_type\" \"_tb_layer\"
+\"_tb_name\" \"Details\"
+\"_tb_id\" \"2\"
+\"_tb_layer_sort\" \"0\"
+}
+// entity 2
+{
+\"origin\" \"0 0 32\"
+\"classname\" \"info_player_start\"
+\"_tb_layer\" \"2\"
+}
+";
+
+        let map = Map::from_text(i).unwrap();
+
+        let keys = map.entities[2].attributes.keys().collect::<Vec<_>>();
+        assert_eq!(keys, ["origin", "classname", "_tb_layer"]);
+        assert_eq!(
+            map.entities[1].comments.as_ref().unwrap(),
+            &[" entity 1", " the second layer"]
+        );
+
+        let out_path = std::env::temp_dir().join("gchimp_trenchbroom_out.map");
+        map.write(&out_path).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&out_path).unwrap(), i);
+
+        // numbering follows the new order
+        let mut map = map;
+        map.entities.remove(1);
+        map.write(&out_path).unwrap();
+
+        let text = std::fs::read_to_string(&out_path).unwrap();
+        assert!(text.contains("// entity 1\n{\n\"origin\""));
+        assert!(!text.contains("the second layer"));
+    }
+
+    /// Worldspawn, a layer, a group in the layer and entities in both.
+    fn editor_test_map() -> Map {
+        let cube = |min: i32, max: i32, texture: &str| {
+            format!(
+                "\
+{{
+( {min} {min} {min} ) ( {min} {max} {min} ) ( {min} {min} {max} ) {texture} [ 0 -1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( {min} {min} {min} ) ( {min} {min} {max} ) ( {max} {min} {min} ) {texture} [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( {min} {min} {min} ) ( {max} {min} {min} ) ( {min} {max} {min} ) {texture} [ -1 0 0 16 ] [ 0 -1 0 -8 ] 0 0.5 2
+( {max} {max} {max} ) ( {max} {max} {min} ) ( {max} {min} {max} ) {texture} [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( {max} {max} {max} ) ( {min} {max} {max} ) ( {max} {max} {min} ) {texture} [ -1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( {max} {max} {max} ) ( {max} {min} {max} ) ( {min} {max} {max} ) {texture} [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+}}
+"
+            )
+        };
+
+        let text = format!(
+            "\
+{{
+\"classname\" \"worldspawn\"
+\"wad\" \"halflife.wad\"
+\"mapversion\" \"220\"
+{}}}
+{{
+\"classname\" \"func_group\"
+\"_tb_type\" \"_tb_layer\"
+\"_tb_name\" \"Details\"
+\"_tb_id\" \"1\"
+\"_tb_layer_sort\" \"0\"
+\"_tb_layer_hidden\" \"1\"
+{}}}
+{{
+\"classname\" \"func_group\"
+\"_tb_type\" \"_tb_group\"
+\"_tb_name\" \"Group\"
+\"_tb_id\" \"2\"
+\"_tb_layer\" \"1\"
+{}}}
+{{
+\"classname\" \"func_wall\"
+\"rendermode\" \"4\"
+\"_tb_group\" \"2\"
+{}}}
+{{
+\"classname\" \"info_player_start\"
+\"angles\" \"0 90 0\"
+\"spawnflags\" \"1\"
+\"origin\" \"-32 64 36\"
+\"_tb_layer\" \"1\"
+}}
+",
+            cube(-64, -32, "world"),
+            cube(0, 16, "layer"),
+            cube(32, 48, "group"),
+            cube(64, 128, "{fence"),
+        );
+
+        let mut map = Map::from_text(&text).unwrap();
+
+        map.tb_header = None;
+        map.entities.iter_mut().for_each(|entity| {
+            entity.comments = None;
+            entity
+                .brushes
+                .iter_mut()
+                .flatten()
+                .for_each(|brush| brush.comments = None);
+        });
+
+        map
+    }
+
+    #[test]
+    fn rmf_round_trip() {
+        let map = editor_test_map();
+
+        let bytes = map.to_rmf_bytes().unwrap();
+        assert_eq!(&bytes[4..7], b"RMF");
+
+        let rmf = Map::from_rmf_bytes(&bytes).unwrap();
+        assert_eq!(map, rmf);
+
+        // writing again gives the same bytes
+        assert_eq!(rmf.to_rmf_bytes().unwrap(), bytes);
+
+        let out_path = std::env::temp_dir().join("gchimp_rmf_out.rmf");
+        map.write(&out_path).unwrap();
+        assert_eq!(Map::from_file(&out_path).unwrap(), map);
+    }
+
+    #[test]
+    fn jmf_round_trip() {
+        let map = editor_test_map();
+
+        let bytes = map.to_jmf_bytes().unwrap();
+        assert_eq!(&bytes[..4], b"JHMF");
+
+        let jmf = Map::from_jmf_bytes(&bytes).unwrap();
+
+        // plane points come from vertices
+        for (entity, jmf_entity) in map.entities.iter().zip(&jmf.entities) {
+            assert_eq!(entity.attributes, jmf_entity.attributes);
+
+            for (brush, jmf_brush) in entity
+                .brushes
+                .iter()
+                .flatten()
+                .zip(jmf_entity.brushes.iter().flatten())
+            {
+                for (plane, jmf_plane) in brush.planes.iter().zip(&jmf_brush.planes) {
+                    assert_eq!(plane.normal(), jmf_plane.normal());
+                    assert_eq!(
+                        plane.normal().dot(plane.p1),
+                        jmf_plane.normal().dot(jmf_plane.p1)
+                    );
+                    assert_eq!(plane.texture_name, jmf_plane.texture_name);
+                    assert_eq!((plane.u, plane.v), (jmf_plane.u, jmf_plane.v));
+                    assert_eq!(
+                        (plane.u_scale, plane.v_scale),
+                        (jmf_plane.u_scale, jmf_plane.v_scale)
+                    );
+                }
+            }
+        }
+
+        assert_eq!(map.entities.len(), jmf.entities.len());
+        assert_eq!(jmf.to_jmf_bytes().unwrap(), bytes);
+    }
+
+    /// Checks a map read from the hand-made editor fixtures.
+    fn check_editor_box_map(map: &Map) {
+        let find = |classname: &str| {
+            map.entities
+                .iter()
+                .find(|entity| entity.attributes.get("classname").unwrap() == classname)
+                .unwrap()
+        };
+
+        // box brushes have axis aligned planes facing away from the center
+        let check_box = |brush: &Brush, texture_name: &str| {
+            assert_eq!(brush.planes.len(), 6);
+
+            let center = brush
+                .planes
+                .iter()
+                .map(|plane| plane.p1 + plane.p2 + plane.p3)
+                .sum::<DVec3>()
+                / 18.;
+
+            brush.planes.iter().for_each(|plane| {
+                let normal = plane.normal();
+
+                assert_eq!(normal.abs().max_element(), 1.);
+                assert!(normal.dot(plane.p1 - center) > 0.);
+                assert_eq!(plane.texture_name, texture_name);
+                assert_eq!((plane.u_scale, plane.v_scale), (1., 1.));
+            });
+        };
+
+        let worldspawn = &map.entities[0];
+        assert_eq!(
+            worldspawn.attributes.get("wad").unwrap(),
+            "\\half-life\\valve\\halflife.wad"
+        );
+
+        let world_brushes = worldspawn.brushes.as_ref().unwrap();
+        assert_eq!(world_brushes.len(), 1);
+        check_box(&world_brushes[0], "CRETE1_FLR");
+
+        let group = map
+            .entities
+            .iter()
+            .find(|entity| {
+                entity
+                    .attributes
+                    .get("_tb_type")
+                    .is_some_and(|t| t == "_tb_group")
+            })
+            .unwrap();
+        let group_brushes = group.brushes.as_ref().unwrap();
+        assert_eq!(group_brushes.len(), 2);
+        group_brushes
+            .iter()
+            .for_each(|brush| check_box(brush, "CRATE01"));
+
+        let func_wall = find("func_wall");
+        assert_eq!(func_wall.attributes.get("rendermode").unwrap(), "4");
+        check_box(&func_wall.brushes.as_ref().unwrap()[0], "{FENCE");
+
+        let player_start = find("info_player_start");
+        assert_eq!(player_start.attributes.get("origin").unwrap(), "0 -128 36");
+        assert_eq!(player_start.attributes.get("angles").unwrap(), "0 90 0");
+        assert!(player_start.brushes.is_none());
+    }
+
+    #[test]
+    fn read_hammer_rmf() {
+        let map = Map::from_file("./test/hammer_box.rmf").unwrap();
+
+        check_editor_box_map(&map);
+    }
+
+    #[test]
+    fn read_jack_jmf() {
+        let (map, warnings) = Map::from_file_with_warnings("./test/jack_box_121.jmf").unwrap();
+
+        check_editor_box_map(&map);
+        assert!(warnings.is_empty());
+
+        // the brush with a patch is skipped
+        let (map, warnings) = Map::from_file_with_warnings("./test/jack_box_122.jmf").unwrap();
+
+        check_editor_box_map(&map);
+        assert_eq!(
+            warnings,
+            vec!["Skipped 1 brushes with patches because GoldSrc does not have curves"]
+        );
+    }
+
     #[test]
     fn fail_read() {
         let file = Map::from_file("./dunkin/do.nut");
@@ -0,0 +1,569 @@
+//! Hammer 3.x RMF version 2.2
+//!
+//! Based of specification from this webpage: https://twhl.info/wiki/page/Specification%3A_RMF
+use byte_writer::ByteWriter;
+use glam::DVec3;
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::{fail, map, verify},
+    multi::{length_count, length_data},
+    number::complete::{le_f32, le_i32, le_u32, le_u8},
+    sequence::tuple,
+};
+
+use crate::{
+    editor::{
+        bytes_to_string, f32_to_f64, parse_color, parse_fixed_string, parse_vec3,
+        write_fixed_string, write_vec3, EditorBrush, EditorEntity, EditorMap, IResult, Visgroup,
+        DEFAULT_OBJECT_COLOR,
+    },
+    winding::brush_windings,
+    Brush, BrushPlane,
+};
+
+const RMF_VERSION: f32 = 2.2;
+const RMF_TEXTURE_NAME_LENGTH: usize = 256;
+const RMF_NAME_LENGTH: usize = 128;
+const DOCINFO_CAMERA_VERSION: f32 = 0.2;
+
+enum Object {
+    Solid {
+        visgroup: i32,
+        brush: Brush,
+    },
+    Entity {
+        visgroup: i32,
+        data: EntityData,
+        solids: Vec<Object>,
+        origin: DVec3,
+    },
+    Group {
+        visgroup: i32,
+        children: Vec<Object>,
+    },
+}
+
+struct EntityData {
+    classname: String,
+    spawnflags: i32,
+    key_values: Vec<(String, String)>,
+}
+
+struct PathNode {
+    position: DVec3,
+    index: i32,
+    name: String,
+    key_values: Vec<(String, String)>,
+}
+
+struct Path {
+    name: String,
+    classname: String,
+    /// 0 is one way, 1 is circular and 2 is ping-pong.
+    direction: i32,
+    nodes: Vec<PathNode>,
+}
+
+struct Rmf {
+    visgroups: Vec<Visgroup>,
+    world_objects: Vec<Object>,
+    world_data: EntityData,
+    paths: Vec<Path>,
+}
+
+/// String with its length and null in front.
+fn parse_nstring(i: &[u8]) -> IResult<String> {
+    map(length_data(le_u8), bytes_to_string)(i)
+}
+
+fn parse_key_values(i: &[u8]) -> IResult<Vec<(String, String)>> {
+    length_count(le_u32, tuple((parse_nstring, parse_nstring)))(i)
+}
+
+fn parse_visgroup(i: &[u8]) -> IResult<Visgroup> {
+    map(
+        tuple((
+            parse_fixed_string(RMF_NAME_LENGTH),
+            parse_color,
+            take(1usize),
+            le_i32,
+            le_u8,
+            take(3usize),
+        )),
+        |(name, color, _, id, visible, _)| Visgroup {
+            id,
+            name,
+            color,
+            visible: visible != 0,
+        },
+    )(i)
+}
+
+fn parse_face(i: &[u8]) -> IResult<BrushPlane> {
+    map(
+        tuple((
+            parse_fixed_string(RMF_TEXTURE_NAME_LENGTH),
+            take(4usize),
+            parse_vec3,
+            le_f32,
+            parse_vec3,
+            le_f32,
+            le_f32,
+            le_f32,
+            le_f32,
+            take(16usize),
+            // vertices are made again from the planes
+            length_count(le_u32, parse_vec3),
+            parse_vec3,
+            parse_vec3,
+            parse_vec3,
+        )),
+        |(
+            texture_name,
+            _,
+            u,
+            u_offset,
+            v,
+            v_offset,
+            rotation,
+            u_scale,
+            v_scale,
+            _,
+            _,
+            p1,
+            p2,
+            p3,
+        )| BrushPlane {
+            p1,
+            p2,
+            p3,
+            texture_name,
+            u: u.extend(f32_to_f64(u_offset)),
+            v: v.extend(f32_to_f64(v_offset)),
+            rotation: f32_to_f64(rotation),
+            u_scale: f32_to_f64(u_scale),
+            v_scale: f32_to_f64(v_scale),
+        },
+    )(i)
+}
+
+fn parse_entity_data(i: &[u8]) -> IResult<EntityData> {
+    map(
+        tuple((
+            parse_nstring,
+            take(4usize),
+            le_i32,
+            parse_key_values,
+            take(12usize),
+        )),
+        |(classname, _, spawnflags, key_values, _)| EntityData {
+            classname,
+            spawnflags,
+            key_values,
+        },
+    )(i)
+}
+
+fn parse_object(i: &[u8]) -> IResult<Object> {
+    let (i, kind) = parse_nstring(i)?;
+    let (i, (visgroup, _color, children)) =
+        tuple((le_i32, parse_color, length_count(le_u32, parse_object)))(i)?;
+
+    match kind.as_str() {
+        "CMapSolid" => {
+            let (i, planes) = length_count(le_u32, parse_face)(i)?;
+
+            Ok((
+                i,
+                Object::Solid {
+                    visgroup,
+                    brush: Brush {
+                        planes,
+                        comments: None,
+                    },
+                },
+            ))
+        }
+        "CMapEntity" => {
+            let (i, (data, _, origin, _)) =
+                tuple((parse_entity_data, take(2usize), parse_vec3, take(4usize)))(i)?;
+
+            Ok((
+                i,
+                Object::Entity {
+                    visgroup,
+                    data,
+                    solids: children,
+                    origin,
+                },
+            ))
+        }
+        "CMapGroup" => Ok((i, Object::Group { visgroup, children })),
+        _ => fail(i),
+    }
+}
+
+fn parse_path_node(i: &[u8]) -> IResult<PathNode> {
+    map(
+        tuple((
+            parse_vec3,
+            le_i32,
+            parse_fixed_string(RMF_NAME_LENGTH),
+            parse_key_values,
+        )),
+        |(position, index, name, key_values)| PathNode {
+            position,
+            index,
+            name,
+            key_values,
+        },
+    )(i)
+}
+
+fn parse_path(i: &[u8]) -> IResult<Path> {
+    map(
+        tuple((
+            parse_fixed_string(RMF_NAME_LENGTH),
+            parse_fixed_string(RMF_NAME_LENGTH),
+            le_i32,
+            length_count(le_u32, parse_path_node),
+        )),
+        |(name, classname, direction, nodes)| Path {
+            name,
+            classname,
+            direction,
+            nodes,
+        },
+    )(i)
+}
+
+// Camera data after the world is not needed.
+fn parse_rmf(i: &[u8]) -> IResult<Rmf> {
+    map(
+        tuple((
+            le_f32,
+            tag("RMF"),
+            length_count(le_u32, parse_visgroup),
+            verify(parse_nstring, |kind: &str| kind == "CMapWorld"),
+            le_i32,
+            parse_color,
+            length_count(le_u32, parse_object),
+            parse_entity_data,
+            length_count(le_u32, parse_path),
+        )),
+        |(_, _, visgroups, _, _, _, world_objects, world_data, paths)| Rmf {
+            visgroups,
+            world_objects,
+            world_data,
+            paths,
+        },
+    )(i)
+}
+
+fn visgroup_id(visgroup: i32) -> Option<i32> {
+    (visgroup > 0).then_some(visgroup)
+}
+
+fn add_objects(editor: &mut EditorMap, objects: Vec<Object>, group: Option<i32>) {
+    for object in objects {
+        match object {
+            Object::Solid { visgroup, brush } => editor.entities[0].brushes.push(EditorBrush {
+                brush,
+                group,
+                visgroup: visgroup_id(visgroup),
+            }),
+            Object::Entity {
+                visgroup,
+                data,
+                solids,
+                origin,
+            } => {
+                let visgroup = visgroup_id(visgroup);
+
+                let brushes = solids
+                    .into_iter()
+                    .filter_map(|solid| match solid {
+                        Object::Solid { brush, .. } => Some(EditorBrush {
+                            brush,
+                            group,
+                            visgroup,
+                        }),
+                        _ => None,
+                    })
+                    .collect::<Vec<EditorBrush>>();
+
+                let attributes = EditorMap::entity_attributes(
+                    data.classname,
+                    data.key_values,
+                    data.spawnflags,
+                    brushes.is_empty().then_some(origin),
+                );
+
+                editor.entities.push(EditorEntity {
+                    attributes,
+                    brushes,
+                    group,
+                    visgroup,
+                });
+            }
+            Object::Group { visgroup, children } => {
+                let id = editor.groups.len() as i32 + 1;
+
+                editor.groups.push(crate::editor::Group {
+                    id,
+                    parent: group,
+                    visgroup: visgroup_id(visgroup),
+                });
+
+                add_objects(editor, children, Some(id));
+            }
+        }
+    }
+}
+
+/// Hammer turns path nodes into entities targeting the next node when exporting.
+fn path_entities(path: Path) -> Vec<EditorEntity> {
+    let names = path
+        .nodes
+        .iter()
+        .map(|node| {
+            if node.name.is_empty() {
+                format!("{}{:02}", path.name, node.index)
+            } else {
+                node.name.clone()
+            }
+        })
+        .collect::<Vec<String>>();
+
+    path.nodes
+        .into_iter()
+        .enumerate()
+        .map(|(index, node)| {
+            let mut key_values = vec![("targetname".to_string(), names[index].clone())];
+
+            let next = if index + 1 < names.len() {
+                Some(&names[index + 1])
+            } else if path.direction == 1 {
+                names.first()
+            } else {
+                None
+            };
+
+            if let Some(next) = next {
+                key_values.push(("target".to_string(), next.clone()));
+            }
+
+            key_values.extend(node.key_values);
+
+            EditorEntity {
+                attributes: EditorMap::entity_attributes(
+                    path.classname.clone(),
+                    key_values,
+                    0,
+                    Some(node.position),
+                ),
+                brushes: vec![],
+                group: None,
+                visgroup: None,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn rmf_to_editor_map(bytes: &[u8]) -> eyre::Result<EditorMap> {
+    let version = bytes
+        .get(..4)
+        .map(|version| f32::from_le_bytes([version[0], version[1], version[2], version[3]]))
+        .ok_or_else(|| eyre::eyre!("File is too small to be RMF."))?;
+
+    if version != RMF_VERSION {
+        return Err(eyre::eyre!(
+            "RMF version {} is not supported. Only version {} is supported.",
+            version,
+            RMF_VERSION
+        ));
+    }
+
+    let (_, rmf) =
+        parse_rmf(bytes).map_err(|err| eyre::eyre!("Cannot parse RMF: {}", err.to_string()))?;
+
+    let mut res = EditorMap::new(EditorMap::entity_attributes(
+        rmf.world_data.classname,
+        rmf.world_data.key_values,
+        rmf.world_data.spawnflags,
+        None,
+    ));
+
+    res.visgroups = rmf.visgroups;
+
+    add_objects(&mut res, rmf.world_objects, None);
+
+    for path in rmf.paths {
+        res.entities.extend(path_entities(path));
+    }
+
+    Ok(res)
+}
+
+fn write_nstring(writer: &mut ByteWriter, s: &str) -> eyre::Result<()> {
+    let length = u8::try_from(s.len() + 1)
+        .map_err(|_| eyre::eyre!("\"{}\" is longer than 254 characters.", s))?;
+
+    writer.append_u8(length);
+    writer.append_string(s);
+    writer.append_u8(0);
+
+    Ok(())
+}
+
+fn write_object_header(writer: &mut ByteWriter, kind: &str, visgroup: Option<i32>) {
+    // kind is always short
+    write_nstring(writer, kind).unwrap();
+    writer.append_i32(visgroup.unwrap_or(0));
+    writer.append_u8_slice(&DEFAULT_OBJECT_COLOR);
+}
+
+fn write_solid(writer: &mut ByteWriter, brush: &Brush, visgroup: Option<i32>) -> eyre::Result<()> {
+    write_object_header(writer, "CMapSolid", visgroup);
+
+    // no children
+    writer.append_i32(0);
+    writer.append_i32(brush.planes.len() as i32);
+
+    for (plane, winding) in brush.planes.iter().zip(brush_windings(brush)) {
+        write_fixed_string(writer, &plane.texture_name, RMF_TEXTURE_NAME_LENGTH)?;
+        writer.append_f32(0.);
+        write_vec3(writer, plane.u.truncate());
+        writer.append_f32(plane.u.w as f32);
+        write_vec3(writer, plane.v.truncate());
+        writer.append_f32(plane.v.w as f32);
+        writer.append_f32(plane.rotation as f32);
+        writer.append_f32(plane.u_scale as f32);
+        writer.append_f32(plane.v_scale as f32);
+        writer.append_u8_slice(&[0; 16]);
+
+        writer.append_i32(winding.len() as i32);
+        winding
+            .into_iter()
+            .for_each(|vertex| write_vec3(writer, vertex));
+
+        write_vec3(writer, plane.p1);
+        write_vec3(writer, plane.p2);
+        write_vec3(writer, plane.p3);
+    }
+
+    Ok(())
+}
+
+fn write_entity_data(writer: &mut ByteWriter, entity: &EditorEntity) -> eyre::Result<()> {
+    write_nstring(writer, entity.classname())?;
+    writer.append_u8_slice(&[0; 4]);
+    writer.append_i32(entity.spawnflags());
+
+    let key_values = entity
+        .attributes
+        .iter()
+        .filter(|(key, _)| !entity.is_special_key(key))
+        .collect::<Vec<_>>();
+
+    writer.append_i32(key_values.len() as i32);
+
+    for (key, value) in key_values {
+        write_nstring(writer, key)?;
+        write_nstring(writer, value)?;
+    }
+
+    writer.append_u8_slice(&[0; 12]);
+
+    Ok(())
+}
+
+fn write_children(
+    writer: &mut ByteWriter,
+    editor: &EditorMap,
+    group: Option<i32>,
+) -> eyre::Result<()> {
+    let solids = editor.entities[0]
+        .brushes
+        .iter()
+        .filter(|brush| brush.group == group)
+        .collect::<Vec<_>>();
+    let entities = editor.entities[1..]
+        .iter()
+        .filter(|entity| entity.group == group)
+        .collect::<Vec<_>>();
+    let groups = editor
+        .groups
+        .iter()
+        .filter(|g| g.parent == group)
+        .collect::<Vec<_>>();
+
+    writer.append_i32((solids.len() + groups.len() + entities.len()) as i32);
+
+    for solid in solids {
+        write_solid(writer, &solid.brush, solid.visgroup)?;
+    }
+
+    for group in groups {
+        write_object_header(writer, "CMapGroup", group.visgroup);
+        write_children(writer, editor, Some(group.id))?;
+    }
+
+    for entity in entities {
+        write_object_header(writer, "CMapEntity", entity.visgroup);
+
+        writer.append_i32(entity.brushes.len() as i32);
+
+        for brush in &entity.brushes {
+            write_solid(writer, &brush.brush, entity.visgroup)?;
+        }
+
+        write_entity_data(writer, entity)?;
+
+        writer.append_u8_slice(&[0; 2]);
+        write_vec3(
+            writer,
+            if entity.is_point_entity() {
+                entity.origin()
+            } else {
+                DVec3::ZERO
+            },
+        );
+        writer.append_u8_slice(&[0; 4]);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn editor_map_to_rmf(editor: &EditorMap) -> eyre::Result<Vec<u8>> {
+    let mut writer = ByteWriter::new();
+
+    writer.append_f32(RMF_VERSION);
+    writer.append_string("RMF");
+
+    writer.append_i32(editor.visgroups.len() as i32);
+
+    for visgroup in &editor.visgroups {
+        write_fixed_string(&mut writer, &visgroup.name, RMF_NAME_LENGTH)?;
+        writer.append_u8_slice(&visgroup.color);
+        writer.append_u8(0);
+        writer.append_i32(visgroup.id);
+        writer.append_u8(visgroup.visible as u8);
+        writer.append_u8_slice(&[0; 3]);
+    }
+
+    write_object_header(&mut writer, "CMapWorld", None);
+    write_children(&mut writer, editor, None)?;
+    write_entity_data(&mut writer, &editor.entities[0])?;
+
+    // no paths because they are already entities
+    writer.append_i32(0);
+
+    write_fixed_string(&mut writer, "DOCINFO", 8)?;
+    writer.append_f32(DOCINFO_CAMERA_VERSION);
+    // no active camera
+    writer.append_i32(-1);
+    writer.append_i32(0);
+
+    Ok(writer.data)
+}
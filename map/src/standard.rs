@@ -0,0 +1,226 @@
+//! Quake "standard" texture alignment, used by Quake and Worldcraft/Hammer 3.x before Valve220.
+//!
+//! Standard planes only have offset, rotation and scale. Texture axes come from the axis closest to the plane normal.
+use glam::{DVec3, DVec4};
+
+use crate::BrushPlane;
+
+/// Plane normal, texture U axis and texture V axis.
+///
+/// Floor, ceiling, west wall, east wall, south wall, north wall. Same order as Quake tools.
+const BASE_AXES: [[DVec3; 3]; 6] = [
+    [DVec3::Z, DVec3::X, DVec3::NEG_Y],
+    [DVec3::NEG_Z, DVec3::X, DVec3::NEG_Y],
+    [DVec3::X, DVec3::Y, DVec3::NEG_Z],
+    [DVec3::NEG_X, DVec3::Y, DVec3::NEG_Z],
+    [DVec3::Y, DVec3::X, DVec3::NEG_Z],
+    [DVec3::NEG_Y, DVec3::X, DVec3::NEG_Z],
+];
+
+/// Texture alignment in standard format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StandardTexture {
+    pub x_offset: f64,
+    pub y_offset: f64,
+    pub rotation: f64,
+    pub x_scale: f64,
+    pub y_scale: f64,
+}
+
+/// U and V axes before rotation. Ties go to the earlier axis, like Quake tools.
+pub fn base_texture_axes(normal: DVec3) -> (DVec3, DVec3) {
+    let mut best_dot = 0.;
+    let mut best_axes = 0;
+
+    for (index, [axis, _, _]) in BASE_AXES.iter().enumerate() {
+        let dot = normal.dot(*axis);
+
+        if dot > best_dot {
+            best_dot = dot;
+            best_axes = index;
+        }
+    }
+
+    (BASE_AXES[best_axes][1], BASE_AXES[best_axes][2])
+}
+
+/// Index of the first non zero component
+fn axis_index(axis: DVec3) -> usize {
+    if axis.x != 0. {
+        0
+    } else if axis.y != 0. {
+        1
+    } else {
+        2
+    }
+}
+
+fn rotation_sin_cos(rotation: f64) -> (f64, f64) {
+    // exact values for right angles
+    match rotation {
+        0. => (0., 1.),
+        90. => (1., 0.),
+        180. => (0., -1.),
+        270. => (-1., 0.),
+        _ => rotation.to_radians().sin_cos(),
+    }
+}
+
+fn rotate_axis(axis: DVec3, (sv, tv): (usize, usize), (sin, cos): (f64, f64)) -> DVec3 {
+    let mut res = axis;
+
+    res[sv] = cos * axis[sv] - sin * axis[tv];
+    res[tv] = sin * axis[sv] + cos * axis[tv];
+
+    res
+}
+
+impl BrushPlane {
+    /// Normal of the plane, same winding as Quake tools.
+    pub fn normal(&self) -> DVec3 {
+        (self.p1 - self.p2)
+            .cross(self.p3 - self.p2)
+            .normalize_or_zero()
+    }
+
+    /// Plane from standard format with texture axes computed.
+    pub fn from_standard(
+        (p1, p2, p3): (DVec3, DVec3, DVec3),
+        texture_name: impl Into<String>,
+        texture: StandardTexture,
+    ) -> Self {
+        let mut res = Self {
+            p1,
+            p2,
+            p3,
+            texture_name: texture_name.into(),
+            u: DVec4::ZERO,
+            v: DVec4::ZERO,
+            rotation: texture.rotation,
+            // scale 0 means 1
+            u_scale: if texture.x_scale == 0. {
+                1.
+            } else {
+                texture.x_scale
+            },
+            v_scale: if texture.y_scale == 0. {
+                1.
+            } else {
+                texture.y_scale
+            },
+        };
+
+        let (u_axis, v_axis) = base_texture_axes(res.normal());
+        let indices = (axis_index(u_axis), axis_index(v_axis));
+        let sin_cos = rotation_sin_cos(texture.rotation);
+
+        res.u = rotate_axis(u_axis, indices, sin_cos).extend(texture.x_offset);
+        res.v = rotate_axis(v_axis, indices, sin_cos).extend(texture.y_offset);
+
+        res
+    }
+
+    /// Texture alignment in standard format.
+    ///
+    /// Standard format cannot have skewed texture or axes off the closest base plane so those are lost.
+    pub fn to_standard(&self) -> StandardTexture {
+        let (u_axis, v_axis) = base_texture_axes(self.normal());
+        let (sv, tv) = (axis_index(u_axis), axis_index(v_axis));
+
+        let u = self.u.truncate();
+        let v = self.v.truncate();
+
+        // rotation is from base U to U on the base plane
+        let u_length = (u[sv] * u[sv] + u[tv] * u[tv]).sqrt();
+
+        let rotation = if u_length < f64::EPSILON {
+            0.
+        } else {
+            (u[tv].atan2(u[sv]) - u_axis[tv].atan2(u_axis[sv])).to_degrees()
+        };
+
+        // no 359.99999
+        let rotation = ((rotation * 1e6).round() / 1e6).rem_euclid(360.);
+
+        let sin_cos = rotation_sin_cos(rotation);
+        let rotated_v = rotate_axis(v_axis, (sv, tv), sin_cos);
+        let v_length = v[sv] * rotated_v[sv] + v[tv] * rotated_v[tv];
+
+        let scale = |scale: f64, length: f64| {
+            if length.abs() < f64::EPSILON {
+                scale
+            } else {
+                scale / length
+            }
+        };
+
+        StandardTexture {
+            x_offset: self.u.w,
+            y_offset: self.v.w,
+            rotation,
+            x_scale: scale(self.u_scale, u_length),
+            y_scale: scale(self.v_scale, v_length),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn floor() -> (DVec3, DVec3, DVec3) {
+        (
+            DVec3::new(0., 0., 0.),
+            DVec3::new(0., 1., 0.),
+            DVec3::new(1., 0., 0.),
+        )
+    }
+
+    #[test]
+    fn standard_to_valve220() {
+        let texture = StandardTexture {
+            x_offset: 16.,
+            y_offset: -8.,
+            rotation: 90.,
+            x_scale: 0.5,
+            y_scale: 0.,
+        };
+
+        let plane = BrushPlane::from_standard(floor(), "floor", texture);
+
+        assert_eq!(plane.normal(), DVec3::Z);
+        assert_eq!(plane.u, DVec4::new(0., 1., 0., 16.));
+        assert_eq!(plane.v, DVec4::new(1., 0., 0., -8.));
+        assert_eq!(plane.u_scale, 0.5);
+        assert_eq!(plane.v_scale, 1.);
+    }
+
+    #[test]
+    fn standard_round_trip() {
+        let wall = (
+            DVec3::new(0., 0., 0.),
+            DVec3::new(0., 0., 1.),
+            DVec3::new(0., 1., 0.),
+        );
+
+        for points in [floor(), wall] {
+            for rotation in [0., 30., 90., 135., 270.] {
+                let texture = StandardTexture {
+                    x_offset: 3.,
+                    y_offset: 5.,
+                    rotation,
+                    x_scale: 2.,
+                    y_scale: -0.25,
+                };
+
+                let res = BrushPlane::from_standard(points, "a", texture).to_standard();
+
+                assert!((res.rotation - rotation).abs() < 1e-6);
+                assert!((res.x_scale - 2.).abs() < 1e-9);
+                assert!((res.y_scale + 0.25).abs() < 1e-9);
+                assert_eq!(res.x_offset, 3.);
+                assert_eq!(res.y_offset, 5.);
+            }
+        }
+    }
+}
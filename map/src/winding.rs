@@ -0,0 +1,149 @@
+//! Face vertices from brush planes.
+use glam::DVec3;
+
+use crate::{Brush, BrushPlane};
+
+/// Big enough to cover the whole map before clipping.
+const BASE_WINDING_SIZE: f64 = 131072.;
+/// Points this close to a plane are on the plane.
+pub const ON_PLANE_EPSILON: f64 = 0.001;
+
+/// Outward normal and distance from origin.
+pub fn plane_equation(plane: &BrushPlane) -> (DVec3, f64) {
+    let normal = plane.normal();
+
+    (normal, normal.dot(plane.p1))
+}
+
+/// A big square on the plane, clockwise when looking at the front of the plane like the plane points.
+fn base_winding(normal: DVec3, distance: f64) -> Vec<DVec3> {
+    // pick the axis that is the least aligned with the normal
+    let up = if normal.z.abs() > normal.x.abs() && normal.z.abs() > normal.y.abs() {
+        DVec3::X
+    } else {
+        DVec3::Z
+    };
+
+    let right = up.cross(normal).normalize() * BASE_WINDING_SIZE;
+    let up = normal.cross(right).normalize() * BASE_WINDING_SIZE;
+    let origin = normal * distance;
+
+    vec![
+        origin - right + up,
+        origin + right + up,
+        origin + right - up,
+        origin - right - up,
+    ]
+}
+
+/// Keeps the part of the winding behind the plane.
+fn clip_winding(winding: &[DVec3], normal: DVec3, distance: f64) -> Vec<DVec3> {
+    let mut res = vec![];
+
+    for (index, &current) in winding.iter().enumerate() {
+        let next = winding[(index + 1) % winding.len()];
+
+        let current_distance = normal.dot(current) - distance;
+        let next_distance = normal.dot(next) - distance;
+
+        if current_distance <= ON_PLANE_EPSILON {
+            res.push(current);
+        }
+
+        if (current_distance > ON_PLANE_EPSILON && next_distance < -ON_PLANE_EPSILON)
+            || (current_distance < -ON_PLANE_EPSILON && next_distance > ON_PLANE_EPSILON)
+        {
+            let fraction = current_distance / (current_distance - next_distance);
+
+            res.push(current + (next - current) * fraction);
+        }
+    }
+
+    res
+}
+
+/// Vertices of one face of the brush, clockwise when looking at the face from outside.
+///
+/// Returns None if the plane does not exist or is clipped away.
+pub fn brush_plane_winding(brush: &Brush, plane_index: usize) -> Option<Vec<DVec3>> {
+    let (normal, distance) = plane_equation(brush.planes.get(plane_index)?);
+
+    if normal == DVec3::ZERO {
+        return None;
+    }
+
+    let winding = brush
+        .planes
+        .iter()
+        .enumerate()
+        .filter(|&(other_index, _)| other_index != plane_index)
+        .fold(base_winding(normal, distance), |winding, (_, other)| {
+            if winding.is_empty() {
+                winding
+            } else {
+                let (other_normal, other_distance) = plane_equation(other);
+
+                clip_winding(&winding, other_normal, other_distance)
+            }
+        });
+
+    // clipped vertices are close to integers most of the time
+    let mut winding = winding
+        .into_iter()
+        .map(|vertex| {
+            let rounded = vertex.round();
+
+            if rounded.distance(vertex) < ON_PLANE_EPSILON {
+                rounded
+            } else {
+                vertex
+            }
+        })
+        .collect::<Vec<DVec3>>();
+
+    winding.dedup_by(|a, b| a.distance(*b) < ON_PLANE_EPSILON);
+
+    if winding.len() > 1 && winding[0].distance(winding[winding.len() - 1]) < ON_PLANE_EPSILON {
+        winding.pop();
+    }
+
+    if winding.len() < 3 {
+        None
+    } else {
+        Some(winding)
+    }
+}
+
+/// Vertices of every face of the brush in the same order as the planes.
+///
+/// Faces that are clipped away have no vertices.
+pub fn brush_windings(brush: &Brush) -> Vec<Vec<DVec3>> {
+    (0..brush.planes.len())
+        .map(|plane_index| brush_plane_winding(brush, plane_index).unwrap_or_default())
+        .collect()
+}
+
+/// Three plane points from face vertices with the normal pointing the same way as `normal`.
+pub(crate) fn plane_points_from_winding(
+    winding: &[DVec3],
+    normal: DVec3,
+) -> Option<(DVec3, DVec3, DVec3)> {
+    // the biggest triangle from the first vertex is the most precise
+    let (p1, p2, p3) = (1..winding.len().saturating_sub(1))
+        .map(|index| (winding[0], winding[index], winding[index + 1]))
+        .max_by(|a, b| triangle_area(*a).total_cmp(&triangle_area(*b)))?;
+
+    if triangle_area((p1, p2, p3)) < ON_PLANE_EPSILON {
+        return None;
+    }
+
+    if (p1 - p2).cross(p3 - p2).dot(normal) < 0. {
+        Some((p3, p2, p1))
+    } else {
+        Some((p1, p2, p3))
+    }
+}
+
+fn triangle_area((p1, p2, p3): (DVec3, DVec3, DVec3)) -> f64 {
+    (p1 - p2).cross(p3 - p2).length() / 2.
+}
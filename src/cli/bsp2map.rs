@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use bsp::Bsp;
+
+use crate::modules::bsp2map::bsp2map;
+
+use super::{Cli, CliRes};
+
+pub struct Bsp2Map;
+impl Cli for Bsp2Map {
+    fn name(&self) -> &'static str {
+        "bsp2map"
+    }
+
+    // In, Out
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.is_empty() {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let bsp_path = PathBuf::from(&args[0]);
+        let out_path = args
+            .get(1)
+            .map(PathBuf::from)
+            .unwrap_or(bsp_path.with_extension("map"));
+
+        let bsp = match Bsp::from_file(&bsp_path) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("Cannot open BSP: {}", err);
+                return CliRes::Err;
+            }
+        };
+
+        let map = match bsp2map(&bsp) {
+            Ok(map) => map,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        };
+
+        if let Err(err) = map.write(&out_path) {
+            println!("Cannot write map: {}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Decompiles BSP into Valve220 .map
+
+Every solid, liquid and sky leaf becomes a brush.
+Output is next to the BSP if not specified.
+
+<.bsp> [output .map]
+"
+        )
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use bsp::Bsp;
+
+use crate::modules::bsp2smd::{bsp_model_to_obj, bsp_model_to_smd};
+
+use super::{Cli, CliRes};
+
+pub struct Bsp2Smd;
+impl Cli for Bsp2Smd {
+    fn name(&self) -> &'static str {
+        "bsp2smd"
+    }
+
+    // In, Out, Model
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() < 2 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let out_path = PathBuf::from(&args[1]);
+
+        let model_index = match args
+            .get(2)
+            .map(|s| s.trim_start_matches('*').parse::<usize>())
+        {
+            Some(Ok(index)) => index,
+            Some(Err(_)) => {
+                println!("Cannot parse model index.");
+                self.cli_help();
+                return CliRes::Err;
+            }
+            None => 0,
+        };
+
+        let bsp = match Bsp::from_file(&args[0]) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("Cannot open BSP: {}", err);
+                return CliRes::Err;
+            }
+        };
+
+        let is_obj = out_path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("obj"));
+
+        let res = if is_obj {
+            bsp_model_to_obj(&bsp, model_index, &out_path)
+        } else {
+            bsp_model_to_smd(&bsp, model_index)
+                .and_then(|smd| smd.write(&out_path).map_err(|err| err.into()))
+        };
+
+        if let Err(err) = res {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Converts BSP brush model geometry into .smd or .obj
+
+Output format is chosen from the output extension.
+Model index is 0 for worldspawn, or the number in \"model\" \"*N\".
+
+<.bsp> <output .smd/.obj> [model index]
+"
+        )
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use bsp::Bsp;
+
+use crate::modules::bsp_lightmap::{export_lightmap_atlas, import_lightmap_atlas};
+
+use super::{Cli, CliRes};
+
+pub struct BspLightmap;
+impl Cli for BspLightmap {
+    fn name(&self) -> &'static str {
+        "bsp_lightmap"
+    }
+
+    // export: In, Out
+    // import: In, Atlas, Out
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() < 2 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let bsp_path = PathBuf::from(&args[1]);
+
+        let mut bsp = match Bsp::from_file(&bsp_path) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("Cannot open BSP: {}", err);
+                return CliRes::Err;
+            }
+        };
+
+        let res = match args[0].as_str() {
+            "export" => {
+                let out_path = args
+                    .get(2)
+                    .map(PathBuf::from)
+                    .unwrap_or(bsp_path.with_extension("png"));
+
+                export_lightmap_atlas(&bsp)
+                    .and_then(|img| img.save(out_path).map_err(|err| err.into()))
+            }
+            "import" => {
+                let Some(atlas_path) = args.get(2) else {
+                    self.cli_help();
+                    return CliRes::Err;
+                };
+
+                let out_path = args.get(3).map(PathBuf::from).unwrap_or(bsp_path.clone());
+
+                image::open(atlas_path)
+                    .map_err(|err| err.into())
+                    .and_then(|img| import_lightmap_atlas(&mut bsp, &img.into_rgb8()))
+                    .and_then(|_| bsp.write_to_file(out_path))
+            }
+            _ => {
+                self.cli_help();
+                return CliRes::Err;
+            }
+        };
+
+        if let Err(err) = res {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Exports BSP lightmaps into an atlas and imports them back
+
+Every light style of a face is next to each other in the atlas.
+Import needs the atlas to be exported from the same BSP.
+Import overwrites the BSP if output is not specified.
+
+export <.bsp> [output .png]
+import <.bsp> <atlas .png> [output .bsp]
+"
+        )
+    }
+}
@@ -0,0 +1,60 @@
+use bsp::Bsp;
+
+use super::{Cli, CliRes};
+
+pub struct BspLimits;
+impl Cli for BspLimits {
+    fn name(&self) -> &'static str {
+        "bsp_limits"
+    }
+
+    // In
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.is_empty() {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let bsp = match Bsp::from_file(&args[0]) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("Cannot open BSP: {}", err);
+                return CliRes::Err;
+            }
+        };
+
+        let report = bsp.limits_report();
+
+        print!("{}", report);
+
+        let warnings = report.warnings();
+
+        if !warnings.is_empty() {
+            println!();
+            warnings
+                .iter()
+                .for_each(|warning| println!("Warning: {}", warning));
+        }
+
+        if report.has_errors() {
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Reports BSP lump usage against GoldSrc limits
+
+Usage at {}% or more is warned. Anything over the limit is an error.
+
+<.bsp>
+",
+            bsp::LIMIT_WARNING_PERCENTAGE
+        )
+    }
+}
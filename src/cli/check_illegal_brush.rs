@@ -19,7 +19,11 @@ impl Cli for CheckIllegalBrush {
             return CliRes::Err;
         }
 
-        let map = Map::from_file(&args[0]).unwrap();
+        let (map, warnings) = Map::from_file_with_warnings(&args[0]).unwrap();
+
+        warnings
+            .iter()
+            .for_each(|warning| println!("Warning: {}", warning));
 
         check_illegal_brush(&map);
 
@@ -31,7 +35,7 @@ impl Cli for CheckIllegalBrush {
             "\
 Map compiler does not tell you enough info about illegal brushes. Here it does.
 
-<.map>
+<.map/.rmf/.jmf>
 "
         )
     }
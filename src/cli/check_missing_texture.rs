@@ -20,7 +20,7 @@ struct CheckMissingTextureCli {
 enum Commands {
     #[command(id = "missing_texture")]
     MissingTexture {
-        /// Sets path to .map
+        /// Sets path to .map, .rmf or .jmf
         #[arg(short, long)]
         map: PathBuf,
         /// Sets path(s) to individual .wad
@@ -41,7 +41,11 @@ impl Cli for CheckMissingTexture {
         let a = CheckMissingTextureCli::parse();
         let Commands::MissingTexture { map, wads } = a.command;
 
-        let map = Map::from_file(map).unwrap();
+        let (map, warnings) = Map::from_file_with_warnings(map).unwrap();
+
+        warnings
+            .iter()
+            .for_each(|warning| println!("Warning: {}", warning));
         let wads = wads
             .iter()
             .map(|wad| Wad::from_file(wad).unwrap())
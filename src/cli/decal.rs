@@ -0,0 +1,119 @@
+use super::*;
+
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::modules::decal::{add_decal_to_wad_file, spray_wad_from_file, DecalOptions};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct DecalCliStruct {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    Decal {
+        #[command(subcommand)]
+        action: Action,
+    },
+}
+
+#[derive(Debug, Args)]
+struct DecalArgs {
+    /// Decal color as "r g b". Average color of the image if not set
+    #[arg(short, long, num_args = 3)]
+    color: Option<Vec<u8>>,
+    /// Keeps the image colors instead of one color
+    #[arg(long)]
+    colored: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+    /// Makes tempdecal.wad
+    Spray {
+        image: PathBuf,
+        /// Output WAD, tempdecal.wad next to the image if not set
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        decal: DecalArgs,
+    },
+    /// Adds a decal to a WAD such as decals.wad
+    Add {
+        image: PathBuf,
+        /// WAD to add to. It is created if it does not exist
+        #[arg(short, long)]
+        wad: PathBuf,
+        /// Decal name, image file name if not set
+        #[arg(short, long)]
+        name: Option<String>,
+        #[command(flatten)]
+        decal: DecalArgs,
+    },
+}
+
+impl From<DecalArgs> for DecalOptions {
+    fn from(value: DecalArgs) -> Self {
+        Self {
+            color: value.color.map(|color| [color[0], color[1], color[2]]),
+            colored: value.colored,
+        }
+    }
+}
+
+pub struct Decal;
+impl Cli for Decal {
+    fn name(&self) -> &'static str {
+        "decal"
+    }
+
+    fn cli(&self) -> CliRes {
+        let cli = DecalCliStruct::parse();
+
+        let Commands::Decal { action } = cli.command;
+
+        let res = match action {
+            Action::Spray {
+                image,
+                output,
+                decal,
+            } => {
+                let output = output.unwrap_or(image.with_file_name("tempdecal.wad"));
+
+                spray_wad_from_file(&image, &decal.into(), output)
+            }
+            Action::Add {
+                image,
+                wad,
+                name,
+                decal,
+            } => add_decal_to_wad_file(&image, name.as_deref(), &decal.into(), wad),
+        };
+
+        if let Err(err) = res {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Makes spray and decal textures
+
+Decal is one color and the image is how opaque it is.
+Images with transparency use alpha, otherwise darker pixels are more opaque.
+
+decal spray <image> [-o tempdecal.wad] [-c r g b] [--colored]
+decal add <image> -w <decals.wad> [-n name] [-c r g b] [--colored]
+"
+        )
+    }
+}
@@ -32,7 +32,11 @@ impl Cli for LightScale {
             })
             .collect();
 
-        let mut map = Map::from_file(&args[0]).unwrap();
+        let (mut map, warnings) = Map::from_file_with_warnings(&args[0]).unwrap();
+
+        warnings
+            .iter()
+            .for_each(|warning| println!("Warning: {}", warning));
 
         light_scale(&mut map, (scalars[0], scalars[1], scalars[2], scalars[3]));
 
@@ -48,7 +52,7 @@ light entity _light values scaling.
 
 Multiplying every number in _light field with given scalars
 
-<.map> <output .map> <R> <G> <B> <Brightness>
+<.map/.rmf/.jmf> <output .map/.rmf/.jmf> <R> <G> <B> <Brightness>
 "
         )
     }
@@ -69,7 +69,7 @@ impl Cli for Map2MdlCli {
 Converts {} into model. 
 Better read the documentation before you do what you do.
 
-./gchimp map2mdl <.map>
+./gchimp map2mdl <.map/.rmf/.jmf>
 ",
             MAP2MDL_ENTITY_NAME
         )
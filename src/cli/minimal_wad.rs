@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use crate::modules::minimal_wad::minimal_wad_from_file;
+
+use super::{Cli, CliRes};
+
+pub struct MinimalWad;
+impl Cli for MinimalWad {
+    fn name(&self) -> &'static str {
+        "minimal_wad"
+    }
+
+    // In, Out, Search folders
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.is_empty() {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let path = PathBuf::from(&args[0]);
+        let out_path = args.get(1).map(PathBuf::from).unwrap_or_else(|| {
+            let file_stem = path.file_stem().unwrap().to_str().unwrap();
+
+            path.with_file_name(format!("{}_textures.wad", file_stem))
+        });
+        let search_dirs = args.iter().skip(2).map(PathBuf::from).collect::<Vec<_>>();
+
+        match minimal_wad_from_file(&path, &search_dirs, &out_path) {
+            Ok(missing) => {
+                for message in &missing {
+                    println!("{}", message);
+                }
+
+                println!("Written {}", out_path.display());
+            }
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Makes a WAD with only the textures used by a map
+
+Textures are from the WADs in worldspawn \"wad\" key.
+WADs that are not found are looked up by file name next to the map and in the search folders.
+Textures embedded in a BSP are not included.
+Output is <map name>_textures.wad if not specified.
+
+<.map/.bsp> [output .wad] [WAD search folder]...
+"
+        )
+    }
+}
@@ -1,14 +1,25 @@
 use map::Map;
 
+mod bsp2map;
+mod bsp2smd;
+mod bsp_lightmap;
+mod bsp_limits;
 mod check_illegal_brush;
 mod check_missing_texture;
 mod custom_script;
+mod decal;
 mod light_scale;
 mod map2mdl;
+mod minimal_wad;
+mod ripent;
 mod rotate_prop_static;
 mod s2g;
 mod split_model;
 mod texture_scale;
+mod vmf2map;
+mod wadconvert;
+mod wadfont;
+mod wadtool;
 
 pub enum CliRes {
     NoCli,
@@ -59,6 +70,17 @@ pub fn cli() -> CliRes {
         &check_illegal_brush::CheckIllegalBrush,
         &map2mdl::Map2MdlCli,
         &split_model::SplitModel,
+        &bsp2map::Bsp2Map,
+        &vmf2map::Vmf2Map,
+        &bsp2smd::Bsp2Smd,
+        &bsp_lightmap::BspLightmap,
+        &bsp_limits::BspLimits,
+        &ripent::Ripent,
+        &wadfont::WadFont,
+        &wadconvert::WadConvert,
+        &wadtool::WadTool,
+        &minimal_wad::MinimalWad,
+        &decal::Decal,
     ];
 
     let help = || {
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use crate::modules::ripent::{ripent_export, ripent_import};
+
+use super::{Cli, CliRes};
+
+pub struct Ripent;
+impl Cli for Ripent {
+    fn name(&self) -> &'static str {
+        "ripent"
+    }
+
+    // export: In, Out
+    // import: In, Ent, Out
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() < 2 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let bsp_path = PathBuf::from(&args[1]);
+        let ent_path = args
+            .get(2)
+            .map(PathBuf::from)
+            .unwrap_or(bsp_path.with_extension("ent"));
+
+        let res = match args[0].as_str() {
+            "export" => ripent_export(&bsp_path, &ent_path),
+            "import" => {
+                let out_path = args.get(3).map(PathBuf::from).unwrap_or(bsp_path.clone());
+
+                ripent_import(&bsp_path, &ent_path, &out_path)
+            }
+            _ => {
+                self.cli_help();
+                return CliRes::Err;
+            }
+        };
+
+        if let Err(err) = res {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Exports BSP entity lump into .ent file and imports it back
+
+Entity and key order are kept.
+Import checks the syntax and brush model references \"*N\" before writing.
+Import overwrites the BSP if output is not specified.
+
+export <.bsp> [output .ent]
+import <.bsp> [.ent] [output .bsp]
+"
+        )
+    }
+}
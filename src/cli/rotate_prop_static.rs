@@ -17,7 +17,11 @@ impl Cli for RotatePropStatic {
             return CliRes::Err;
         }
 
-        let mut map = Map::from_file(&args[0]).unwrap();
+        let (mut map, warnings) = Map::from_file_with_warnings(&args[0]).unwrap();
+
+        warnings
+            .iter()
+            .for_each(|warning| println!("Warning: {}", warning));
 
         rotate_prop_static(&mut map, if args.len() > 2 { Some(&args[2]) } else { None });
 
@@ -32,7 +36,7 @@ impl Cli for RotatePropStatic {
 Rotate Source prop_static by +90 Z in (Y Z X)
 Can optionally change prop_static to a different entity through classname
 
-<.map> <output .map> <new prop_static classname>
+<.map/.rmf/.jmf> <output .map/.rmf/.jmf> <new prop_static classname>
 "
         )
     }
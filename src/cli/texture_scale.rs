@@ -25,7 +25,11 @@ impl Cli for TextureScale {
             return CliRes::Err;
         }
 
-        let mut map = Map::from_file(&args[0]).unwrap();
+        let (mut map, warnings) = Map::from_file_with_warnings(&args[0]).unwrap();
+
+        warnings
+            .iter()
+            .for_each(|warning| println!("Warning: {}", warning));
 
         texture_scale(&mut map, scalar.unwrap());
 
@@ -43,7 +47,7 @@ impl Cli for TextureScale {
             "\
 Texture scale
 
-<.map> <output .map> <scalar>
+<.map/.rmf/.jmf> <output .map/.rmf/.jmf> <scalar>
 "
         )
     }
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use crate::modules::vmf2map::{vmf2map, Vmf};
+
+use super::{Cli, CliRes};
+
+pub struct Vmf2Map;
+impl Cli for Vmf2Map {
+    fn name(&self) -> &'static str {
+        "vmf2map"
+    }
+
+    // In, Out
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.is_empty() {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let vmf_path = PathBuf::from(&args[0]);
+        let out_path = args
+            .get(1)
+            .map(PathBuf::from)
+            .unwrap_or(vmf_path.with_extension("map"));
+
+        let vmf = match Vmf::from_file(&vmf_path) {
+            Ok(vmf) => vmf,
+            Err(err) => {
+                println!("Cannot open VMF: {}", err);
+                return CliRes::Err;
+            }
+        };
+
+        let map = match vmf2map(&vmf) {
+            Ok(map) => map,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        };
+
+        if let Err(err) = map.write(&out_path) {
+            println!("Cannot write map: {}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Converts Source .vmf into Valve220 .map
+
+Entities are renamed to GoldSrc ones and props become cycler_sprite.
+Displacements become one brush per triangle.
+Material paths are reduced to WAD texture names.
+Output is next to the VMF if not specified.
+
+<.vmf> [output .map]
+"
+        )
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use wad::{types::Palette, QUAKE_PALETTE};
+
+use crate::modules::waddy::Waddy;
+
+use super::{Cli, CliRes};
+
+pub struct WadConvert;
+impl Cli for WadConvert {
+    fn name(&self) -> &'static str {
+        "wadconvert"
+    }
+
+    // Format, In, Out, Palette
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() < 2 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let wad_path = PathBuf::from(&args[1]);
+        let out_path = args.get(2).map(PathBuf::from).unwrap_or(wad_path.clone());
+
+        let mut waddy = match Waddy::from_wad_file(&wad_path) {
+            Ok(waddy) => waddy,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        };
+
+        let res = match args[0].as_str() {
+            "wad2" => {
+                let palette = match args.get(3) {
+                    Some(palette_path) => {
+                        match std::fs::read(palette_path)
+                            .map_err(|err| err.into())
+                            .and_then(|bytes| Palette::from_lmp(&bytes))
+                        {
+                            Ok(palette) => palette.get_bytes().clone(),
+                            Err(err) => {
+                                println!("{}", err);
+                                return CliRes::Err;
+                            }
+                        }
+                    }
+                    None => QUAKE_PALETTE.to_vec(),
+                };
+
+                waddy.convert_to_wad2(&palette)
+            }
+            "wad3" => waddy.convert_to_wad3(),
+            _ => {
+                self.cli_help();
+                return CliRes::Err;
+            }
+        };
+
+        if let Err(err) = res.and_then(|_| waddy.save_to_file(&out_path)) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Converts WAD3 (Half-Life) to WAD2 (Quake) and back
+
+WAD2 textures are changed to the Quake palette, or palette.lmp if specified.
+Fonts cannot be in WAD2.
+Overwrites the WAD if output is not specified.
+
+wad2 <.wad> [output .wad] [palette.lmp]
+wad3 <.wad> [output .wad]
+"
+        )
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use crate::modules::wadfont::wadfont;
+
+use super::{Cli, CliRes};
+
+pub struct WadFont;
+impl Cli for WadFont {
+    fn name(&self) -> &'static str {
+        "wadfont"
+    }
+
+    // In, Size, Out, Name
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() < 2 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let input_path = PathBuf::from(&args[0]);
+
+        let Ok(pixel_height) = args[1].parse::<f32>() else {
+            println!("Cannot parse font size");
+            return CliRes::Err;
+        };
+
+        let output_path = args
+            .get(2)
+            .map(PathBuf::from)
+            .unwrap_or(input_path.with_extension("wad"));
+        let entry_name = args.get(3).map(|s| s.as_str()).unwrap_or("font");
+
+        if let Err(err) = wadfont(&input_path, pixel_height, entry_name, &output_path) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Makes a WAD with a font entry from a .ttf/.otf file or a glyph sheet image
+
+Font size is the row height in pixels. It is ignored for glyph sheets.
+Glyph sheet has 16 rows of 16 characters. Character widths are measured from the visible pixels.
+
+<.ttf/.otf/image> <font size> [output .wad] [entry name]
+"
+        )
+    }
+}
@@ -0,0 +1,114 @@
+use super::*;
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use wad::types::Wad;
+
+use crate::modules::wadtool::{diff_wads, merge_wads, CollisionPolicy, MergeOptions};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct WadToolCliStruct {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    Wadtool {
+        #[command(subcommand)]
+        action: Action,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+    /// Merges WADs into one, earlier WADs come first
+    Merge {
+        /// Output WAD
+        #[arg(short, long)]
+        output: PathBuf,
+        /// What to do with textures with the same name but different pixels: first, last or rename
+        #[arg(short, long, default_value = "first")]
+        collision: String,
+        /// Removes textures with the same pixels as an earlier texture
+        #[arg(short, long)]
+        dedupe: bool,
+        /// WADs to merge
+        #[arg(required = true, num_args = 1..)]
+        wads: Vec<PathBuf>,
+    },
+    /// Lists added, removed and changed textures
+    Diff {
+        /// Old WAD
+        old: PathBuf,
+        /// New WAD
+        new: PathBuf,
+    },
+}
+
+pub struct WadTool;
+impl Cli for WadTool {
+    fn name(&self) -> &'static str {
+        "wadtool"
+    }
+
+    fn cli(&self) -> CliRes {
+        let cli = WadToolCliStruct::parse();
+
+        let Commands::Wadtool { action } = cli.command;
+
+        let res = match action {
+            Action::Merge {
+                output,
+                collision,
+                dedupe,
+                wads,
+            } => CollisionPolicy::try_from(collision.as_str()).and_then(|collision| {
+                let wads = wads
+                    .iter()
+                    .map(Wad::from_file)
+                    .collect::<eyre::Result<Vec<Wad>>>()?;
+
+                let (wad, report) = merge_wads(&wads, &MergeOptions { collision, dedupe })?;
+
+                print!("{}", report);
+                println!("{} textures", wad.entries.len());
+
+                wad.write_to_file(output)
+            }),
+            Action::Diff { old, new } => Wad::from_file(old).and_then(|old| {
+                let new = Wad::from_file(new)?;
+                let diff = diff_wads(&old, &new);
+
+                if diff.is_empty() {
+                    println!("No difference");
+                } else {
+                    print!("{}", diff);
+                }
+
+                Ok(())
+            }),
+        };
+
+        if let Err(err) = res {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Merges WADs and compares them
+
+wadtool merge -o <output .wad> [-c first|last|rename] [-d] <.wad>...
+wadtool diff <old .wad> <new .wad>
+"
+        )
+    }
+}
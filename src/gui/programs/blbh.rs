@@ -8,7 +8,10 @@ use eframe::egui;
 
 use crate::{
     config::Config,
-    gui::{utils::preview_file_being_dropped, TabProgram},
+    gui::{
+        utils::{preview_file_being_dropped, quantize_options_ui},
+        TabProgram,
+    },
     modules::blender_lightmap_baker_helper::{
         blender_lightmap_baker_helper, BLBHOptions, BLBH, BLBH_DEFAULT_UV_SHRINK_FACTOR,
     },
@@ -150,6 +153,8 @@ impl TabProgram for BLBHGui {
                 .on_hover_text("Flags every texture with flat shade");
         });
 
+        quantize_options_ui(ui, "BLBH", &mut self.options.quantize_options);
+
         ui.horizontal(|ui| {
             ui.label("UV Shrink");
             // only check value if lost focus
@@ -12,6 +12,8 @@ use crate::{
     modules::map2mdl::{entity::MAP2MDL_ENTITY_NAME, Map2Mdl, Map2MdlOptions, Map2MdlSync},
 };
 
+const SUPPORTED_MAP_FORMATS: &[&str] = &["map", "rmf", "jmf"];
+
 pub struct Map2MdlGui {
     app_config: Config,
     map: String,
@@ -110,12 +112,15 @@ impl TabProgram for Map2MdlGui {
                     ui.label("Map:");
                     ui.add_enabled_ui(!self.use_entity, |ui| {
                         ui.add(
-                            egui::TextEdit::singleline(&mut self.map).hint_text("Choose .map file"),
+                            egui::TextEdit::singleline(&mut self.map)
+                                .hint_text("Choose .map, .rmf or .jmf file"),
                         );
                     });
                     if ui.button("Add").clicked() {
                         if let Some(path) = rfd::FileDialog::new().pick_file() {
-                            if path.extension().is_some_and(|ext| ext == "map") {
+                            if path.extension().is_some_and(|ext| {
+                                SUPPORTED_MAP_FORMATS.contains(&ext.to_str().unwrap_or_default())
+                            }) {
                                 self.map = path.display().to_string();
                                 self.use_entity = false;
                             }
@@ -184,7 +189,11 @@ impl TabProgram for Map2MdlGui {
             if i.raw.dropped_files.len() == 1 {
                 let item = i.raw.dropped_files[0].clone();
                 if let Some(item) = item.path {
-                    if item.is_file() && item.extension().is_some_and(|ext| ext == "map") {
+                    if item.is_file()
+                        && item.extension().is_some_and(|ext| {
+                            SUPPORTED_MAP_FORMATS.contains(&ext.to_str().unwrap_or_default())
+                        })
+                    {
                         self.map = item.to_str().unwrap().to_string();
                         self.use_entity = false;
                     }
@@ -11,7 +11,7 @@ use crate::{
     config::Config,
     gui::{
         constants::{PROGRAM_HEIGHT, PROGRAM_WIDTH},
-        utils::preview_file_being_dropped,
+        utils::{preview_file_being_dropped, quantize_options_ui},
         TabProgram,
     },
     include_image,
@@ -83,6 +83,7 @@ impl SkyModGui {
             convert_texture,
             flatshade,
             output_name,
+            quantize_options,
         } = self.options.clone();
 
         let handle = thread::spawn(move || {
@@ -100,7 +101,8 @@ impl SkyModGui {
                 .studiomdl(studiomdl.as_str())
                 .output_name(output_name.as_str())
                 .convert_texture(convert_texture)
-                .flat_shade(flatshade);
+                .flat_shade(flatshade)
+                .quantize_options(quantize_options);
 
             #[cfg(target_os = "linux")]
             skymod.wineprefix(wineprefix);
@@ -213,6 +215,8 @@ Recommeded to leave it checked for uniformly lit texture.",
             ui.text_edit_singleline(&mut self.options.output_name)
         });
 
+        quantize_options_ui(ui, "SkyMod", &mut self.options.quantize_options);
+
         ui.separator();
 
         ui.horizontal(|ui| {
@@ -7,7 +7,10 @@ use eframe::egui;
 use egui_extras::{Column, TableBuilder};
 
 use crate::{
-    gui::{utils::preview_file_being_dropped, TabProgram},
+    gui::{
+        utils::{preview_file_being_dropped, quantize_options_ui},
+        TabProgram,
+    },
     modules::textile::{TexTileBuilder, TexTileOptions, TexTileSync},
 };
 
@@ -61,6 +64,7 @@ impl TexTileGui {
             is_transparent,
             transparent_threshold,
             change_name,
+            quantize_options,
         } = self.options;
 
         let items = self.items.clone();
@@ -77,6 +81,7 @@ impl TexTileGui {
                 .tiling_scalar(tiling_scalar)
                 .transparent(is_transparent)
                 .transparent_threshold(transparent_threshold)
+                .quantize_options(quantize_options)
                 .sync(sync.clone());
 
             *sync.done().lock().unwrap() = false;
@@ -140,6 +145,8 @@ Append \"_<scalar>\" if tiling",
                     );
             });
 
+        quantize_options_ui(ui, "TexTile", &mut self.options.quantize_options);
+
         ui.separator();
         ui.horizontal(|ui| {
             let is_done = *self.sync.done().lock().unwrap();
@@ -6,18 +6,25 @@ use std::{
 use arboard::Clipboard;
 use eframe::egui::{self, Context, Modifiers, RichText, ScrollArea, Sense, Ui};
 use image::{ImageBuffer, RgbaImage};
-use wad::types::FileEntry;
+use wad::{
+    types::{Entry, FileEntry, Wad},
+    QUAKE_PALETTE,
+};
 
 use rayon::prelude::*;
 
 use crate::{
     gui::{
         constants::{PROGRAM_HEIGHT, PROGRAM_WIDTH},
-        utils::{display_image_viewport_from_texture, preview_file_being_dropped, WadImage},
+        utils::{
+            display_image_viewport_from_texture, preview_file_being_dropped, quantize_options_ui,
+            WadImage,
+        },
         TabProgram,
     },
     modules::waddy::Waddy,
     persistent_storage::PersistentStorage,
+    utils::img_stuffs::palette_from_path,
 };
 
 pub struct WaddyGui {
@@ -95,8 +102,38 @@ impl TextureTile {
     }
 }
 
+/// External BSP textures do not have image data so they are drawn with this color.
+const EXTERNAL_TEXTURE_COLOR: [u8; 3] = [64, 64, 64];
+
+fn texture_tile_from_entry(ui: &mut Ui, index: usize, entry: &Entry) -> Option<TextureTile> {
+    let texture_name = entry.directory_entry.texture_name.get_string();
+    let dimensions = entry.file_entry.dimensions();
+
+    let wad_image = match &entry.file_entry {
+        FileEntry::MipTex(miptex) if miptex.is_external() => WadImage::from_wad_image(
+            ui,
+            texture_name,
+            &vec![0u8; (miptex.width * miptex.height) as usize],
+            &[EXTERNAL_TEXTURE_COLOR],
+            dimensions,
+        ),
+        FileEntry::Raw(_) => return None,
+        // qpic and font are shown as they are
+        file_entry => WadImage::from_wad_image(
+            ui,
+            texture_name,
+            file_entry.image(),
+            file_entry.palette(),
+            dimensions,
+        ),
+    };
+
+    Some(TextureTile::new(index, wad_image))
+}
+
 const BASE_IMAGE_TILE_SIZE: f32 = 96.0;
 const SUPPORTED_TEXTURE_FORMATS: &[&str] = &["png", "jpeg", "jpg", "bmp", "vtf"];
+const SUPPORTED_PALETTE_FORMATS: &[&str] = &["lmp", "bmp"];
 
 const PERSISTENT_STORAGE_RECENTLY_USED_UPDATE_ERROR: &str =
     "cannot update recently used wad for Waddy";
@@ -202,6 +239,148 @@ impl WaddyGui {
                         ui.close_menu();
                     }
 
+                    if ui.button("Replace").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("All Files", SUPPORTED_TEXTURE_FORMATS)
+                            .pick_file()
+                        {
+                            // TODO TOAST
+                            if let Err(err) = self.instances[instance_index]
+                                .waddy
+                                .replace_texture_from_path(texture_tile_index, path)
+                            {
+                                println!("{}", err);
+                            } else {
+                                self.update_after_replace_image(
+                                    ui,
+                                    instance_index,
+                                    texture_tile_index,
+                                );
+                            }
+                        }
+
+                        ui.close_menu();
+                    }
+
+                    if ui
+                        .button("Replace (keep palette)")
+                        .on_hover_text("New image uses the colors of the current palette")
+                        .clicked()
+                    {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("All Files", SUPPORTED_TEXTURE_FORMATS)
+                            .pick_file()
+                        {
+                            // TODO TOAST
+                            if let Err(err) = self.instances[instance_index]
+                                .waddy
+                                .replace_texture_with_palette_from_path(
+                                    texture_tile_index,
+                                    path,
+                                    None,
+                                )
+                            {
+                                println!("{}", err);
+                            } else {
+                                self.update_after_replace_image(
+                                    ui,
+                                    instance_index,
+                                    texture_tile_index,
+                                );
+                            }
+                        }
+
+                        ui.close_menu();
+                    }
+
+                    if ui
+                        .button("Replace with palette")
+                        .on_hover_text("Pick an image and then a .lmp or indexed .bmp palette")
+                        .clicked()
+                    {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("All Files", SUPPORTED_TEXTURE_FORMATS)
+                            .pick_file()
+                        {
+                            if let Some(palette_path) = rfd::FileDialog::new()
+                                .add_filter("Palette", SUPPORTED_PALETTE_FORMATS)
+                                .pick_file()
+                            {
+                                // TODO TOAST
+                                if let Err(err) =
+                                    palette_from_path(palette_path).and_then(|palette| {
+                                        self.instances[instance_index]
+                                            .waddy
+                                            .replace_texture_with_palette_from_path(
+                                                texture_tile_index,
+                                                path,
+                                                Some(&palette),
+                                            )
+                                    })
+                                {
+                                    println!("{}", err);
+                                } else {
+                                    self.update_after_replace_image(
+                                        ui,
+                                        instance_index,
+                                        texture_tile_index,
+                                    );
+                                }
+                            }
+                        }
+
+                        ui.close_menu();
+                    }
+
+                    if self.instances[instance_index].texture_tiles[texture_tile_index]
+                        .name()
+                        .starts_with('!')
+                    {
+                        ui.menu_button("Water fog", |ui| {
+                            match self.instances[instance_index]
+                                .waddy
+                                .water_fog(texture_tile_index)
+                            {
+                                Ok(mut fog) => {
+                                    let mut changed = false;
+
+                                    ui.horizontal(|ui| {
+                                        ui.label("Color");
+                                        changed |=
+                                            ui.color_edit_button_srgb(&mut fog.color).changed();
+                                    });
+
+                                    ui.horizontal(|ui| {
+                                        ui.label("Density");
+                                        changed |= ui
+                                            .add(egui::DragValue::new(&mut fog.density))
+                                            .on_hover_text("Higher is thicker")
+                                            .changed();
+                                    });
+
+                                    if changed {
+                                        // TODO TOAST
+                                        if let Err(err) = self.instances[instance_index]
+                                            .waddy
+                                            .set_water_fog(texture_tile_index, fog)
+                                        {
+                                            println!("{}", err);
+                                        } else {
+                                            self.update_after_replace_image(
+                                                ui,
+                                                instance_index,
+                                                texture_tile_index,
+                                            );
+                                        }
+                                    }
+                                }
+                                Err(err) => {
+                                    ui.label(err.to_string());
+                                }
+                            }
+                        });
+                    }
+
                     // export when there's lots of selected or not
                     if self.instances[instance_index].selected.is_empty() {
                         if ui.button("Export").clicked() {
@@ -768,8 +947,12 @@ impl WaddyGui {
             to_delete.sort();
 
             to_delete.iter().rev().for_each(|&delete| {
-                self.instances[instance_index].texture_tiles.remove(delete);
-                self.instances[instance_index].waddy.remove_texture(delete);
+                // BSP textures used by faces cannot be removed
+                if let Err(err) = self.instances[instance_index].waddy.remove_texture(delete) {
+                    println!("{}", err);
+                } else {
+                    self.instances[instance_index].texture_tiles.remove(delete);
+                }
             });
 
             self.instances[instance_index].to_delete.clear();
@@ -825,31 +1008,50 @@ impl WaddyGui {
             .last()
             .unwrap();
 
-        let texture_name = new_entry.directory_entry.texture_name.get_string();
-        let dimensions = if let FileEntry::MipTex(miptex) = &new_entry.file_entry {
-            (miptex.width, miptex.height)
-        } else {
-            unreachable!()
-        };
-        let wad_image = if let FileEntry::MipTex(miptex) = &new_entry.file_entry {
-            WadImage::from_wad_image(
-                ui,
-                texture_name.clone(),
-                miptex.mip_images[0].data.get_bytes(),
-                miptex.palette.get_bytes(),
-                dimensions,
-            )
-        } else {
+        let Some(texture_tile) = texture_tile_from_entry(ui, instance_index, new_entry) else {
             unreachable!()
         };
 
         self.instances[instance_index]
             .texture_tiles
-            .push(TextureTile::new(instance_index, wad_image));
+            .push(texture_tile);
 
         self.instances[instance_index].is_changed = true;
     }
 
+    // call it right after replacing the image of a texture to update its tile
+    fn update_after_replace_image(
+        &mut self,
+        ui: &mut Ui,
+        instance_index: usize,
+        texture_tile_index: usize,
+    ) {
+        let entry = &self.instances[instance_index].waddy.wad().entries[texture_tile_index];
+
+        let Some(texture_tile) = texture_tile_from_entry(ui, texture_tile_index, entry) else {
+            unreachable!()
+        };
+
+        self.instances[instance_index].texture_tiles[texture_tile_index] = texture_tile;
+        self.instances[instance_index].is_changed = true;
+    }
+
+    // call it after textures are changed all at once, such as when BSP textures are externalized
+    fn update_all_texture_tiles(&mut self, ui: &mut Ui, instance_index: usize) {
+        let texture_tiles = self.instances[instance_index]
+            .waddy
+            .wad()
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| texture_tile_from_entry(ui, index, entry))
+            .collect::<Vec<TextureTile>>();
+
+        self.instances[instance_index].texture_tiles = texture_tiles;
+        self.instances[instance_index].selected.clear();
+        self.instances[instance_index].is_changed = true;
+    }
+
     // FIXME: it is ram guzzler
     fn start_waddy_instance(&mut self, ui: &mut Ui, path: Option<&Path>) -> eyre::Result<()> {
         // return is_changed here so that the user knows they need to save the file to have the file
@@ -859,7 +1061,8 @@ impl WaddyGui {
             if ext == "wad" {
                 (Waddy::from_wad_file(path)?, false)
             } else if ext == "bsp" {
-                (Waddy::from_bsp_file(path)?, true)
+                // BSP is saved back to itself
+                (Waddy::from_bsp_file(path)?, false)
             } else {
                 unreachable!()
             }
@@ -881,26 +1084,11 @@ impl WaddyGui {
             .entries
             .iter()
             .enumerate()
-            .filter_map(|(index, entry)| {
-                if let FileEntry::MipTex(miptex) = &entry.file_entry {
-                    let wad_image = WadImage::from_wad_image(
-                        ui,
-                        entry.directory_entry.texture_name.get_string(),
-                        miptex.mip_images[0].data.get_bytes(),
-                        miptex.palette.get_bytes(),
-                        (miptex.width, miptex.height),
-                    );
-
-                    return Some(TextureTile::new(index, wad_image));
-                    // None
-                }
-
-                None
-            })
+            .filter_map(|(index, entry)| texture_tile_from_entry(ui, index, entry))
             .collect::<Vec<TextureTile>>();
 
         self.instances.push(WaddyInstance {
-            path: path.map(|path| path.with_extension("wad")),
+            path: path.map(|path| path.to_path_buf()),
             waddy,
             texture_tiles,
             is_changed,
@@ -994,6 +1182,217 @@ impl WaddyGui {
                 ui.close_menu();
             }
 
+            // BSP can only have textures
+            if !self.instances[instance_index].waddy.is_bsp() {
+                ui.menu_button("Import as", |ui| {
+                    if ui
+                        .button("Picture (qpic)")
+                        .on_hover_text("HUD and menu images, not resized")
+                        .clicked()
+                    {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("All Files", SUPPORTED_TEXTURE_FORMATS)
+                            .pick_file()
+                        {
+                            // TODO TOAST
+                            if let Err(err) = self.instances[instance_index]
+                                .waddy
+                                .add_qpic_from_path(path)
+                            {
+                                println!("{}", err);
+                            } else {
+                                self.update_after_add_image(ui, instance_index);
+                            }
+                        }
+
+                        ui.close_menu();
+                    }
+
+                    if ui
+                        .button("Font")
+                        .on_hover_text("256 pixels wide image with 16x16 characters")
+                        .clicked()
+                    {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("All Files", SUPPORTED_TEXTURE_FORMATS)
+                            .pick_file()
+                        {
+                            // TODO TOAST
+                            if let Err(err) = self.instances[instance_index]
+                                .waddy
+                                .add_font_from_path(path)
+                            {
+                                println!("{}", err);
+                            } else {
+                                self.update_after_add_image(ui, instance_index);
+                            }
+                        }
+
+                        ui.close_menu();
+                    }
+
+                    if ui
+                        .button("Texture with palette")
+                        .on_hover_text("Pick an image and then a .lmp or indexed .bmp palette")
+                        .clicked()
+                    {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("All Files", SUPPORTED_TEXTURE_FORMATS)
+                            .pick_file()
+                        {
+                            if let Some(palette_path) = rfd::FileDialog::new()
+                                .add_filter("Palette", SUPPORTED_PALETTE_FORMATS)
+                                .pick_file()
+                            {
+                                // TODO TOAST
+                                if let Err(err) =
+                                    palette_from_path(palette_path).and_then(|palette| {
+                                        self.instances[instance_index]
+                                            .waddy
+                                            .add_texture_with_palette_from_path(path, &palette)
+                                    })
+                                {
+                                    println!("{}", err);
+                                } else {
+                                    self.update_after_add_image(ui, instance_index);
+                                }
+                            }
+                        }
+
+                        ui.close_menu();
+                    }
+
+                    for (label, alternate) in [
+                        ("Animated texture", false),
+                        ("Animated texture (alternate)", true),
+                    ] {
+                        if ui
+                            .button(label)
+                            .on_hover_text(
+                                "A GIF or numbered images such as lava0.png, lava1.png, ...",
+                            )
+                            .clicked()
+                        {
+                            if let Some(paths) = rfd::FileDialog::new()
+                                .add_filter("All Files", SUPPORTED_TEXTURE_FORMATS)
+                                .add_filter("GIF", &["gif"])
+                                .pick_files()
+                            {
+                                // TODO TOAST
+                                if let Err(err) = self.instances[instance_index]
+                                    .waddy
+                                    .add_animated_texture_from_paths(&paths, alternate)
+                                {
+                                    println!("{}", err);
+                                } else {
+                                    self.update_all_texture_tiles(ui, instance_index);
+                                }
+                            }
+
+                            ui.close_menu();
+                        }
+                    }
+                });
+
+                ui.menu_button("Convert to", |ui| {
+                    let is_wad2 = self.instances[instance_index].waddy.is_wad2();
+
+                    if ui
+                        .add_enabled(!is_wad2, egui::Button::new("WAD2 (Quake)"))
+                        .on_hover_text("Textures are changed to Quake palette")
+                        .clicked()
+                    {
+                        // TODO TOAST
+                        if let Err(err) = self.instances[instance_index]
+                            .waddy
+                            .convert_to_wad2(&QUAKE_PALETTE)
+                        {
+                            println!("{}", err);
+                        } else {
+                            self.update_all_texture_tiles(ui, instance_index);
+                        }
+
+                        ui.close_menu();
+                    }
+
+                    if ui
+                        .add_enabled(is_wad2, egui::Button::new("WAD3 (Half-Life)"))
+                        .clicked()
+                    {
+                        // TODO TOAST
+                        if let Err(err) = self.instances[instance_index].waddy.convert_to_wad3() {
+                            println!("{}", err);
+                        } else {
+                            self.update_all_texture_tiles(ui, instance_index);
+                        }
+
+                        ui.close_menu();
+                    }
+                });
+            }
+
+            if self.instances[instance_index].waddy.is_bsp() {
+                ui.separator();
+
+                if ui
+                    .button("Externalize textures")
+                    .on_hover_text("Moves embedded textures into a new WAD")
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("All Files", &["wad"])
+                        .save_file()
+                    {
+                        // TODO TOAST
+                        if let Err(err) = self.instances[instance_index]
+                            .waddy
+                            .externalize_textures(path.with_extension("wad"))
+                        {
+                            println!("{}", err);
+                        } else {
+                            self.update_all_texture_tiles(ui, instance_index);
+                        }
+                    }
+
+                    ui.close_menu();
+                }
+
+                if ui
+                    .button("Internalize textures")
+                    .on_hover_text("Embeds textures from WAD files")
+                    .clicked()
+                {
+                    if let Some(paths) = rfd::FileDialog::new()
+                        .add_filter("All Files", &["wad"])
+                        .pick_files()
+                    {
+                        let wads = paths
+                            .iter()
+                            .filter_map(|path| Wad::from_file(path).ok())
+                            .collect::<Vec<Wad>>();
+
+                        // TODO TOAST
+                        match self.instances[instance_index]
+                            .waddy
+                            .internalize_textures(&wads)
+                        {
+                            Ok(missing) => {
+                                if !missing.is_empty() {
+                                    println!("Cannot find textures: {}", missing.join(", "));
+                                }
+
+                                self.update_all_texture_tiles(ui, instance_index);
+                            }
+                            Err(err) => println!("{}", err),
+                        }
+                    }
+
+                    ui.close_menu();
+                }
+
+                ui.separator();
+            }
+
             if ui.button("Export All").clicked() {
                 if let Some(path) = rfd::FileDialog::new().pick_folder() {
                     // TODO TOAST TOAST
@@ -1014,6 +1413,15 @@ impl WaddyGui {
                 if ui.checkbox(&mut self.fit_texture, "Fit texture").clicked() {
                     ui.close_menu();
                 }
+
+                ui.separator();
+                ui.label("Image import");
+
+                quantize_options_ui(
+                    ui,
+                    &format!("Waddy {}", instance_index),
+                    self.instances[instance_index].waddy.quantize_options_mut(),
+                );
             });
 
             ui.separator();
@@ -1038,12 +1446,10 @@ impl WaddyGui {
                 .push_waddy_recent_wads(path.to_str().unwrap())
                 .expect(PERSISTENT_STORAGE_RECENTLY_USED_UPDATE_ERROR);
 
+            let path = path.clone();
+
             // TODO TOAST TOAST
-            if let Err(err) = self.instances[instance_index]
-                .waddy
-                .wad()
-                .write_to_file(path.as_path())
-            {
+            if let Err(err) = self.save_instance_to_file(instance_index, path.as_path()) {
                 println!("{}", err);
             } else {
                 self.instances[instance_index].is_changed = false;
@@ -1053,9 +1459,26 @@ impl WaddyGui {
         }
     }
 
+    /// Saves as BSP if the textures are from BSP and the path is .bsp, otherwise saves as WAD
+    fn save_instance_to_file(&mut self, instance_index: usize, path: &Path) -> eyre::Result<()> {
+        let waddy = &mut self.instances[instance_index].waddy;
+
+        if waddy.is_bsp() && path.extension().is_some_and(|ext| ext == "bsp") {
+            waddy.save_bsp_to_file(path)
+        } else {
+            waddy.save_to_file(path.with_extension("wad"))
+        }
+    }
+
     fn menu_save_as_dialogue(&mut self, instance_index: usize) {
+        let extensions: &[&str] = if self.instances[instance_index].waddy.is_bsp() {
+            &["bsp", "wad"]
+        } else {
+            &["wad"]
+        };
+
         if let Some(path) = rfd::FileDialog::new()
-            .add_filter("All Files", &["wad"])
+            .add_filter("All Files", extensions)
             .set_file_name(if let Some(path) = &self.instances[instance_index].path {
                 path.file_stem().unwrap().to_str().unwrap()
             } else {
@@ -1070,11 +1493,7 @@ impl WaddyGui {
                 .expect(PERSISTENT_STORAGE_RECENTLY_USED_UPDATE_ERROR);
 
             // TODO TOAST TOAST
-            if let Err(err) = self.instances[instance_index]
-                .waddy
-                .wad()
-                .write_to_file(path.with_extension("wad"))
-            {
+            if let Err(err) = self.save_instance_to_file(instance_index, path.as_path()) {
                 println!("{}", err);
             } else {
                 // Change path to the current WAD file if we use Save As
@@ -1,5 +1,9 @@
 use eframe::egui::{
-    self, Align2, Color32, Context, Id, Image, LayerId, Order, TextStyle, TextureHandle,
+    self, Align2, Color32, Context, Id, Image, LayerId, Order, TextStyle, TextureHandle, Ui,
+};
+
+use crate::utils::img_stuffs::{
+    Dithering, ErrorMetric, QuantizeColorSpace, QuantizeOptions, Quantizer, DEFAULT_DITHER_STRENGTH,
 };
 
 /// Preview hovering files:
@@ -24,6 +28,68 @@ pub fn preview_files_being_dropped_min_max_file(ctx: &egui::Context, min: usize,
     }
 }
 
+/// Options for converting images to 8bpp. `id` must be unique in the program.
+pub fn quantize_options_ui(ui: &mut Ui, id: &str, options: &mut QuantizeOptions) {
+    ui.horizontal(|ui| {
+        ui.label("Quantizer");
+        egui::ComboBox::from_id_source((id, "quantizer"))
+            .selected_text(format!("{:?}", options.quantizer))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut options.quantizer, Quantizer::KMeans, "KMeans");
+                ui.selectable_value(&mut options.quantizer, Quantizer::Wu, "Wu");
+            })
+            .response
+            .on_hover_text("KMeans has better colors, Wu is faster");
+
+        ui.label("Color space");
+        egui::ComboBox::from_id_source((id, "colorspace"))
+            .selected_text(format!("{:?}", options.colorspace))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut options.colorspace, QuantizeColorSpace::Oklab, "Oklab");
+                ui.selectable_value(&mut options.colorspace, QuantizeColorSpace::Srgb, "Srgb");
+            })
+            .response
+            .on_hover_text("Oklab has smoother gradients");
+
+        let mut is_dithering = matches!(options.dithering, Dithering::FloydSteinberg(_));
+
+        if ui
+            .checkbox(&mut is_dithering, "Dithering")
+            .on_hover_text("Floyd-Steinberg dithering, reduces banding in gradients")
+            .changed()
+        {
+            options.dithering = if is_dithering {
+                Dithering::FloydSteinberg(DEFAULT_DITHER_STRENGTH)
+            } else {
+                Dithering::None
+            };
+        }
+
+        match &mut options.dithering {
+            Dithering::FloydSteinberg(strength) => {
+                ui.add(egui::DragValue::new(strength).range(0.0..=1.0).speed(0.01))
+                    .on_hover_text("Dithering strength");
+            }
+            Dithering::None => {
+                ui.label("Error metric");
+                egui::ComboBox::from_id_source((id, "error metric"))
+                    .selected_text(format!("{:?}", options.error_metric))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut options.error_metric, ErrorMetric::Rgb, "Rgb");
+                        ui.selectable_value(
+                            &mut options.error_metric,
+                            ErrorMetric::WeightedRgb,
+                            "WeightedRgb",
+                        );
+                        ui.selectable_value(&mut options.error_metric, ErrorMetric::Oklab, "Oklab");
+                    })
+                    .response
+                    .on_hover_text("How pixels are matched to palette colors");
+            }
+        }
+    });
+}
+
 // fn is_in_rect(p: Pos2, rect: Rect) -> bool {
 //     let is_in = |v, min, max| min <= v && v <= max;
 
@@ -10,7 +10,7 @@ use eyre::eyre;
 
 use crate::utils::{
     constants::{EPSILON, STUDIOMDL_ERROR_PATTERN},
-    img_stuffs::{rgba8_to_8bpp, write_8bpp_to_file, GoldSrcBmp},
+    img_stuffs::{rgba8_to_8bpp_with_options, write_8bpp_to_file, GoldSrcBmp, QuantizeOptions},
     run_bin::run_studiomdl,
     simple_calculs::{Matrix2x2, Plane3D, Polygon3D},
     smd_stuffs::textures_used_in_triangles,
@@ -29,6 +29,7 @@ pub struct BLBHOptions {
     pub compile_model: bool,
     pub flat_shade: bool,
     pub uv_shrink_factor: f32,
+    pub quantize_options: QuantizeOptions,
     pub studiomdl: String,
     #[cfg(target_os = "linux")]
     pub wineprefix: String,
@@ -42,6 +43,7 @@ impl Default for BLBHOptions {
             compile_model: true,
             flat_shade: true,
             uv_shrink_factor: BLBH_DEFAULT_UV_SHRINK_FACTOR,
+            quantize_options: QuantizeOptions::default(),
             studiomdl: Default::default(),
             #[cfg(target_os = "linux")]
             wineprefix: Default::default(),
@@ -88,7 +90,8 @@ pub fn blender_lightmap_baker_helper(blbh: &BLBH) -> eyre::Result<()> {
                     image,
                     palette,
                     dimensions,
-                } = rgba8_to_8bpp(curr_image.to_rgba8()).unwrap();
+                } = rgba8_to_8bpp_with_options(curr_image.to_rgba8(), &options.quantize_options)
+                    .unwrap();
 
                 let out_file_name = format!("{}{}{}.bmp", texture_file_name, w_block, h_block);
                 write_8bpp_to_file(
@@ -506,6 +509,7 @@ mod test {
             compile_model: true,
             flat_shade: true,
             uv_shrink_factor: BLBH_DEFAULT_UV_SHRINK_FACTOR,
+            quantize_options: QuantizeOptions::default(),
             studiomdl: String::from("/home/khang/gchimp/dist/studiomdl.exe"),
             #[cfg(target_os = "linux")]
             wineprefix: String::from("/home/khang/.local/share/wineprefixes/wine32/"),
@@ -0,0 +1,475 @@
+//! CSG operations on brushes like the ones in the editor.
+//!
+//! New faces keep the texture axes of the plane they come from so the alignment does not change.
+//!
+//! Faces come from the map crate windings rather than `Polygon3D` so they match what the RMF and JMF writers see.
+use glam::{DVec3, Vec4Swizzles};
+use map::{
+    base_texture_axes, brush_plane_winding, plane_equation, Brush, BrushPlane, ON_PLANE_EPSILON,
+};
+
+/// Texture axes this close to the face normal cannot be used on the face.
+static MIN_TEXTURE_AXES_ALIGNMENT: f64 = 0.1;
+
+/// Same plane facing the other way with the same texture.
+fn flip_plane(plane: &BrushPlane) -> BrushPlane {
+    BrushPlane {
+        p1: plane.p3,
+        p3: plane.p1,
+        ..plane.clone()
+    }
+}
+
+/// Moves the plane outward by the distance.
+fn offset_plane(plane: &BrushPlane, distance: f64) -> BrushPlane {
+    let offset = plane.normal() * distance;
+
+    BrushPlane {
+        p1: plane.p1 + offset,
+        p2: plane.p2 + offset,
+        p3: plane.p3 + offset,
+        ..plane.clone()
+    }
+}
+
+/// Vertices of every face without duplicates.
+pub fn brush_vertices(brush: &Brush) -> Vec<DVec3> {
+    let mut res: Vec<DVec3> = vec![];
+
+    (0..brush.planes.len())
+        .filter_map(|plane_index| brush_plane_winding(brush, plane_index))
+        .flatten()
+        .for_each(|vertex| {
+            if !res
+                .iter()
+                .any(|other| other.distance(vertex) < ON_PLANE_EPSILON)
+            {
+                res.push(vertex);
+            }
+        });
+
+    res
+}
+
+/// Removes planes that do not make a face.
+///
+/// Returns None if the brush has no volume.
+fn without_unused_planes(brush: Brush) -> Option<Brush> {
+    let planes = (0..brush.planes.len())
+        .filter(|&plane_index| brush_plane_winding(&brush, plane_index).is_some())
+        .map(|plane_index| brush.planes[plane_index].clone())
+        .collect::<Vec<BrushPlane>>();
+
+    if planes.len() < 4 {
+        return None;
+    }
+
+    Some(Brush {
+        planes,
+        comments: brush.comments,
+    })
+}
+
+/// Keeps the part of the brush behind the plane, where the plane normal points away from.
+///
+/// The plane becomes a new face with its texture. Returns None if nothing is left.
+pub fn clip_brush(brush: &Brush, plane: &BrushPlane) -> Option<Brush> {
+    let (normal, distance) = plane_equation(plane);
+
+    if normal == DVec3::ZERO {
+        return None;
+    }
+
+    let vertices = brush_vertices(brush);
+
+    if vertices.is_empty() {
+        return None;
+    }
+
+    let distances = vertices
+        .iter()
+        .map(|vertex| normal.dot(*vertex) - distance)
+        .collect::<Vec<f64>>();
+
+    // entirely in front or flat on the plane
+    if distances.iter().all(|&d| d >= -ON_PLANE_EPSILON) {
+        return None;
+    }
+
+    // plane misses the brush
+    if distances.iter().all(|&d| d <= ON_PLANE_EPSILON) {
+        return Some(brush.clone());
+    }
+
+    let mut planes = brush.planes.clone();
+    planes.push(plane.clone());
+
+    without_unused_planes(Brush {
+        planes,
+        comments: brush.comments.clone(),
+    })
+}
+
+/// Splits the brush into the parts behind and in front of the plane.
+///
+/// The part in front has the plane flipped as its new face.
+pub fn split_brush(brush: &Brush, plane: &BrushPlane) -> (Option<Brush>, Option<Brush>) {
+    (
+        clip_brush(brush, plane),
+        clip_brush(brush, &flip_plane(plane)),
+    )
+}
+
+/// Carves the cutter out of the brush like the editor does.
+///
+/// The new faces have the textures of the cutter. Returns the brush unchanged if they do not overlap.
+pub fn subtract_brush(brush: &Brush, cutter: &Brush) -> Vec<Brush> {
+    let mut res = vec![];
+    let mut remaining = brush.clone();
+
+    for plane in cutter.planes.iter() {
+        // part in front of a cutter plane is outside the cutter
+        if let Some(outside) = clip_brush(&remaining, &flip_plane(plane)) {
+            res.push(outside);
+        }
+
+        match clip_brush(&remaining, plane) {
+            Some(inside) => remaining = inside,
+            None => return vec![brush.clone()],
+        }
+    }
+
+    // what remains is inside the cutter
+    res
+}
+
+/// Turns the brush into walls with the thickness.
+///
+/// Positive thickness makes the walls inside the brush, negative makes them outside.
+/// Inner faces have the textures of the faces they come from.
+pub fn hollow_brush(brush: &Brush, thickness: f64) -> eyre::Result<Vec<Brush>> {
+    if thickness == 0. {
+        return Ok(vec![brush.clone()]);
+    }
+
+    let offset_brush = without_unused_planes(Brush {
+        planes: brush
+            .planes
+            .iter()
+            .map(|plane| offset_plane(plane, -thickness))
+            .collect(),
+        comments: None,
+    })
+    .ok_or(eyre::eyre!(
+        "Brush is too small to hollow with thickness {}.",
+        thickness
+    ))?;
+
+    let res = if thickness > 0. {
+        subtract_brush(brush, &offset_brush)
+    } else {
+        subtract_brush(&offset_brush, brush)
+    };
+
+    Ok(res)
+}
+
+/// Merges the brushes into their convex hull like the editor does.
+///
+/// Faces on the same plane as an original face keep its texture.
+/// Other faces take the texture of the original face facing the most similar way.
+pub fn merge_brushes(brushes: &[Brush]) -> eyre::Result<Brush> {
+    let vertices =
+        brushes
+            .iter()
+            .flat_map(brush_vertices)
+            .fold(vec![], |mut acc: Vec<DVec3>, vertex| {
+                if !acc
+                    .iter()
+                    .any(|other| other.distance(vertex) < ON_PLANE_EPSILON)
+                {
+                    acc.push(vertex);
+                }
+
+                acc
+            });
+
+    let faces = brushes
+        .iter()
+        .flat_map(|brush| {
+            (0..brush.planes.len())
+                .filter(|&plane_index| brush_plane_winding(brush, plane_index).is_some())
+                .map(|plane_index| &brush.planes[plane_index])
+        })
+        .collect::<Vec<&BrushPlane>>();
+
+    if faces.is_empty() {
+        return Err(eyre::eyre!("No brush to merge."));
+    }
+
+    let mut hull_planes: Vec<(DVec3, f64, [DVec3; 3])> = vec![];
+
+    for i in 0..vertices.len() {
+        for j in (i + 1)..vertices.len() {
+            for k in (j + 1)..vertices.len() {
+                let (a, b, c) = (vertices[i], vertices[j], vertices[k]);
+                let normal = (b - a).cross(c - a);
+
+                if normal.length() < ON_PLANE_EPSILON {
+                    continue;
+                }
+
+                let normal = normal.normalize();
+                let distance = normal.dot(a);
+
+                let (normal, distance) = if vertices
+                    .iter()
+                    .all(|vertex| normal.dot(*vertex) - distance <= ON_PLANE_EPSILON)
+                {
+                    (normal, distance)
+                } else if vertices
+                    .iter()
+                    .all(|vertex| normal.dot(*vertex) - distance >= -ON_PLANE_EPSILON)
+                {
+                    (-normal, -distance)
+                } else {
+                    continue;
+                };
+
+                if hull_planes.iter().any(|(other_normal, other_distance, _)| {
+                    other_normal.dot(normal) > 1. - ON_PLANE_EPSILON
+                        && (other_distance - distance).abs() < ON_PLANE_EPSILON
+                }) {
+                    continue;
+                }
+
+                hull_planes.push((normal, distance, [a, b, c]));
+            }
+        }
+    }
+
+    if hull_planes.len() < 4 {
+        return Err(eyre::eyre!("Merged brush has no volume."));
+    }
+
+    let planes = hull_planes
+        .into_iter()
+        .map(|(normal, distance, [a, b, c])| {
+            let same_plane = faces.iter().find(|face| {
+                let (face_normal, face_distance) = plane_equation(face);
+
+                face_normal.dot(normal) > 1. - ON_PLANE_EPSILON
+                    && (face_distance - distance).abs() < ON_PLANE_EPSILON
+            });
+
+            if let Some(face) = same_plane {
+                return (*face).clone();
+            }
+
+            let (p1, p2, p3) = if (a - b).cross(c - b).dot(normal) > 0. {
+                (a, b, c)
+            } else {
+                (c, b, a)
+            };
+
+            let closest = faces
+                .iter()
+                .max_by(|x, y| x.normal().dot(normal).total_cmp(&y.normal().dot(normal)))
+                .unwrap();
+
+            // world aligned texture axes work on any face that they are not perpendicular to
+            let axes_normal = closest.u.xyz().cross(closest.v.xyz()).normalize_or_zero();
+
+            let (u, v) = if axes_normal.dot(normal).abs() > MIN_TEXTURE_AXES_ALIGNMENT {
+                (closest.u, closest.v)
+            } else {
+                let (u, v) = base_texture_axes(normal);
+
+                (u.extend(0.), v.extend(0.))
+            };
+
+            BrushPlane {
+                p1,
+                p2,
+                p3,
+                texture_name: closest.texture_name.clone(),
+                u,
+                v,
+                rotation: closest.rotation,
+                u_scale: closest.u_scale,
+                v_scale: closest.v_scale,
+            }
+        })
+        .collect::<Vec<BrushPlane>>();
+
+    Ok(Brush {
+        planes,
+        comments: None,
+    })
+}
+
+pub trait BrushCsgImpl {
+    fn clip(&self, plane: &BrushPlane) -> Option<Brush>;
+    fn split(&self, plane: &BrushPlane) -> (Option<Brush>, Option<Brush>);
+    fn subtract(&self, cutter: &Brush) -> Vec<Brush>;
+    fn hollow(&self, thickness: f64) -> eyre::Result<Vec<Brush>>;
+    fn merge(&self, other: &Brush) -> eyre::Result<Brush>;
+}
+
+impl BrushCsgImpl for Brush {
+    fn clip(&self, plane: &BrushPlane) -> Option<Brush> {
+        clip_brush(self, plane)
+    }
+
+    fn split(&self, plane: &BrushPlane) -> (Option<Brush>, Option<Brush>) {
+        split_brush(self, plane)
+    }
+
+    fn subtract(&self, cutter: &Brush) -> Vec<Brush> {
+        subtract_brush(self, cutter)
+    }
+
+    fn hollow(&self, thickness: f64) -> eyre::Result<Vec<Brush>> {
+        hollow_brush(self, thickness)
+    }
+
+    fn merge(&self, other: &Brush) -> eyre::Result<Brush> {
+        merge_brushes(&[self.clone(), other.clone()])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::map_stuffs::brush_from_mins_maxs;
+
+    use super::*;
+
+    fn cube(mins: [f64; 3], maxs: [f64; 3], texture: &str) -> Brush {
+        brush_from_mins_maxs(&mins, &maxs, texture)
+    }
+
+    fn bounds(brush: &Brush) -> (DVec3, DVec3) {
+        brush_vertices(brush)
+            .into_iter()
+            .fold((DVec3::MAX, DVec3::MIN), |(mins, maxs), vertex| {
+                (mins.min(vertex), maxs.max(vertex))
+            })
+    }
+
+    fn volume(brush: &Brush) -> f64 {
+        let (mins, maxs) = bounds(brush);
+        let size = maxs - mins;
+
+        size.x * size.y * size.z
+    }
+
+    #[test]
+    fn clip() {
+        let brush = cube([-32., -32., -32.], [32., 32., 32.], "crate");
+        let plane = BrushPlane {
+            p1: DVec3::new(0., 0., 0.),
+            p2: DVec3::new(0., 1., 0.),
+            p3: DVec3::new(1., 0., 0.),
+            ..brush.planes[0].clone()
+        };
+
+        // normal is +Z so the top half is removed
+        assert_eq!(plane.normal(), DVec3::Z);
+
+        let clipped = clip_brush(&brush, &plane).unwrap();
+
+        assert_eq!(
+            bounds(&clipped),
+            (DVec3::splat(-32.), DVec3::new(32., 32., 0.))
+        );
+        assert_eq!(clipped.planes.len(), 6);
+
+        let (back, front) = split_brush(&brush, &plane);
+
+        assert_eq!(bounds(&back.unwrap()).1.z, 0.);
+        assert_eq!(bounds(&front.unwrap()).0.z, 0.);
+
+        // plane missing the brush keeps it the same
+        let plane = offset_plane(&plane, 64.);
+
+        assert_eq!(clip_brush(&brush, &plane).unwrap(), brush);
+    }
+
+    #[test]
+    fn subtract() {
+        let brush = cube([-32., -32., -32.], [32., 32., 32.], "crate");
+        let cutter = cube([-16., -16., 0.], [16., 16., 64.], "cutter");
+
+        let res = subtract_brush(&brush, &cutter);
+
+        let total = res.iter().map(volume).sum::<f64>();
+
+        assert_eq!(total, 64. * 64. * 64. - 32. * 32. * 32.);
+
+        // faces inside the hole have the cutter texture
+        assert!(res
+            .iter()
+            .flat_map(|brush| brush.planes.iter())
+            .any(|plane| plane.texture_name == "cutter"));
+
+        // not touching
+        let cutter = cube([32., 32., 32.], [64., 64., 64.], "cutter");
+
+        assert_eq!(subtract_brush(&brush, &cutter), vec![brush]);
+    }
+
+    #[test]
+    fn hollow() {
+        let brush = cube([-32., -32., -32.], [32., 32., 32.], "crate");
+
+        let walls = hollow_brush(&brush, 8.).unwrap();
+
+        assert_eq!(walls.len(), 6);
+        assert_eq!(
+            walls.iter().map(volume).sum::<f64>(),
+            64. * 64. * 64. - 48. * 48. * 48.
+        );
+
+        // texture alignment stays the same on every face
+        assert!(walls
+            .iter()
+            .flat_map(|wall| wall.planes.iter())
+            .all(|plane| brush
+                .planes
+                .iter()
+                .any(|original| original.u == plane.u && original.v == plane.v)));
+
+        let walls = hollow_brush(&brush, -8.).unwrap();
+
+        assert_eq!(
+            walls.iter().map(volume).sum::<f64>(),
+            80. * 80. * 80. - 64. * 64. * 64.
+        );
+
+        assert!(hollow_brush(&brush, 32.).is_err());
+    }
+
+    #[test]
+    fn merge() {
+        let a = cube([0., 0., 0.], [32., 32., 32.], "a");
+        let b = cube([32., 0., 0.], [64., 32., 32.], "b");
+
+        let merged = merge_brushes(&[a.clone(), b]).unwrap();
+
+        assert_eq!(merged.planes.len(), 6);
+        assert_eq!(bounds(&merged), (DVec3::ZERO, DVec3::new(64., 32., 32.)));
+
+        // faces on the original planes keep the texture
+        assert!(merged.planes.iter().any(|plane| *plane == a.planes[0]));
+
+        // diagonal neighbors make new faces
+        let c = cube([32., 32., 0.], [64., 64., 32.], "c");
+        let merged = a.merge(&c).unwrap();
+
+        assert_eq!(merged.planes.len(), 8);
+        assert!(merged
+            .planes
+            .iter()
+            .all(|plane| plane.texture_name == "a" || plane.texture_name == "c"));
+    }
+}
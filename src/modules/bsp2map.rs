@@ -0,0 +1,588 @@
+use bsp::{Bsp, LeafContent};
+use glam::{DVec3, DVec4};
+use map::{base_texture_axes, Attributes, Brush, BrushPlane, Entity, Map};
+
+use crate::utils::{
+    constants::{NULL_TEXTURE, ORIGIN_TEXTURE},
+    map_stuffs::brush_from_mins_maxs,
+    misc::parse_triplet,
+    simple_calculs::{Plane3D, Point3D, Polygon3D},
+};
+
+/// Big enough to cover the whole map before clipping.
+static BASE_POLYGON_SIZE: f64 = 65536.;
+/// Extra space around the model bounds so brushes in the void are not paper thin.
+static BOUNDS_PADDING: f64 = 16.;
+/// Snaps plane points to the closest integer when they are this close.
+static SNAP_EPSILON: f64 = 0.01;
+
+/// A half-space from walking the BSP tree.
+///
+/// Normal points outward from the brush. Brush is where `normal.dot(p) <= distance`.
+#[derive(Clone, Copy)]
+struct BoundingPlane {
+    normal: DVec3,
+    distance: f64,
+    /// The BSP plane this comes from and whether the brush is on the back side of it.
+    bsp_plane: Option<(usize, bool)>,
+}
+
+impl BoundingPlane {
+    /// Inward plane so [`Polygon3D::cut`] keeps the inside of the brush.
+    fn to_plane3d(self) -> Plane3D {
+        Plane3D::new(
+            -self.normal.x,
+            -self.normal.y,
+            -self.normal.z,
+            -self.distance,
+        )
+    }
+}
+
+fn bsp_vec3(v: bsp::Vec3) -> DVec3 {
+    DVec3::new(v.x as f64, v.y as f64, v.z as f64)
+}
+
+/// A big square on the plane with vertices sorted.
+fn base_polygon(plane: &BoundingPlane) -> Option<Polygon3D> {
+    let normal = plane.normal.normalize();
+
+    // pick the axis that is the least aligned with the normal
+    let up = if normal.z.abs() > normal.x.abs() && normal.z.abs() > normal.y.abs() {
+        DVec3::X
+    } else {
+        DVec3::Z
+    };
+
+    let right = up.cross(normal).normalize() * BASE_POLYGON_SIZE;
+    let up = normal.cross(right).normalize() * BASE_POLYGON_SIZE;
+    let origin = normal * plane.distance / plane.normal.length();
+
+    Polygon3D::from(
+        [
+            origin - right - up,
+            origin + right - up,
+            origin + right + up,
+            origin - right + up,
+        ]
+        .into_iter()
+        .map(Point3D::from)
+        .collect::<Vec<Point3D>>(),
+    )
+    .with_sorted_vertices()
+    .ok()
+}
+
+fn snap(v: DVec3) -> DVec3 {
+    let rounded = v.round();
+
+    DVec3::new(
+        if (v.x - rounded.x).abs() < SNAP_EPSILON {
+            rounded.x
+        } else {
+            v.x
+        },
+        if (v.y - rounded.y).abs() < SNAP_EPSILON {
+            rounded.y
+        } else {
+            v.y
+        },
+        if (v.z - rounded.z).abs() < SNAP_EPSILON {
+            rounded.z
+        } else {
+            v.z
+        },
+    )
+}
+
+/// Finds the model face on the same plane that faces the same way as the brush side.
+///
+/// Prefers the face with its center inside the brush side.
+fn find_face_for_brush_side(
+    bsp: &Bsp,
+    model_index: usize,
+    (plane_index, back): (usize, bool),
+    side_polygon: &[DVec3],
+    planes: &[BoundingPlane],
+) -> Option<usize> {
+    let model = &bsp.models[model_index];
+    // side 0 faces along the plane normal, which is outward when the brush is on the back
+    let wanted_side = if back { 0 } else { 1 };
+
+    let side_center = side_polygon.iter().sum::<DVec3>() / side_polygon.len() as f64;
+
+    (model.first_face as usize..(model.first_face as usize + model.face_count as usize))
+        .filter(|&face_index| {
+            let face = &bsp.faces[face_index];
+            face.plane as usize == plane_index && face.side == wanted_side
+        })
+        .map(|face_index| {
+            let vertices = bsp.face_vertices(face_index);
+            let center =
+                vertices.iter().map(|&v| bsp_vec3(v)).sum::<DVec3>() / vertices.len().max(1) as f64;
+
+            let inside = planes
+                .iter()
+                .all(|plane| plane.normal.dot(center) <= plane.distance + 0.1);
+
+            (face_index, inside, center.distance(side_center))
+        })
+        .min_by(|a, b| b.1.cmp(&a.1).then(a.2.total_cmp(&b.2)))
+        .map(|(face_index, _, _)| face_index)
+}
+
+fn brush_plane_from_face(
+    bsp: &Bsp,
+    face_index: usize,
+    (p1, p2, p3): (DVec3, DVec3, DVec3),
+    origin: DVec3,
+) -> BrushPlane {
+    let texinfo = &bsp.texinfo[bsp.faces[face_index].texinfo as usize];
+    let texture_name = bsp
+        .textures
+        .get(texinfo.texture_index as usize)
+        .map(|texture| texture.texture_name.get_string())
+        .unwrap_or(NULL_TEXTURE.to_string());
+
+    let u = bsp_vec3(texinfo.u);
+    let v = bsp_vec3(texinfo.v);
+
+    // texinfo axes have the scale baked in
+    // vertices are moved by -origin so the texture has to be shifted back by origin
+    let u_scale = 1. / u.length();
+    let v_scale = 1. / v.length();
+    let u_offset = texinfo.u_offset as f64 + origin.dot(u);
+    let v_offset = texinfo.v_offset as f64 + origin.dot(v);
+
+    let u = u.normalize();
+    let v = v.normalize();
+
+    BrushPlane {
+        p1,
+        p2,
+        p3,
+        texture_name,
+        u: DVec4::new(u.x, u.y, u.z, u_offset),
+        v: DVec4::new(v.x, v.y, v.z, v_offset),
+        rotation: 0.,
+        u_scale,
+        v_scale,
+    }
+}
+
+/// Creates a brush from the region bounded by planes.
+///
+/// Returns None if the region has no volume.
+fn brush_from_bounding_planes(
+    bsp: &Bsp,
+    model_index: usize,
+    planes: &[BoundingPlane],
+    origin: DVec3,
+    fallback_texture: &str,
+) -> Option<Brush> {
+    let mut brush_planes = vec![];
+
+    for (plane_index, plane) in planes.iter().enumerate() {
+        let polygon = planes
+            .iter()
+            .enumerate()
+            .filter(|(other_index, _)| *other_index != plane_index)
+            .try_fold(base_polygon(plane)?, |polygon, (_, other)| {
+                polygon
+                    .cut(&other.to_plane3d())
+                    .filter(|polygon| polygon.vertices().len() >= 3)
+            });
+
+        // the plane does not contribute to the brush
+        let Some(polygon) = polygon else {
+            continue;
+        };
+
+        let vertices = polygon
+            .vertices()
+            .iter()
+            .map(|v| snap(v.to_dvec3()) - origin)
+            .collect::<Vec<DVec3>>();
+
+        // map plane normal from three points points inward so swap if it is the other way
+        let (p1, mut p2, mut p3) = (vertices[0], vertices[1], vertices[2]);
+
+        if (p2 - p1).cross(p3 - p1).dot(plane.normal) > 0. {
+            std::mem::swap(&mut p2, &mut p3);
+        }
+
+        let face_index = plane.bsp_plane.and_then(|bsp_plane| {
+            find_face_for_brush_side(bsp, model_index, bsp_plane, &vertices, planes)
+        });
+
+        let brush_plane = if let Some(face_index) = face_index {
+            brush_plane_from_face(bsp, face_index, (p1, p2, p3), origin)
+        } else {
+            // ties go to the earlier axis like the compiler so 45 degree faces match the BSP
+            let (u, v) = base_texture_axes(plane.normal);
+
+            BrushPlane {
+                p1,
+                p2,
+                p3,
+                texture_name: fallback_texture.to_string(),
+                u: u.extend(0.),
+                v: v.extend(0.),
+                rotation: 0.,
+                u_scale: 1.,
+                v_scale: 1.,
+            }
+        };
+
+        brush_planes.push(brush_plane);
+    }
+
+    if brush_planes.len() < 4 {
+        return None;
+    }
+
+    Some(Brush {
+        planes: brush_planes,
+        comments: None,
+    })
+}
+
+fn bounds_planes(mins: DVec3, maxs: DVec3) -> Vec<BoundingPlane> {
+    let mins = mins - DVec3::splat(BOUNDS_PADDING);
+    let maxs = maxs + DVec3::splat(BOUNDS_PADDING);
+
+    [
+        (DVec3::X, maxs.x),
+        (DVec3::Y, maxs.y),
+        (DVec3::Z, maxs.z),
+        (DVec3::NEG_X, -mins.x),
+        (DVec3::NEG_Y, -mins.y),
+        (DVec3::NEG_Z, -mins.z),
+    ]
+    .into_iter()
+    .map(|(normal, distance)| BoundingPlane {
+        normal,
+        distance,
+        bsp_plane: None,
+    })
+    .collect()
+}
+
+/// Walks the drawing hull of a model and turns every non-empty leaf into a brush.
+fn walk_bsp_tree(
+    bsp: &Bsp,
+    model_index: usize,
+    node_index: i32,
+    planes: &mut Vec<BoundingPlane>,
+    origin: DVec3,
+    brushes: &mut Vec<Brush>,
+) {
+    if node_index < 0 {
+        let leaf_index = (-node_index - 1) as usize;
+
+        let contents = if leaf_index == 0 {
+            LeafContent::ContentsSolid
+        } else {
+            bsp.leaves[leaf_index].contents
+        };
+
+        if matches!(contents, LeafContent::ContentsEmpty) {
+            return;
+        }
+
+        if let Some(brush) =
+            brush_from_bounding_planes(bsp, model_index, planes, origin, NULL_TEXTURE)
+        {
+            brushes.push(brush);
+        }
+
+        return;
+    }
+
+    let node = &bsp.nodes[node_index as usize];
+    let plane = &bsp.planes[node.plane as usize];
+
+    let normal = bsp_vec3(plane.normal);
+    let distance = plane.distance as f64;
+
+    // front child is where normal.dot(p) >= distance
+    planes.push(BoundingPlane {
+        normal: -normal,
+        distance: -distance,
+        bsp_plane: Some((node.plane as usize, false)),
+    });
+    walk_bsp_tree(
+        bsp,
+        model_index,
+        node.children[0] as i32,
+        planes,
+        origin,
+        brushes,
+    );
+    planes.pop();
+
+    planes.push(BoundingPlane {
+        normal,
+        distance,
+        bsp_plane: Some((node.plane as usize, true)),
+    });
+    walk_bsp_tree(
+        bsp,
+        model_index,
+        node.children[1] as i32,
+        planes,
+        origin,
+        brushes,
+    );
+    planes.pop();
+}
+
+/// Rebuilds brushes of a brush model.
+///
+/// Brushes are in world space if `origin` is zero, otherwise they are moved by `-origin`.
+pub fn bsp_model_to_brushes(bsp: &Bsp, model_index: usize, origin: DVec3) -> Vec<Brush> {
+    let model = &bsp.models[model_index];
+
+    let mut planes = bounds_planes(bsp_vec3(model.mins), bsp_vec3(model.maxs));
+    let mut brushes = vec![];
+
+    walk_bsp_tree(
+        bsp,
+        model_index,
+        model.head_nodes[0],
+        &mut planes,
+        origin,
+        &mut brushes,
+    );
+
+    brushes
+}
+
+/// Decompiles a BSP into a Valve220 .map
+///
+/// Every solid, liquid or sky leaf becomes a brush so the result has a lot more brushes than the original.
+pub fn bsp2map(bsp: &Bsp) -> eyre::Result<Map> {
+    if bsp.models.is_empty() {
+        return Err(eyre::eyre!("BSP does not have any model."));
+    }
+
+    // .map needs worldspawn to be the first entity
+    let worldspawn_index = bsp.entities.iter().position(|entity| {
+        entity
+            .get("classname")
+            .is_some_and(|classname| classname == "worldspawn")
+    });
+
+    if worldspawn_index.is_some_and(|index| index != 0) {
+        return Err(eyre::eyre!("Worldspawn is not the first entity."));
+    }
+
+    let entities = bsp
+        .entities
+        .iter()
+        .map(|bsp_entity| {
+            let mut attributes = Attributes::new();
+
+            bsp_entity.iter().for_each(|(key, value)| {
+                attributes.insert(key.to_owned(), value.to_owned());
+            });
+
+            let is_worldspawn = attributes
+                .get("classname")
+                .is_some_and(|classname| classname == "worldspawn");
+
+            let model_index = if is_worldspawn {
+                Some(0)
+            } else {
+                attributes
+                    .get("model")
+                    .and_then(|model| model.strip_prefix("*"))
+                    .and_then(|model| model.parse::<usize>().ok())
+            };
+
+            let Some(model_index) = model_index.filter(|&index| index < bsp.models.len()) else {
+                return Entity {
+                    attributes,
+                    brushes: None,
+                    comments: None,
+                    trailing_comments: vec![],
+                };
+            };
+
+            if is_worldspawn {
+                attributes.insert("mapversion".to_string(), "220".to_string());
+            } else {
+                attributes.shift_remove("model");
+            }
+
+            // brush entity with origin brush has its brushes relative to the origin
+            let origin = attributes
+                .get("origin")
+                .and_then(|origin| parse_triplet(origin).ok())
+                .map(DVec3::from)
+                .filter(|_| !is_worldspawn)
+                .unwrap_or(DVec3::ZERO);
+
+            let mut brushes = bsp_model_to_brushes(bsp, model_index, -origin);
+
+            if origin != DVec3::ZERO {
+                let mins = origin - DVec3::splat(8.);
+                let maxs = origin + DVec3::splat(8.);
+
+                brushes.push(brush_from_mins_maxs(
+                    &mins.to_array(),
+                    &maxs.to_array(),
+                    ORIGIN_TEXTURE,
+                ));
+
+                attributes.shift_remove("origin");
+            }
+
+            Entity {
+                attributes,
+                brushes: Some(brushes),
+                comments: None,
+                trailing_comments: vec![],
+            }
+        })
+        .collect::<Vec<Entity>>();
+
+    Ok(Map {
+        tb_header: None,
+        entities,
+        trailing_comments: vec![],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decompile() {
+        let bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        let map = bsp2map(&bsp).unwrap();
+
+        assert_eq!(map.entities.len(), bsp.entities.len());
+
+        let worldspawn = &map.entities[0];
+
+        assert_eq!(
+            worldspawn.attributes.get("classname").unwrap(),
+            "worldspawn"
+        );
+        assert_eq!(worldspawn.attributes.get("mapversion").unwrap(), "220");
+
+        let brushes = worldspawn.brushes.as_ref().unwrap();
+
+        assert!(!brushes.is_empty());
+        assert!(brushes.iter().all(|brush| brush.planes.len() >= 4));
+
+        // some faces should have the real textures
+        assert!(brushes
+            .iter()
+            .flat_map(|brush| brush.planes.iter())
+            .any(|plane| plane.texture_name != NULL_TEXTURE));
+    }
+
+    #[test]
+    fn decompile_origin_brush() {
+        let mut bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        let entity_origin = DVec3::new(64., -32., 16.);
+
+        // worldspawn model again but as a brush entity with origin
+        let mut entity = bsp::Entity::new();
+        entity.insert("classname".to_string(), "func_rotating".to_string());
+        entity.insert("model".to_string(), "*0".to_string());
+        entity.insert("origin".to_string(), "64 -32 16".to_string());
+        bsp.entities.push(entity);
+
+        let map = bsp2map(&bsp).unwrap();
+
+        let world_brushes = map.entities[0].brushes.as_ref().unwrap();
+        let entity = map.entities.last().unwrap();
+        let entity_brushes = entity.brushes.as_ref().unwrap();
+
+        assert!(!entity.attributes.contains_key("origin"));
+        assert_eq!(entity_brushes.len(), world_brushes.len() + 1);
+        assert_eq!(
+            entity_brushes.last().unwrap().planes[0].texture_name,
+            ORIGIN_TEXTURE
+        );
+
+        let texture_coordinate = |point: DVec3, plane: &BrushPlane| {
+            (
+                point.dot(plane.u.truncate()) / plane.u_scale + plane.u.w,
+                point.dot(plane.v.truncate()) / plane.v_scale + plane.v.w,
+            )
+        };
+
+        // textures move with the brushes
+        world_brushes
+            .iter()
+            .zip(entity_brushes)
+            .flat_map(|(world_brush, entity_brush)| {
+                world_brush.planes.iter().zip(&entity_brush.planes)
+            })
+            .for_each(|(world_plane, entity_plane)| {
+                assert_eq!(entity_plane.p1, world_plane.p1 + entity_origin);
+
+                // sides without a face are not aligned to anything
+                if world_plane.texture_name == NULL_TEXTURE {
+                    return;
+                }
+
+                let (world_u, world_v) = texture_coordinate(world_plane.p1, world_plane);
+                let (entity_u, entity_v) = texture_coordinate(entity_plane.p1, entity_plane);
+
+                assert!((world_u - entity_u).abs() < 0.01);
+                assert!((world_v - entity_v).abs() < 0.01);
+            });
+    }
+
+    #[test]
+    fn worldspawn_not_first() {
+        let mut bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+
+        bsp.entities.swap(0, 1);
+
+        assert!(bsp2map(&bsp).is_err());
+    }
+
+    #[test]
+    fn decompile_write_read() {
+        let bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        let map = bsp2map(&bsp).unwrap();
+
+        let out = std::env::temp_dir().join("gchimp_bsp2map_normal.map");
+        map.write(&out).unwrap();
+
+        let map2 = Map::from_file(&out).unwrap();
+
+        assert_eq!(map.entities.len(), map2.entities.len());
+        assert_eq!(
+            map.entities[0].brushes.as_ref().unwrap().len(),
+            map2.entities[0].brushes.as_ref().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn diagonal_face_texture_axes() {
+        let bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+
+        let mut planes = bounds_planes(DVec3::splat(-32.), DVec3::splat(32.));
+        planes.push(BoundingPlane {
+            normal: DVec3::new(1., 0., 1.).normalize(),
+            distance: 0.,
+            bsp_plane: None,
+        });
+
+        let brush =
+            brush_from_bounding_planes(&bsp, 0, &planes, DVec3::ZERO, NULL_TEXTURE).unwrap();
+        let diagonal = brush.planes.last().unwrap();
+
+        // equally close to X and Z, the floor axes win
+        assert_eq!(diagonal.u, DVec4::new(1., 0., 0., 0.));
+        assert_eq!(diagonal.v, DVec4::new(0., -1., 0., 0.));
+    }
+}
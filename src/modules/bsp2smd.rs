@@ -0,0 +1,233 @@
+use std::{fmt::Write, path::Path};
+
+use bsp::Bsp;
+use glam::{DVec2, DVec3};
+use smd::{Smd, Triangle, Vertex};
+
+use crate::err;
+
+fn bsp_vec3(v: bsp::Vec3) -> DVec3 {
+    DVec3::new(v.x as f64, v.y as f64, v.z as f64)
+}
+
+/// Same as how the engine does it. Texel coordinate is divided by the texture dimensions.
+///
+/// V coordinate is flipped like .map to .smd conversion.
+fn face_uv(
+    p: DVec3,
+    (u, u_offset): (DVec3, f64),
+    (v, v_offset): (DVec3, f64),
+    (width, height): (u32, u32),
+) -> DVec2 {
+    let res = DVec2::new(p.dot(u) + u_offset, p.dot(v) + v_offset)
+        / DVec2::new(width as f64, height as f64);
+
+    res * DVec2::new(1., -1.)
+}
+
+/// Triangulates a face of a BSP.
+///
+/// Triangles are wound counter-clockwise looking from the front of the face.
+pub fn bsp_face_to_triangles(bsp: &Bsp, face_index: usize) -> Vec<Triangle> {
+    let face = &bsp.faces[face_index];
+    let texinfo = &bsp.texinfo[face.texinfo as usize];
+    let plane = &bsp.planes[face.plane as usize];
+
+    let (material, dimensions) = bsp
+        .textures
+        .get(texinfo.texture_index as usize)
+        .map(|texture| {
+            (
+                texture.texture_name.get_string(),
+                (texture.width.max(1), texture.height.max(1)),
+            )
+        })
+        .unwrap_or(("NULL".to_string(), (16, 16)));
+
+    let norm = if face.side == 0 {
+        bsp_vec3(plane.normal)
+    } else {
+        -bsp_vec3(plane.normal)
+    };
+
+    let u = (bsp_vec3(texinfo.u), texinfo.u_offset as f64);
+    let v = (bsp_vec3(texinfo.v), texinfo.v_offset as f64);
+
+    let vertices = bsp
+        .face_vertices(face_index)
+        .into_iter()
+        .map(|pos| {
+            let pos = bsp_vec3(pos);
+
+            Vertex {
+                parent: 0,
+                pos,
+                norm,
+                uv: face_uv(pos, u, v, dimensions),
+                source: None,
+            }
+        })
+        .collect::<Vec<Vertex>>();
+
+    if vertices.len() < 3 {
+        return vec![];
+    }
+
+    // faces are convex so a fan is good enough
+    (1..vertices.len() - 1)
+        .map(|i| {
+            let (v0, mut v1, mut v2) = (&vertices[0], &vertices[i], &vertices[i + 1]);
+
+            if (v1.pos - v0.pos).cross(v2.pos - v0.pos).dot(norm) < 0. {
+                std::mem::swap(&mut v1, &mut v2);
+            }
+
+            Triangle {
+                material: material.clone(),
+                vertices: vec![v0.clone(), v1.clone(), v2.clone()],
+            }
+        })
+        .collect()
+}
+
+/// Triangulates every face of a brush model. Model 0 is worldspawn.
+pub fn bsp_model_to_triangles(bsp: &Bsp, model_index: usize) -> eyre::Result<Vec<Triangle>> {
+    let Some(model) = bsp.models.get(model_index) else {
+        return err!("Model {} does not exist.", model_index);
+    };
+
+    let first_face = model.first_face as usize;
+    let face_count = model.face_count as usize;
+
+    if first_face + face_count > bsp.faces.len() {
+        return err!("Model {} has out of bound faces.", model_index);
+    }
+
+    Ok((first_face..first_face + face_count)
+        .flat_map(|face_index| bsp_face_to_triangles(bsp, face_index))
+        .collect())
+}
+
+/// Converts a brush model into an SMD with one bone.
+pub fn bsp_model_to_smd(bsp: &Bsp, model_index: usize) -> eyre::Result<Smd> {
+    let mut smd = Smd::new_basic();
+
+    bsp_model_to_triangles(bsp, model_index)?
+        .into_iter()
+        .for_each(|triangle| {
+            smd.add_triangle(triangle);
+        });
+
+    Ok(smd)
+}
+
+/// Writes triangles as Wavefront OBJ. Materials are referenced by name with `usemtl`.
+pub fn triangles_to_obj(triangles: &[Triangle]) -> String {
+    let mut res = String::new();
+    let mut current_material: Option<&str> = None;
+
+    triangles
+        .iter()
+        .enumerate()
+        .for_each(|(triangle_index, triangle)| {
+            for vertex in &triangle.vertices {
+                writeln!(res, "v {} {} {}", vertex.pos.x, vertex.pos.y, vertex.pos.z).unwrap();
+                writeln!(res, "vt {} {}", vertex.uv.x, vertex.uv.y).unwrap();
+                writeln!(
+                    res,
+                    "vn {} {} {}",
+                    vertex.norm.x, vertex.norm.y, vertex.norm.z
+                )
+                .unwrap();
+            }
+
+            if current_material != Some(triangle.material.as_str()) {
+                writeln!(res, "usemtl {}", triangle.material).unwrap();
+                current_material = Some(triangle.material.as_str());
+            }
+
+            // obj index starts from 1
+            let first = triangle_index * 3 + 1;
+
+            writeln!(
+                res,
+                "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}",
+                first,
+                first + 1,
+                first + 2
+            )
+            .unwrap();
+        });
+
+    res
+}
+
+/// Converts a brush model into an OBJ file.
+pub fn bsp_model_to_obj(bsp: &Bsp, model_index: usize, path: impl AsRef<Path>) -> eyre::Result<()> {
+    let triangles = bsp_model_to_triangles(bsp, model_index)?;
+
+    std::fs::write(path, triangles_to_obj(&triangles))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn world_triangles() {
+        let bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        let triangles = bsp_model_to_triangles(&bsp, 0).unwrap();
+
+        assert!(!triangles.is_empty());
+
+        triangles.iter().for_each(|triangle| {
+            assert_eq!(triangle.vertices.len(), 3);
+
+            let [v0, v1, v2] = [
+                &triangle.vertices[0],
+                &triangle.vertices[1],
+                &triangle.vertices[2],
+            ];
+
+            // winding agrees with the normal
+            assert!((v1.pos - v0.pos).cross(v2.pos - v0.pos).dot(v0.norm) >= 0.);
+        });
+
+        assert!(bsp_model_to_triangles(&bsp, bsp.models.len()).is_err());
+    }
+
+    #[test]
+    fn uv_matches_texinfo() {
+        let bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        let triangles = bsp_face_to_triangles(&bsp, 0);
+
+        let face = &bsp.faces[0];
+        let texinfo = &bsp.texinfo[face.texinfo as usize];
+        let texture = &bsp.textures[texinfo.texture_index as usize];
+
+        let vertex = &triangles[0].vertices[0];
+        let s = vertex.pos.dot(bsp_vec3(texinfo.u)) + texinfo.u_offset as f64;
+        let t = vertex.pos.dot(bsp_vec3(texinfo.v)) + texinfo.v_offset as f64;
+
+        assert!((vertex.uv.x - s / texture.width as f64).abs() < 0.0001);
+        assert!((vertex.uv.y + t / texture.height as f64).abs() < 0.0001);
+    }
+
+    #[test]
+    fn obj() {
+        let bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        let triangles = bsp_model_to_triangles(&bsp, 0).unwrap();
+        let obj = triangles_to_obj(&triangles);
+
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("f ")).count(),
+            triangles.len()
+        );
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("v ")).count(),
+            triangles.len() * 3
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use bsp::Bsp;
+use image::{Rgb, RgbImage};
+
+use crate::err;
+
+/// Atlas is at least this wide so small maps do not end up as a long strip.
+static MIN_ATLAS_WIDTH: u32 = 256;
+/// Empty texels between face blocks so editing near the edges does not bleed.
+static ATLAS_PADDING: u32 = 1;
+
+/// Where a face lightmap is in the atlas.
+///
+/// Light styles of a face are placed next to each other horizontally, starting from `x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LightmapAtlasEntry {
+    pub face_index: usize,
+    pub x: u32,
+    pub y: u32,
+    /// Width of one light style
+    pub width: u32,
+    pub height: u32,
+    pub style_count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightmapAtlasLayout {
+    pub width: u32,
+    pub height: u32,
+    pub entries: Vec<LightmapAtlasEntry>,
+}
+
+/// Packs face lightmaps row by row in face order.
+///
+/// The layout only depends on the BSP so the same BSP always gives the same layout.
+/// That is how the atlas is read back.
+pub fn lightmap_atlas_layout(bsp: &Bsp) -> LightmapAtlasLayout {
+    let faces = (0..bsp.faces.len())
+        .filter(|&face_index| bsp.face_has_lightmap(face_index))
+        .map(|face_index| {
+            let extents = bsp.face_lightmap_extents(face_index);
+
+            (
+                face_index,
+                extents.width() as u32,
+                extents.height() as u32,
+                bsp.face_light_style_count(face_index) as u32,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let widest = faces
+        .iter()
+        .map(|(_, width, _, style_count)| width * style_count + ATLAS_PADDING)
+        .max()
+        .unwrap_or(0);
+
+    let atlas_width = widest.max(MIN_ATLAS_WIDTH);
+
+    let mut entries = vec![];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+
+    for (face_index, width, height, style_count) in faces {
+        let block_width = width * style_count + ATLAS_PADDING;
+
+        if x + block_width > atlas_width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+
+        entries.push(LightmapAtlasEntry {
+            face_index,
+            x,
+            y,
+            width,
+            height,
+            style_count,
+        });
+
+        x += block_width;
+        row_height = row_height.max(height + ATLAS_PADDING);
+    }
+
+    LightmapAtlasLayout {
+        width: atlas_width,
+        height: (y + row_height).max(1),
+        entries,
+    }
+}
+
+/// Puts every face lightmap, including all light styles, into one image.
+pub fn export_lightmap_atlas(bsp: &Bsp) -> eyre::Result<RgbImage> {
+    let layout = lightmap_atlas_layout(bsp);
+    let mut img = RgbImage::new(layout.width, layout.height);
+
+    for entry in layout.entries.iter() {
+        let Some(styles) = bsp.face_lightmap(entry.face_index) else {
+            return err!("Cannot get lightmap of face {}", entry.face_index);
+        };
+
+        for (style_index, texels) in styles.iter().enumerate() {
+            let style_x = entry.x + style_index as u32 * entry.width;
+
+            for (texel_index, texel) in texels.iter().enumerate() {
+                let texel_x = style_x + texel_index as u32 % entry.width;
+                let texel_y = entry.y + texel_index as u32 / entry.width;
+
+                img.put_pixel(texel_x, texel_y, Rgb(*texel));
+            }
+        }
+    }
+
+    Ok(img)
+}
+
+/// Writes lightmaps from an atlas made by [`export_lightmap_atlas`] back into the BSP.
+///
+/// The BSP must have the same faces as the one used to export.
+pub fn import_lightmap_atlas(bsp: &mut Bsp, img: &RgbImage) -> eyre::Result<()> {
+    let layout = lightmap_atlas_layout(bsp);
+
+    if img.dimensions() != (layout.width, layout.height) {
+        return err!(
+            "Atlas is {}x{} but the BSP needs {}x{}",
+            img.width(),
+            img.height(),
+            layout.width,
+            layout.height
+        );
+    }
+
+    for entry in layout.entries.iter() {
+        for style_index in 0..entry.style_count {
+            let style_x = entry.x + style_index * entry.width;
+
+            let texels = (0..entry.height)
+                .flat_map(|y| (0..entry.width).map(move |x| (x, y)))
+                .map(|(x, y)| img.get_pixel(style_x + x, entry.y + y).0)
+                .collect::<Vec<[u8; 3]>>();
+
+            bsp.set_face_lightmap(entry.face_index, style_index as usize, &texels)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout_no_overlap() {
+        let bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        let layout = lightmap_atlas_layout(&bsp);
+
+        assert!(!layout.entries.is_empty());
+
+        let mut used = vec![false; (layout.width * layout.height) as usize];
+
+        for entry in layout.entries.iter() {
+            for y in entry.y..entry.y + entry.height {
+                for x in entry.x..entry.x + entry.width * entry.style_count {
+                    let index = (y * layout.width + x) as usize;
+
+                    assert!(!used[index]);
+                    used[index] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn export_import() {
+        let bsp = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        let mut img = export_lightmap_atlas(&bsp).unwrap();
+
+        let entry = lightmap_atlas_layout(&bsp).entries[0];
+
+        // paint the first face
+        for y in entry.y..entry.y + entry.height {
+            for x in entry.x..entry.x + entry.width {
+                img.put_pixel(x, y, Rgb([1, 2, 3]));
+            }
+        }
+
+        let mut bsp2 = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        import_lightmap_atlas(&mut bsp2, &img).unwrap();
+
+        let bsp2 = Bsp::from_bytes(&bsp2.write_to_bytes()).unwrap();
+
+        assert!(bsp2.face_lightmap(entry.face_index).unwrap()[0]
+            .iter()
+            .all(|texel| *texel == [1, 2, 3]));
+
+        // other faces are untouched
+        for entry in lightmap_atlas_layout(&bsp).entries.iter().skip(1) {
+            assert_eq!(
+                bsp.face_lightmap(entry.face_index),
+                bsp2.face_lightmap(entry.face_index)
+            );
+        }
+
+        // wrong size is rejected
+        let mut bsp3 = Bsp::from_file("bsp/src/tests/normal.bsp").unwrap();
+        assert!(import_lightmap_atlas(&mut bsp3, &RgbImage::new(1, 1)).is_err());
+    }
+}
@@ -1,8 +1,12 @@
 use std::{fs::OpenOptions, io::Read, path::Path};
 
-use rhai::Engine;
+use glam::DVec3;
+use map::{base_texture_axes, Brush, BrushPlane};
+use rhai::{Array, Dynamic, Engine, EvalAltResult};
 
-use super::{duplicate_triangle, light_scale, rotate_prop_static, texture_scale};
+use crate::utils::{constants::NULL_TEXTURE, map_stuffs::brush_from_mins_maxs};
+
+use super::{brush_csg, duplicate_triangle, light_scale, rotate_prop_static, texture_scale};
 
 fn rotate_prop_static_single(map: &mut map::Map) {
     rotate_prop_static::rotate_prop_static(map, None);
@@ -36,6 +40,114 @@ fn texture_scale_int(map: &mut map::Map, scalar: i64) {
     texture_scale(map, scalar as f64);
 }
 
+fn array_to_dvec3(array: Array) -> Result<DVec3, Box<EvalAltResult>> {
+    let numbers = array
+        .into_iter()
+        .map(|number| {
+            number
+                .as_float()
+                .or_else(|_| number.as_int().map(|number| number as f64))
+        })
+        .collect::<Result<Vec<f64>, _>>()?;
+
+    if numbers.len() != 3 {
+        return Err("Expected an array of 3 numbers.".into());
+    }
+
+    Ok(DVec3::new(numbers[0], numbers[1], numbers[2]))
+}
+
+fn brushes_to_array(brushes: Vec<Brush>) -> Array {
+    brushes.into_iter().map(Dynamic::from).collect()
+}
+
+fn array_to_brushes(array: Array) -> Vec<Brush> {
+    array
+        .into_iter()
+        .filter_map(|brush| brush.try_cast::<Brush>())
+        .collect()
+}
+
+fn new_brush(mins: Array, maxs: Array, texture: &str) -> Result<Brush, Box<EvalAltResult>> {
+    let mins = array_to_dvec3(mins)?;
+    let maxs = array_to_dvec3(maxs)?;
+
+    Ok(brush_from_mins_maxs(
+        &mins.to_array(),
+        &maxs.to_array(),
+        texture,
+    ))
+}
+
+fn entity_brushes(map: &mut map::Map, entity_index: i64) -> Array {
+    map.entities
+        .get(entity_index as usize)
+        .and_then(|entity| entity.brushes.clone())
+        .map(brushes_to_array)
+        .unwrap_or_default()
+}
+
+fn set_entity_brushes(map: &mut map::Map, entity_index: i64, brushes: Array) {
+    if let Some(entity) = map.entities.get_mut(entity_index as usize) {
+        entity.brushes = Some(array_to_brushes(brushes));
+    }
+}
+
+/// Plane from three points with the normal pointing where the clipped part is.
+fn clip_brush(
+    brush: &mut Brush,
+    p1: Array,
+    p2: Array,
+    p3: Array,
+    texture: &str,
+) -> Result<Array, Box<EvalAltResult>> {
+    let (p1, p2, p3) = (
+        array_to_dvec3(p1)?,
+        array_to_dvec3(p2)?,
+        array_to_dvec3(p3)?,
+    );
+    let (u, v) = base_texture_axes((p1 - p2).cross(p3 - p2).normalize_or_zero());
+
+    let plane = BrushPlane {
+        p1,
+        p2,
+        p3,
+        texture_name: texture.to_string(),
+        u: u.extend(0.),
+        v: v.extend(0.),
+        rotation: 0.,
+        u_scale: 1.,
+        v_scale: 1.,
+    };
+
+    Ok(brushes_to_array(
+        brush_csg::clip_brush(brush, &plane).into_iter().collect(),
+    ))
+}
+
+fn clip_brush_null(
+    brush: &mut Brush,
+    p1: Array,
+    p2: Array,
+    p3: Array,
+) -> Result<Array, Box<EvalAltResult>> {
+    clip_brush(brush, p1, p2, p3, NULL_TEXTURE)
+}
+
+fn hollow_brush(brush: &mut Brush, thickness: f64) -> Result<Array, Box<EvalAltResult>> {
+    brush_csg::hollow_brush(brush, thickness)
+        .map(brushes_to_array)
+        .map_err(|err| err.to_string().into())
+}
+
+fn hollow_brush_int(brush: &mut Brush, thickness: i64) -> Result<Array, Box<EvalAltResult>> {
+    hollow_brush(brush, thickness as f64)
+}
+
+fn merge_brushes(brushes: Array) -> Result<Brush, Box<EvalAltResult>> {
+    brush_csg::merge_brushes(&array_to_brushes(brushes)).map_err(|err| err.to_string().into())
+}
+
 // TODO propagate results
 pub fn custom_script(rhai_file: &Path) {
     // Rhai engine part
@@ -66,7 +178,7 @@ pub fn custom_script(rhai_file: &Path) {
         .register_fn("new_map", |file_name: String| {
             map::Map::from_file(file_name).unwrap()
         })
-        .register_fn("write", |map, out: String| {
+        .register_fn("write", |map: &mut map::Map, out: String| {
             let _ = map::Map::write(map, out);
         })
         .register_fn("light_scale", light_scale::light_scale)
@@ -92,6 +204,20 @@ pub fn custom_script(rhai_file: &Path) {
         })
         .register_fn("duplicate_triangle", duplicate_triangle::duplicate_triangle);
 
+    engine
+        .register_type_with_name::<Brush>("Brush")
+        .register_fn("new_brush", new_brush)
+        .register_fn("brushes", entity_brushes)
+        .register_fn("set_brushes", set_entity_brushes)
+        .register_fn("clip", clip_brush)
+        .register_fn("clip", clip_brush_null)
+        .register_fn("subtract", |brush: &mut Brush, cutter: Brush| {
+            brushes_to_array(brush_csg::subtract_brush(brush, &cutter))
+        })
+        .register_fn("hollow", hollow_brush)
+        .register_fn("hollow", hollow_brush_int)
+        .register_fn("merge", merge_brushes);
+
     let file = OpenOptions::new().read(true).open(rhai_file);
 
     if let Err(err) = file {
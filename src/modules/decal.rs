@@ -0,0 +1,381 @@
+//! Makes spray (tempdecal.wad) and decal (decals.wad) textures.
+//!
+//! Decal textures start with `{`. A decal is one color and its pixels are how opaque it is.
+//! Palette is a grayscale ramp where index is alpha and index 255 is the decal color.
+//! A colored decal keeps the image colors instead and index 255 is transparent.
+use std::path::{Path, PathBuf};
+
+use eyre::eyre;
+use image::{imageops, RgbaImage};
+use wad::types::{Entry, Wad};
+
+use crate::utils::{
+    constants::{
+        MAX_GOLDSRC_TEXTURE_NAME_LENGTH, MAX_GOLDSRC_TEXTURE_SIZE, PALETTE_PAD_COLOR,
+        PALETTE_TRANSPARENT_COLOR2,
+    },
+    img_stuffs::{mipmaps_from_8bpp, rgba8_to_8bpp, GoldSrcBmp},
+};
+
+/// Spray texture name in tempdecal.wad
+pub const SPRAY_TEXTURE_NAME: &str = "{LOGO";
+/// Sprays cannot have more pixels than this.
+pub const MAX_SPRAY_PIXELS: u32 = 14336;
+/// Decal dimensions are multiples of this.
+const DECAL_SIZE_STEP: u32 = 16;
+
+#[derive(Debug, Clone, Default)]
+pub struct DecalOptions {
+    /// Color of a one color decal. Average color of the image if not set.
+    pub color: Option<[u8; 3]>,
+    /// Keeps the image colors. Transparent pixels become index 255.
+    pub colored: bool,
+}
+
+/// Scales image down to fit `max_pixels` and [`MAX_GOLDSRC_TEXTURE_SIZE`] with dimensions in multiples of 16.
+///
+/// Dimensions are at least 16 so the image is 16x16 when `max_pixels` is less than 256.
+pub fn fit_decal_size(image: RgbaImage, max_pixels: Option<u32>) -> RgbaImage {
+    let (width, height) = image.dimensions();
+
+    let mut scale = (MAX_GOLDSRC_TEXTURE_SIZE as f32 / width.max(height) as f32).min(1.);
+
+    if let Some(max_pixels) = max_pixels {
+        scale = scale.min((max_pixels as f32 / (width * height) as f32).sqrt());
+    }
+
+    let fit = |side: u32| {
+        ((side as f32 * scale) as u32 / DECAL_SIZE_STEP * DECAL_SIZE_STEP).max(DECAL_SIZE_STEP)
+    };
+
+    let (mut new_width, mut new_height) = (fit(width), fit(height));
+
+    // rounding up to 16 could still go over
+    if let Some(max_pixels) = max_pixels {
+        while new_width * new_height > max_pixels
+            && (new_width > DECAL_SIZE_STEP || new_height > DECAL_SIZE_STEP)
+        {
+            if new_width >= new_height {
+                new_width -= DECAL_SIZE_STEP;
+            } else {
+                new_height -= DECAL_SIZE_STEP;
+            }
+        }
+    }
+
+    if (new_width, new_height) == (width, height) {
+        return image;
+    }
+
+    imageops::resize(
+        &image,
+        new_width,
+        new_height,
+        imageops::FilterType::Lanczos3,
+    )
+}
+
+/// Decal opacity of every pixel.
+///
+/// Uses the alpha channel if the image has transparency.
+/// Otherwise darker is more opaque so black on white images work.
+fn decal_alpha(image: &RgbaImage) -> Vec<u8> {
+    let has_transparency = image.pixels().any(|pixel| pixel[3] != 255);
+
+    image
+        .pixels()
+        .map(|pixel| {
+            if has_transparency {
+                pixel[3]
+            } else {
+                let luminance =
+                    0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+
+                255 - luminance.round() as u8
+            }
+        })
+        .collect()
+}
+
+/// Average color weighted by opacity
+fn decal_average_color(image: &RgbaImage, alpha: &[u8]) -> [u8; 3] {
+    let (sum, weight) =
+        image
+            .pixels()
+            .zip(alpha)
+            .fold(([0f64; 3], 0f64), |(mut sum, weight), (pixel, &alpha)| {
+                let alpha = alpha as f64;
+
+                (0..3).for_each(|i| sum[i] += pixel[i] as f64 * alpha);
+
+                (sum, weight + alpha)
+            });
+
+    if weight == 0. {
+        return [0, 0, 0];
+    }
+
+    sum.map(|channel| (channel / weight).round() as u8)
+}
+
+/// One color decal palette, a grayscale ramp and the decal color at index 255
+pub fn decal_palette(color: [u8; 3]) -> Vec<[u8; 3]> {
+    let mut palette = (0..=255u8).map(|i| [i, i, i]).collect::<Vec<[u8; 3]>>();
+    palette[255] = color;
+
+    palette
+}
+
+/// Colored image with transparent pixels at index 255
+fn colored_decal(image: RgbaImage) -> eyre::Result<(Vec<u8>, Vec<[u8; 3]>)> {
+    let has_transparency = image.pixels().any(|pixel| pixel[3] == 0);
+
+    let GoldSrcBmp {
+        mut image,
+        mut palette,
+        ..
+    } = rgba8_to_8bpp(image)?;
+
+    palette.resize(256, PALETTE_PAD_COLOR);
+
+    let transparent_index = palette
+        .iter()
+        .position(|&color| color == PALETTE_TRANSPARENT_COLOR2);
+
+    // swap transparent color with the last one
+    if let (true, Some(transparent_index)) = (has_transparency, transparent_index) {
+        palette.swap(transparent_index, 255);
+
+        image.iter_mut().for_each(|index| {
+            if *index as usize == transparent_index {
+                *index = 255;
+            } else if *index == 255 {
+                *index = transparent_index as u8;
+            }
+        });
+    }
+
+    Ok((image, palette))
+}
+
+/// Makes a decal texture. `{` is added to the name if it is not there.
+///
+/// `max_pixels` limits the image size, such as [`MAX_SPRAY_PIXELS`] for sprays.
+pub fn decal_entry(
+    name: &str,
+    image: RgbaImage,
+    options: &DecalOptions,
+    max_pixels: Option<u32>,
+) -> eyre::Result<Entry> {
+    let name = if name.starts_with('{') {
+        name.to_string()
+    } else {
+        format!("{{{}", name)
+    };
+
+    if name.len() > MAX_GOLDSRC_TEXTURE_NAME_LENGTH {
+        return Err(eyre!(
+            "Decal name \"{}\" is longer than {} characters",
+            name,
+            MAX_GOLDSRC_TEXTURE_NAME_LENGTH
+        ));
+    }
+
+    let image = fit_decal_size(image, max_pixels);
+    let dimensions = image.dimensions();
+
+    let (image, palette) = if options.colored {
+        colored_decal(image)?
+    } else {
+        let alpha = decal_alpha(&image);
+        let color = options
+            .color
+            .unwrap_or_else(|| decal_average_color(&image, &alpha));
+
+        (alpha, decal_palette(color))
+    };
+
+    let [mip0, mip1, mip2, mip3] = mipmaps_from_8bpp(image, dimensions);
+
+    Ok(Entry::new(
+        name,
+        dimensions,
+        &[&mip0, &mip1, &mip2, &mip3],
+        palette,
+    ))
+}
+
+/// Makes tempdecal.wad with the spray.
+pub fn spray_wad(image: RgbaImage, options: &DecalOptions) -> eyre::Result<Wad> {
+    let mut wad = Wad::new();
+
+    wad.entries.push(decal_entry(
+        SPRAY_TEXTURE_NAME,
+        image,
+        options,
+        Some(MAX_SPRAY_PIXELS),
+    )?);
+    wad.header.num_dirs = 1;
+
+    Ok(wad)
+}
+
+pub fn spray_wad_from_file(
+    image_path: impl AsRef<Path> + Into<PathBuf>,
+    options: &DecalOptions,
+    out_path: impl AsRef<Path> + Into<PathBuf>,
+) -> eyre::Result<()> {
+    let image = image::open(image_path.as_ref())?.into_rgba8();
+
+    spray_wad(image, options)?.write_to_file(out_path)
+}
+
+/// Adds a decal to a WAD such as decals.wad, replacing the decal with the same name.
+///
+/// The WAD is created if it does not exist. Decal name is the image file name if not set.
+pub fn add_decal_to_wad_file(
+    image_path: impl AsRef<Path> + Into<PathBuf>,
+    name: Option<&str>,
+    options: &DecalOptions,
+    wad_path: impl AsRef<Path> + Into<PathBuf>,
+) -> eyre::Result<()> {
+    let image_path = image_path.as_ref();
+    let wad_path = wad_path.as_ref();
+
+    let image = image::open(image_path)?.into_rgba8();
+    let name = match name {
+        Some(name) => name,
+        None => image_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or(eyre!("Cannot get decal name from {}", image_path.display()))?,
+    };
+
+    let entry = decal_entry(name, image, options, None)?;
+
+    let mut wad = if wad_path.exists() {
+        Wad::from_file(wad_path)?
+    } else {
+        Wad::new()
+    };
+
+    let texture_name = entry.texture_name();
+
+    match wad
+        .entries
+        .iter()
+        .position(|other| other.texture_name().eq_ignore_ascii_case(&texture_name))
+    {
+        Some(index) => wad.entries[index] = entry,
+        None => wad.entries.push(entry),
+    }
+
+    wad.header.num_dirs = wad.entries.len() as i32;
+
+    wad.write_to_file(wad_path)
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgba;
+    use wad::types::FileEntry;
+
+    use super::*;
+
+    #[test]
+    fn size_rules() {
+        let image = fit_decal_size(RgbaImage::new(300, 100), Some(MAX_SPRAY_PIXELS));
+        let (width, height) = image.dimensions();
+
+        assert_eq!(width % 16, 0);
+        assert_eq!(height % 16, 0);
+        assert!(width * height <= MAX_SPRAY_PIXELS);
+
+        let image = fit_decal_size(RgbaImage::new(1000, 8), None);
+        assert_eq!(image.dimensions(), (512, 16));
+
+        let image = fit_decal_size(RgbaImage::new(64, 32), None);
+        assert_eq!(image.dimensions(), (64, 32));
+
+        // cannot go smaller than 16x16
+        let image = fit_decal_size(RgbaImage::new(300, 100), Some(100));
+        assert_eq!(image.dimensions(), (16, 16));
+    }
+
+    #[test]
+    fn one_color_decal() {
+        // black circle on white
+        let image = RgbaImage::from_fn(32, 32, |x, y| {
+            if (x as i32 - 16).pow(2) + (y as i32 - 16).pow(2) < 100 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+
+        let entry = decal_entry("hole", image, &DecalOptions::default(), None).unwrap();
+
+        assert_eq!(entry.texture_name(), "{hole");
+
+        let FileEntry::MipTex(miptex) = &entry.file_entry else {
+            panic!("not miptex")
+        };
+
+        let palette = miptex.palette.get_bytes();
+        assert_eq!(palette[255], [0, 0, 0]);
+        assert_eq!(palette[128], [128, 128, 128]);
+
+        let image = miptex.mip_images[0].data.get_bytes();
+        assert_eq!(image[16 * 32 + 16], 255);
+        assert_eq!(image[0], 0);
+
+        assert_eq!(miptex.mip_images[3].data.get_bytes().len(), 4 * 4);
+    }
+
+    #[test]
+    fn decal_color() {
+        let image = RgbaImage::from_fn(16, 16, |x, _| Rgba([255, 0, 0, (x * 16) as u8]));
+
+        let entry = decal_entry("{red", image.clone(), &DecalOptions::default(), None).unwrap();
+        assert_eq!(entry.file_entry.palette()[255], [255, 0, 0]);
+        assert_eq!(entry.file_entry.image()[1], 16);
+
+        let options = DecalOptions {
+            color: Some([0, 255, 0]),
+            colored: false,
+        };
+        let entry = decal_entry("{red", image, &options, None).unwrap();
+        assert_eq!(entry.file_entry.palette()[255], [0, 255, 0]);
+
+        assert!(decal_entry("a_very_long_name", RgbaImage::new(16, 16), &options, None).is_err());
+    }
+
+    #[test]
+    fn colored_spray() {
+        let image = RgbaImage::from_fn(64, 64, |x, _| {
+            if x < 32 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([255, 0, 0, 255])
+            }
+        });
+
+        let options = DecalOptions {
+            color: None,
+            colored: true,
+        };
+
+        let wad = spray_wad(image, &options).unwrap();
+        let wad = Wad::from_bytes(&wad.write_to_bytes()).unwrap();
+
+        let entry = &wad.entries[0];
+
+        assert_eq!(entry.texture_name(), SPRAY_TEXTURE_NAME);
+        assert_eq!(entry.file_entry.palette()[255], PALETTE_TRANSPARENT_COLOR2);
+        assert_eq!(entry.file_entry.image()[0], 255);
+        assert_eq!(
+            entry.file_entry.palette()[entry.file_entry.image()[63] as usize],
+            [255, 0, 0]
+        );
+    }
+}
@@ -482,7 +482,7 @@ impl Map2Mdl {
         }
 
         // very convoluted error propagating
-        let map_file = self.map.as_ref().map(Map::from_file);
+        let map_file = self.map.as_ref().map(Map::from_file_with_warnings);
 
         if let Some(Err(err)) = &map_file {
             return err!("Cannot parse map file: {}", err);
@@ -490,7 +490,13 @@ impl Map2Mdl {
 
         let mut map_file = if let Some(map_file) = map_file {
             self.log("Converting map");
-            map_file.ok()
+            map_file.ok().map(|(map, warnings)| {
+                warnings
+                    .iter()
+                    .for_each(|warning| self.log(format!("Warning: {}", warning).as_str()));
+
+                map
+            })
         } else {
             None
         };
@@ -975,6 +981,8 @@ However, it will still turn {} into model displaying entities such as cycler_spr
                                         ("model".to_owned(), curr_model_name),
                                     ]),
                                     brushes: None,
+                                    comments: None,
+                                    trailing_comments: vec![],
                                 };
 
                                 entities_to_insert.push(new_entity);
@@ -1000,7 +1008,7 @@ However, it will still turn {} into model displaying entities such as cycler_spr
                                 // remove origin because brush entity
                                 // otherwise the editor would confuse
                                 // maybe need to remove more in the future if there's problems
-                                clip_brush_entity.attributes.remove("origin");
+                                clip_brush_entity.attributes.shift_remove("origin");
 
                                 entities_to_insert.push(clip_brush_entity);
                             }
@@ -1028,6 +1036,8 @@ However, it will still turn {} into model displaying entities such as cycler_spr
                                         "func_detail".to_owned(),
                                     )]),
                                     brushes: vec![new_brush].into(),
+                                    comments: None,
+                                    trailing_comments: vec![],
                                 };
 
                                 entities_to_insert.push(new_brush_entity);
@@ -1054,8 +1064,10 @@ However, it will still turn {} into model displaying entities such as cycler_spr
                     });
 
                 // lastly^2 write the map file
-                self.log(format!("Writing new {}", self.map.as_ref().unwrap().display()).as_str());
-                map.write(self.map.as_ref().unwrap())?;
+                let map_path = map_output_path(self.map.as_ref().unwrap());
+
+                self.log(format!("Writing new {}", map_path.display()).as_str());
+                map.write(map_path)?;
             } else {
                 self.log("Converting whole map file");
 
@@ -1122,10 +1134,44 @@ However, it will still turn {} into model displaying entities such as cycler_spr
     }
 }
 
+/// Where entity mode writes the map with the new entities.
+///
+/// RMF and JMF would lose editor data like paths and the camera so they get a .map next to them instead.
+fn map_output_path(map_path: &Path) -> PathBuf {
+    let is_editor_format = map_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("rmf") || extension.eq_ignore_ascii_case("jmf")
+        });
+
+    if is_editor_format {
+        map_path.with_extension("map")
+    } else {
+        map_path.to_path_buf()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn editor_format_output_path() {
+        assert_eq!(
+            map_output_path(Path::new("maps/box.map")),
+            PathBuf::from("maps/box.map")
+        );
+        assert_eq!(
+            map_output_path(Path::new("maps/box.rmf")),
+            PathBuf::from("maps/box.map")
+        );
+        assert_eq!(
+            map_output_path(Path::new("maps/box.JMF")),
+            PathBuf::from("maps/box.map")
+        );
+    }
+
     #[test]
     fn run() {
         let mut binding = Map2Mdl::default();
@@ -0,0 +1,238 @@
+//! Makes a WAD with only the textures a map uses.
+//!
+//! Textures come from the WADs in worldspawn "wad" key. It is the same as `-wadinclude` without the compiler.
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use bsp::Bsp;
+use eyre::eyre;
+use map::Map;
+use wad::types::Wad;
+
+use crate::utils::{map_stuffs::textures_used_in_map, wad_stuffs::SimpleWad};
+
+/// Finds WAD files from worldspawn "wad" key.
+///
+/// Paths in the key are usually absolute Windows paths from the mapper's computer.
+/// If a path does not exist, its file name is looked up in `search_dirs`.
+///
+/// Returns found paths and paths that cannot be found.
+pub fn wad_paths_from_wad_key(
+    wad_key: &str,
+    search_dirs: &[PathBuf],
+) -> (Vec<PathBuf>, Vec<String>) {
+    let mut found = vec![];
+    let mut not_found = vec![];
+
+    for wad_path in wad_key
+        .split(';')
+        .map(|path| path.trim())
+        .filter(|path| !path.is_empty())
+    {
+        if Path::new(wad_path).is_file() {
+            found.push(PathBuf::from(wad_path));
+            continue;
+        }
+
+        let file_name = wad_path.rsplit(['/', '\\']).next().unwrap_or(wad_path);
+
+        match search_dirs
+            .iter()
+            .map(|dir| dir.join(file_name))
+            .find(|path| path.is_file())
+        {
+            Some(path) => found.push(path),
+            None => not_found.push(wad_path.to_string()),
+        }
+    }
+
+    (found, not_found)
+}
+
+/// Textures and worldspawn "wad" key of a .map or .bsp
+///
+/// Textures embedded in a BSP are not included.
+pub fn textures_and_wad_key_from_file(
+    path: impl AsRef<Path> + Into<PathBuf>,
+) -> eyre::Result<(HashSet<String>, String)> {
+    let path = path.as_ref();
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("map") => {
+            let map = Map::from_file(path)?;
+
+            let wad_key = map
+                .entities
+                .first()
+                .and_then(|worldspawn| worldspawn.attributes.get("wad"))
+                .cloned()
+                .unwrap_or_default();
+
+            Ok((textures_used_in_map(&map), wad_key))
+        }
+        Some("bsp") => {
+            let bsp = Bsp::from_file(path)?;
+
+            let wad_key = bsp
+                .entities
+                .first()
+                .and_then(|worldspawn| worldspawn.get("wad"))
+                .cloned()
+                .unwrap_or_default();
+
+            let textures = bsp
+                .textures
+                .iter()
+                .filter(|texture| texture.is_external())
+                .map(|texture| texture.texture_name.get_string())
+                .collect();
+
+            Ok((textures, wad_key))
+        }
+        _ => Err(eyre!("{} is not a .map or .bsp", path.display())),
+    }
+}
+
+/// Copies `textures` from `wads` into a new WAD. Texture names are not case sensitive.
+///
+/// Returns the new WAD and textures that cannot be found, sorted.
+pub fn minimal_wad(textures: &HashSet<String>, wads: &[Wad]) -> (Wad, Vec<String>) {
+    let simple_wad = SimpleWad::from_wads(wads);
+
+    let mut textures = textures.iter().collect::<Vec<&String>>();
+    textures.sort();
+
+    let mut res = Wad::new();
+    let mut missing = vec![];
+
+    for texture in textures {
+        let wad_file_index = simple_wad
+            .get(texture)
+            .or_else(|| {
+                simple_wad
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(texture))
+                    .map(|(_, entry)| entry)
+            })
+            .map(|entry| entry.wad_file_index());
+
+        let entry = wad_file_index.and_then(|wad_file_index| {
+            wads[wad_file_index]
+                .entries
+                .iter()
+                .find(|entry| entry.texture_name().eq_ignore_ascii_case(texture))
+        });
+
+        match entry {
+            Some(entry) => res.entries.push(entry.clone()),
+            None => missing.push(texture.to_string()),
+        }
+    }
+
+    res.header.num_dirs = res.entries.len() as i32;
+
+    (res, missing)
+}
+
+/// Writes a WAD with the textures of a .map or .bsp from the WADs in its "wad" key.
+///
+/// WADs are also looked up next to the map and in `search_dirs`.
+/// Returns the missing textures and WADs as messages.
+pub fn minimal_wad_from_file(
+    path: impl AsRef<Path> + Into<PathBuf>,
+    search_dirs: &[PathBuf],
+    out_path: impl AsRef<Path> + Into<PathBuf>,
+) -> eyre::Result<Vec<String>> {
+    let path = path.as_ref();
+    let (textures, wad_key) = textures_and_wad_key_from_file(path)?;
+
+    let mut search_dirs = search_dirs.to_vec();
+
+    if let Some(parent) = path.parent() {
+        search_dirs.insert(0, parent.to_path_buf());
+    }
+
+    let (wad_paths, missing_wads) = wad_paths_from_wad_key(&wad_key, &search_dirs);
+
+    let wads = wad_paths
+        .iter()
+        .map(Wad::from_file)
+        .collect::<eyre::Result<Vec<Wad>>>()?;
+
+    let (wad, missing_textures) = minimal_wad(&textures, &wads);
+
+    wad.write_to_file(out_path)?;
+
+    Ok(missing_wads
+        .into_iter()
+        .map(|wad| format!("Cannot find WAD: {}", wad))
+        .chain(
+            missing_textures
+                .into_iter()
+                .map(|texture| format!("Cannot find texture: {}", texture)),
+        )
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wad_key() {
+        let (found, not_found) = wad_paths_from_wad_key(
+            "\\half-life\\valve\\wad_test.wad;C:\\missing.wad;wad/test/wad_test2.wad;",
+            &[PathBuf::from("wad/test")],
+        );
+
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("wad/test/wad_test.wad"),
+                PathBuf::from("wad/test/wad_test2.wad")
+            ]
+        );
+        assert_eq!(not_found, vec!["C:\\missing.wad"]);
+    }
+
+    #[test]
+    fn from_map() {
+        let map_path = std::env::temp_dir().join("gchimp_minimal_wad.map");
+        let out_path = std::env::temp_dir().join("gchimp_minimal_wad.wad");
+
+        // one texture in a different case and one that does not exist
+        let brush = |texture: &str| {
+            [
+                "( 0 0 0 ) ( 0 1 0 ) ( 1 0 0 )",
+                "( 0 0 16 ) ( 1 0 16 ) ( 0 1 16 )",
+                "( 0 0 0 ) ( 1 0 0 ) ( 0 0 1 )",
+                "( 0 16 0 ) ( 0 16 1 ) ( 1 16 0 )",
+                "( 0 0 0 ) ( 0 0 1 ) ( 0 1 0 )",
+                "( 16 0 0 ) ( 16 1 0 ) ( 16 0 1 )",
+            ]
+            .iter()
+            .map(|points| format!("{} {} [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1\n", points, texture))
+            .collect::<String>()
+        };
+
+        let text = format!(
+            "{{\n\"classname\" \"worldspawn\"\n\"wad\" \"\\\\somewhere\\\\wad_test2.wad\"\n{{\n{}}}\n{{\n{}}}\n}}\n",
+            brush("BLACK"),
+            brush("missing")
+        );
+
+        std::fs::write(&map_path, text).unwrap();
+
+        let missing =
+            minimal_wad_from_file(&map_path, &[PathBuf::from("wad/test")], &out_path).unwrap();
+
+        assert_eq!(missing, vec!["Cannot find texture: missing"]);
+
+        let wad = Wad::from_file(&out_path).unwrap();
+
+        assert_eq!(wad.entries.len(), 1);
+        assert_eq!(wad.entries[0].texture_name(), "black");
+    }
+}
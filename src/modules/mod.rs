@@ -1,16 +1,26 @@
 pub mod blender_lightmap_baker_helper;
+pub mod brush_csg;
+pub mod bsp2map;
+pub mod bsp2smd;
+pub mod bsp_lightmap;
 pub mod check_illegal_brush;
 pub mod check_missing_texture;
 pub mod custom_script;
+pub mod decal;
 pub mod demdoc;
 pub mod duplicate_triangle;
 pub mod find_low_scaling;
 pub mod light_scale;
 pub mod map2mdl;
+pub mod minimal_wad;
+pub mod ripent;
 pub mod rotate_prop_static;
 pub mod s2g;
 pub mod skymod;
 pub mod split_model;
 pub mod textile;
 pub mod texture_scale;
+pub mod vmf2map;
 pub mod waddy;
+pub mod wadfont;
+pub mod wadtool;
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use bsp::Bsp;
+
+/// Writes the entity lump of a BSP into a .ent file.
+pub fn ripent_export(bsp_path: impl AsRef<Path>, ent_path: impl AsRef<Path>) -> eyre::Result<()> {
+    let bsp = Bsp::from_file(bsp_path.as_ref())?;
+
+    bsp.export_entities_to_file(ent_path.as_ref())
+}
+
+/// Replaces the entity lump of a BSP with a .ent file and writes the BSP to `out_path`.
+///
+/// Nothing is written if the .ent file is invalid.
+pub fn ripent_import(
+    bsp_path: impl AsRef<Path>,
+    ent_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+) -> eyre::Result<()> {
+    let mut bsp = Bsp::from_file(bsp_path.as_ref())?;
+
+    bsp.import_entities_from_file(ent_path.as_ref())?;
+
+    bsp.write_to_file(out_path.as_ref())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn export_import() {
+        let bsp_path = "bsp/src/tests/normal.bsp";
+        let ent_path = std::env::temp_dir().join("gchimp_ripent_normal.ent");
+        let out_path = std::env::temp_dir().join("gchimp_ripent_normal.bsp");
+
+        ripent_export(bsp_path, &ent_path).unwrap();
+
+        let text = std::fs::read_to_string(&ent_path).unwrap();
+        let edited = text.replacen(
+            "\"classname\" \"worldspawn\"",
+            "\"classname\" \"worldspawn\"\n\"message\" \"ripent\"",
+            1,
+        );
+        std::fs::write(&ent_path, &edited).unwrap();
+
+        ripent_import(bsp_path, &ent_path, &out_path).unwrap();
+
+        let bsp = Bsp::from_file(&out_path).unwrap();
+        assert_eq!(bsp.export_entities(), edited);
+        assert_eq!(bsp.entities[0].get("message").unwrap(), "ripent");
+    }
+}
@@ -10,7 +10,7 @@ use ndarray::prelude::*;
 
 use crate::utils::{
     constants::{MAX_GOLDSRC_MODEL_TEXTURE_COUNT, STUDIOMDL_ERROR_PATTERN},
-    img_stuffs::{rgba8_to_8bpp, write_8bpp_to_file, GoldSrcBmp},
+    img_stuffs::{rgba8_to_8bpp_with_options, write_8bpp_to_file, GoldSrcBmp, QuantizeOptions},
     run_bin::run_studiomdl,
 };
 
@@ -21,6 +21,7 @@ pub struct SkyModOptions {
     pub convert_texture: bool,
     pub flatshade: bool,
     pub output_name: String,
+    pub quantize_options: QuantizeOptions,
 }
 
 impl Default for SkyModOptions {
@@ -31,6 +32,7 @@ impl Default for SkyModOptions {
             convert_texture: true,
             flatshade: true,
             output_name: "skybox".to_string(),
+            quantize_options: QuantizeOptions::default(),
         }
     }
 }
@@ -126,6 +128,11 @@ impl SkyModBuilder {
         self
     }
 
+    pub fn quantize_options(&mut self, a: QuantizeOptions) -> &mut Self {
+        self.options.quantize_options = a;
+        self
+    }
+
     pub fn work(&self) -> eyre::Result<()> {
         // check stuffs
         for i in 0..6 {
@@ -247,7 +254,8 @@ impl SkyModBuilder {
                             image: img,
                             palette,
                             dimensions: dimension,
-                        } = rgba8_to_8bpp(section).unwrap();
+                        } = rgba8_to_8bpp_with_options(section, &self.options.quantize_options)
+                            .unwrap();
 
                         write_8bpp_to_file(
                             &img,
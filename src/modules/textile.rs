@@ -11,7 +11,8 @@ use rayon::prelude::*;
 use vtf::Vtf;
 
 use crate::utils::img_stuffs::{
-    eight_bpp_transparent_img, rgba8_to_8bpp, tile_and_resize, write_8bpp_to_file, GoldSrcBmp,
+    eight_bpp_transparent_img, rgba8_to_8bpp_with_options, tile_and_resize, write_8bpp_to_file,
+    GoldSrcBmp, QuantizeOptions,
 };
 
 pub struct TexTileBuilder {
@@ -32,6 +33,7 @@ pub struct TexTileOptions {
     ///
     /// Appends "`_<scalar>`" if tiling
     pub change_name: bool,
+    pub quantize_options: QuantizeOptions,
 }
 
 impl Default for TexTileOptions {
@@ -48,6 +50,7 @@ impl Default for TexTileOptions {
             is_transparent: false,
             transparent_threshold: 0.75,
             change_name: true,
+            quantize_options: QuantizeOptions::default(),
         }
     }
 }
@@ -183,6 +186,11 @@ impl TexTileBuilder {
         self
     }
 
+    pub fn quantize_options(&mut self, a: QuantizeOptions) -> &mut Self {
+        self.options.quantize_options = a;
+        self
+    }
+
     pub fn work(&mut self) -> eyre::Result<()> {
         // transparent shoudl be the last step
         // the reason is that transparent pixel could be interpolated when tiling or scaled down
@@ -258,7 +266,7 @@ impl TexTileBuilder {
         let eight_bpps = rgba_images
             .into_par_iter()
             .map(|(path, img)| {
-                let new_img = rgba8_to_8bpp(img);
+                let new_img = rgba8_to_8bpp_with_options(img, &self.options.quantize_options);
 
                 if new_img.is_err() {
                     let log = format!(
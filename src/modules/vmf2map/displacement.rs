@@ -0,0 +1,260 @@
+use glam::DVec3;
+use map::{base_texture_axes, brush_plane_winding, Brush, BrushPlane};
+
+use crate::utils::{constants::NULL_TEXTURE, misc::parse_triplet};
+
+use super::parser::VmfNode;
+
+/// How deep the triangle brushes go below the displacement surface.
+static DISPLACEMENT_THICKNESS: f64 = 8.;
+/// Triangles steeper than this relative to the face are extruded along their own normal.
+static MIN_EXTRUDE_ALIGNMENT: f64 = 0.1;
+/// Triangles smaller than this are skipped.
+static MIN_TRIANGLE_AREA: f64 = 0.01;
+
+struct DispInfo {
+    power: u32,
+    start_position: DVec3,
+    elevation: f64,
+    normals: Vec<DVec3>,
+    distances: Vec<f64>,
+    offsets: Vec<DVec3>,
+}
+
+impl DispInfo {
+    fn size(&self) -> usize {
+        (1 << self.power) + 1
+    }
+}
+
+/// Numbers of every row in a grid block like `normals` or `distances`.
+///
+/// Missing block is all zeros.
+fn parse_rows(
+    dispinfo: &VmfNode,
+    name: &str,
+    size: usize,
+    numbers_per_vertex: usize,
+) -> eyre::Result<Vec<f64>> {
+    let Some(node) = dispinfo.child(name) else {
+        return Ok(vec![0.; size * size * numbers_per_vertex]);
+    };
+
+    (0..size).try_fold(vec![], |mut acc, row_index| {
+        let row = node.get(&format!("row{}", row_index)).ok_or(eyre::eyre!(
+            "Displacement {} is missing row {}.",
+            name,
+            row_index
+        ))?;
+
+        let numbers = row
+            .split_ascii_whitespace()
+            .map(|number| number.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()?;
+
+        if numbers.len() != size * numbers_per_vertex {
+            return Err(eyre::eyre!(
+                "Displacement {} row {} has {} numbers instead of {}.",
+                name,
+                row_index,
+                numbers.len(),
+                size * numbers_per_vertex
+            ));
+        }
+
+        acc.extend(numbers);
+
+        Ok(acc)
+    })
+}
+
+fn to_dvec3s(numbers: Vec<f64>) -> Vec<DVec3> {
+    numbers
+        .chunks_exact(3)
+        .map(|chunk| DVec3::new(chunk[0], chunk[1], chunk[2]))
+        .collect()
+}
+
+impl TryFrom<&VmfNode> for DispInfo {
+    type Error = eyre::Report;
+
+    fn try_from(dispinfo: &VmfNode) -> Result<Self, Self::Error> {
+        let power = dispinfo
+            .get("power")
+            .ok_or(eyre::eyre!("Displacement does not have power."))?
+            .parse::<u32>()?;
+
+        if !(2..=4).contains(&power) {
+            return Err(eyre::eyre!(
+                "Displacement power {} is not supported.",
+                power
+            ));
+        }
+
+        let start_position = dispinfo
+            .get("startposition")
+            .ok_or(eyre::eyre!("Displacement does not have start position."))?
+            .replace(['[', ']'], " ");
+        let start_position = DVec3::from(parse_triplet(&start_position)?);
+
+        let elevation = dispinfo
+            .get("elevation")
+            .map(|elevation| elevation.parse::<f64>())
+            .transpose()?
+            .unwrap_or(0.);
+
+        let size = (1 << power) + 1;
+
+        Ok(Self {
+            power,
+            start_position,
+            elevation,
+            normals: to_dvec3s(parse_rows(dispinfo, "normals", size, 3)?),
+            distances: parse_rows(dispinfo, "distances", size, 1)?,
+            offsets: to_dvec3s(parse_rows(dispinfo, "offsets", size, 3)?),
+        })
+    }
+}
+
+/// Plane points with the normal pointing away from the center of the brush.
+fn outward_plane_points(
+    (p1, p2, p3): (DVec3, DVec3, DVec3),
+    center: DVec3,
+) -> (DVec3, DVec3, DVec3) {
+    if (p1 - p2).cross(p3 - p2).dot(p1 - center) < 0. {
+        (p3, p2, p1)
+    } else {
+        (p1, p2, p3)
+    }
+}
+
+/// A prism under the triangle with the top face textured like the displacement face.
+fn triangle_brush(triangle: [DVec3; 3], up: DVec3, surface: &BrushPlane) -> Option<Brush> {
+    let [a, b, c] = triangle;
+    let triangle_normal = (b - a).cross(c - a);
+
+    if triangle_normal.length() / 2. < MIN_TRIANGLE_AREA {
+        return None;
+    }
+
+    let triangle_normal = triangle_normal.normalize();
+    let extrude = if triangle_normal.dot(up).abs() > MIN_EXTRUDE_ALIGNMENT {
+        up
+    } else if triangle_normal.dot(up) < 0. {
+        -triangle_normal
+    } else {
+        triangle_normal
+    } * DISPLACEMENT_THICKNESS;
+
+    let [a2, b2, c2] = [a - extrude, b - extrude, c - extrude];
+    let center = (a + b + c + a2 + b2 + c2) / 6.;
+
+    let null_plane = |points: (DVec3, DVec3, DVec3)| {
+        let (p1, p2, p3) = outward_plane_points(points, center);
+        let (u, v) = base_texture_axes((p1 - p2).cross(p3 - p2).normalize());
+
+        BrushPlane {
+            p1,
+            p2,
+            p3,
+            texture_name: NULL_TEXTURE.to_string(),
+            u: u.extend(0.),
+            v: v.extend(0.),
+            rotation: 0.,
+            u_scale: 1.,
+            v_scale: 1.,
+        }
+    };
+
+    let (p1, p2, p3) = outward_plane_points((a, b, c), center);
+    let top = BrushPlane {
+        p1,
+        p2,
+        p3,
+        ..surface.clone()
+    };
+
+    Some(Brush {
+        planes: vec![
+            top,
+            null_plane((a2, b2, c2)),
+            null_plane((a, b, a2)),
+            null_plane((b, c, b2)),
+            null_plane((c, a, c2)),
+        ],
+        comments: None,
+    })
+}
+
+/// Approximates a displacement on a brush face with one brush per triangle.
+pub fn displacement_to_brushes(
+    brush: &Brush,
+    plane_index: usize,
+    dispinfo: &VmfNode,
+) -> eyre::Result<Vec<Brush>> {
+    let dispinfo = DispInfo::try_from(dispinfo)?;
+    let surface = &brush.planes[plane_index];
+    let face_normal = surface.normal();
+
+    let mut corners = brush_plane_winding(brush, plane_index)
+        .ok_or(eyre::eyre!("Displacement face does not exist."))?;
+
+    if corners.len() != 4 {
+        return Err(eyre::eyre!(
+            "Displacement face has {} corners instead of 4.",
+            corners.len()
+        ));
+    }
+
+    // grid starts from the corner closest to start position
+    let start_index = (0..4)
+        .min_by(|&a, &b| {
+            corners[a]
+                .distance(dispinfo.start_position)
+                .total_cmp(&corners[b].distance(dispinfo.start_position))
+        })
+        .unwrap();
+
+    corners.rotate_left(start_index);
+
+    let size = dispinfo.size();
+    let steps = (size - 1) as f64;
+
+    // rows go from the first corner to the second, columns from the first corner to the fourth
+    let vertex = |row: usize, column: usize| {
+        let left = corners[0].lerp(corners[1], row as f64 / steps);
+        let right = corners[3].lerp(corners[2], row as f64 / steps);
+        let index = row * size + column;
+
+        left.lerp(right, column as f64 / steps)
+            + dispinfo.normals[index] * dispinfo.distances[index]
+            + dispinfo.offsets[index]
+            + face_normal * dispinfo.elevation
+    };
+
+    let mut res = vec![];
+
+    for row in 0..(size - 1) {
+        for column in 0..(size - 1) {
+            let a = vertex(row, column);
+            let b = vertex(row + 1, column);
+            let c = vertex(row + 1, column + 1);
+            let d = vertex(row, column + 1);
+
+            // alternate the diagonal like the engine does
+            let triangles = if (row + column) % 2 == 0 {
+                [[a, b, c], [a, c, d]]
+            } else {
+                [[a, b, d], [b, c, d]]
+            };
+
+            triangles.into_iter().for_each(|triangle| {
+                if let Some(brush) = triangle_brush(triangle, face_normal, surface) {
+                    res.push(brush);
+                }
+            });
+        }
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,592 @@
+//! Converts Source VMF into GoldSrc Valve220 .map
+use glam::DVec4;
+use map::{Attributes, Brush, BrushPlane, Entity, Map};
+
+use crate::{
+    modules::rotate_prop_static::rotate_prop_static,
+    utils::{
+        constants::{CLIP_TEXTURE, MAX_GOLDSRC_TEXTURE_NAME_LENGTH, NULL_TEXTURE, ORIGIN_TEXTURE},
+        misc::{fix_backslash, parse_triplet},
+    },
+};
+
+use displacement::displacement_to_brushes;
+
+mod displacement;
+mod parser;
+
+pub use parser::{Vmf, VmfNode};
+
+/// Source tool materials and their GoldSrc textures.
+static TOOL_TEXTURES: &[(&str, &str)] = &[
+    ("tools/toolsnodraw", NULL_TEXTURE),
+    ("tools/toolsareaportal", NULL_TEXTURE),
+    ("tools/toolsoccluder", NULL_TEXTURE),
+    ("tools/toolsclip", CLIP_TEXTURE),
+    ("tools/toolsplayerclip", CLIP_TEXTURE),
+    ("tools/toolsnpcclip", CLIP_TEXTURE),
+    ("tools/toolsinvisible", CLIP_TEXTURE),
+    ("tools/toolsskybox", "sky"),
+    ("tools/toolsskybox2d", "sky"),
+    ("tools/toolstrigger", "AAATRIGGER"),
+    ("tools/toolsorigin", ORIGIN_TEXTURE),
+    ("tools/toolshint", "HINT"),
+    ("tools/toolsskip", "SKIP"),
+];
+
+/// Source classnames and their GoldSrc equivalents.
+static ENTITY_CLASSNAMES: &[(&str, &str)] = &[
+    ("info_player_terrorist", "info_player_deathmatch"),
+    ("info_player_counterterrorist", "info_player_start"),
+    ("info_player_teamspawn", "info_player_deathmatch"),
+    ("info_player_combine", "info_player_deathmatch"),
+    ("info_player_rebel", "info_player_deathmatch"),
+    ("func_brush", "func_wall"),
+    ("func_lod", "func_wall"),
+    ("func_physbox", "func_pushable"),
+    ("light_dynamic", "light"),
+    ("env_sprite_oriented", "env_sprite"),
+];
+
+/// Source entities that do nothing in GoldSrc.
+static DROPPED_CLASSNAMES: &[&str] = &[
+    "env_cubemap",
+    "env_fog_controller",
+    "env_tonemap_controller",
+    "shadow_control",
+    "water_lod_control",
+    "sky_camera",
+    "func_areaportal",
+    "func_areaportalwindow",
+    "func_occluder",
+    "info_overlay",
+    "info_lighting",
+];
+
+/// Props become prop_static so they are rotated the same way before becoming a model entity.
+static PROP_CLASSNAMES: &[&str] = &[
+    "prop_static",
+    "prop_dynamic",
+    "prop_dynamic_override",
+    "prop_physics",
+    "prop_physics_override",
+    "prop_physics_multiplayer",
+];
+static PROP_STATIC_CLASSNAME: &str = "prop_static";
+static PROP_NEW_CLASSNAME: &str = "cycler_sprite";
+
+/// Reduces a material path to a WAD texture name.
+fn material_to_texture(material: &str) -> String {
+    let material = fix_backslash(material);
+    let lowercase = material.to_lowercase();
+
+    if let Some((_, texture)) = TOOL_TEXTURES.iter().find(|(tool, _)| *tool == lowercase) {
+        return texture.to_string();
+    }
+
+    material
+        .rsplit('/')
+        .next()
+        .unwrap_or(&material)
+        .chars()
+        .take(MAX_GOLDSRC_TEXTURE_NAME_LENGTH)
+        .collect()
+}
+
+/// "(x y z) (x y z) (x y z)"
+fn parse_side_plane(plane: &str) -> eyre::Result<[[f64; 3]; 3]> {
+    let points = plane
+        .split(')')
+        .map(|point| point.trim().trim_start_matches('('))
+        .filter(|point| !point.is_empty())
+        .map(parse_triplet)
+        .collect::<eyre::Result<Vec<[f64; 3]>>>()?;
+
+    if points.len() != 3 {
+        return Err(eyre::eyre!("Cannot parse side plane: {}", plane));
+    }
+
+    Ok([points[0], points[1], points[2]])
+}
+
+/// "[x y z offset] scale"
+fn parse_side_axis(axis: &str) -> eyre::Result<(DVec4, f64)> {
+    let numbers = axis
+        .replace(['[', ']'], " ")
+        .split_ascii_whitespace()
+        .map(|number| number.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()?;
+
+    if numbers.len() != 5 {
+        return Err(eyre::eyre!("Cannot parse side texture axis: {}", axis));
+    }
+
+    Ok((
+        DVec4::new(numbers[0], numbers[1], numbers[2], numbers[3]),
+        numbers[4],
+    ))
+}
+
+fn side_to_brush_plane(side: &VmfNode) -> eyre::Result<BrushPlane> {
+    let [p1, p2, p3] = parse_side_plane(
+        side.get("plane")
+            .ok_or(eyre::eyre!("Side does not have plane."))?,
+    )?;
+    let (u, u_scale) = parse_side_axis(
+        side.get("uaxis")
+            .ok_or(eyre::eyre!("Side does not have U axis."))?,
+    )?;
+    let (v, v_scale) = parse_side_axis(
+        side.get("vaxis")
+            .ok_or(eyre::eyre!("Side does not have V axis."))?,
+    )?;
+    let rotation = side
+        .get("rotation")
+        .map(|rotation| rotation.parse::<f64>())
+        .transpose()?
+        .unwrap_or(0.);
+
+    Ok(BrushPlane {
+        p1: p1.into(),
+        p2: p2.into(),
+        p3: p3.into(),
+        texture_name: material_to_texture(side.get("material").unwrap_or(NULL_TEXTURE)),
+        u,
+        v,
+        rotation,
+        u_scale,
+        v_scale,
+    })
+}
+
+/// A solid with displacements becomes the triangle brushes of its displacements.
+fn solid_to_brushes(solid: &VmfNode) -> eyre::Result<Vec<Brush>> {
+    let brush = Brush {
+        planes: solid
+            .children_named("side")
+            .map(side_to_brush_plane)
+            .collect::<eyre::Result<Vec<BrushPlane>>>()?,
+        comments: None,
+    };
+
+    let displacements = solid
+        .children_named("side")
+        .enumerate()
+        .filter_map(|(plane_index, side)| side.child("dispinfo").map(|disp| (plane_index, disp)))
+        .collect::<Vec<(usize, &VmfNode)>>();
+
+    if displacements.is_empty() {
+        return Ok(vec![brush]);
+    }
+
+    displacements
+        .into_iter()
+        .try_fold(vec![], |mut acc, (plane_index, dispinfo)| {
+            acc.extend(displacement_to_brushes(&brush, plane_index, dispinfo)?);
+            Ok(acc)
+        })
+}
+
+/// Children with the name including the ones hidden in the editor.
+fn visible_and_hidden<'a>(node: &'a VmfNode, name: &'a str) -> impl Iterator<Item = &'a VmfNode> {
+    node.children_named(name).chain(
+        node.children_named("hidden")
+            .flat_map(move |hidden| hidden.children_named(name)),
+    )
+}
+
+fn node_to_entity(node: &VmfNode) -> eyre::Result<Entity> {
+    let mut attributes = Attributes::new();
+
+    node.key_values
+        .iter()
+        .filter(|(key, _)| key != "id")
+        .for_each(|(key, value)| {
+            attributes.insert(key.to_owned(), value.to_owned());
+        });
+
+    let solids = visible_and_hidden(node, "solid").collect::<Vec<&VmfNode>>();
+
+    let brushes = if solids.is_empty() {
+        None
+    } else {
+        Some(solids.into_iter().try_fold(vec![], |mut acc, solid| {
+            acc.extend(solid_to_brushes(solid)?);
+            Ok::<_, eyre::Report>(acc)
+        })?)
+    };
+
+    Ok(Entity {
+        attributes,
+        brushes,
+        comments: None,
+        trailing_comments: vec![],
+    })
+}
+
+/// Converts VMF into Valve220 .map
+///
+/// Props become cycler_sprite with the prop_static rotation fix so they work with models from S2G.
+pub fn vmf2map(vmf: &Vmf) -> eyre::Result<Map> {
+    let world = vmf
+        .nodes_named("world")
+        .next()
+        .ok_or(eyre::eyre!("VMF does not have world."))?;
+
+    let mut worldspawn = node_to_entity(world)?;
+
+    worldspawn
+        .attributes
+        .insert("mapversion".to_string(), "220".to_string());
+
+    if worldspawn.brushes.is_none() {
+        worldspawn.brushes = Some(vec![]);
+    }
+
+    let mut entities = vec![worldspawn];
+
+    for node in vmf.nodes_named("entity").chain(
+        vmf.nodes_named("hidden")
+            .flat_map(|hidden| hidden.children_named("entity")),
+    ) {
+        let mut entity = node_to_entity(node)?;
+
+        let Some(classname) = entity.attributes.get("classname").cloned() else {
+            continue;
+        };
+
+        if DROPPED_CLASSNAMES.contains(&classname.as_str()) {
+            continue;
+        }
+
+        if PROP_CLASSNAMES.contains(&classname.as_str()) {
+            entity
+                .attributes
+                .insert("classname".to_string(), PROP_STATIC_CLASSNAME.to_string());
+
+            // rotation fix only applies to props with angles
+            if !entity.attributes.contains_key("angles") {
+                entity
+                    .attributes
+                    .insert("angles".to_string(), "0 0 0".to_string());
+            }
+        } else if let Some((_, new_classname)) = ENTITY_CLASSNAMES
+            .iter()
+            .find(|(source, _)| *source == classname)
+        {
+            entity
+                .attributes
+                .insert("classname".to_string(), new_classname.to_string());
+        }
+
+        entities.push(entity);
+    }
+
+    let mut map = Map {
+        tb_header: None,
+        entities,
+        trailing_comments: vec![],
+    };
+
+    rotate_prop_static(&mut map, Some(PROP_NEW_CLASSNAME));
+
+    Ok(map)
+}
+
+#[cfg(test)]
+mod test {
+    use glam::DVec3;
+    use map::brush_plane_winding;
+
+    use super::*;
+
+    static TEST_VMF: &str = r#"versioninfo
+{
+	"editorversion" "400"
+	"mapversion" "1"
+}
+// comment
+world
+{
+	"id" "1"
+	"mapversion" "1"
+	"classname" "worldspawn"
+	"skyname" "sky_day01_01"
+	solid
+	{
+		"id" "2"
+		side
+		{
+			"id" "1"
+			"plane" "(-64 64 0) (64 64 0) (64 -64 0)"
+			"material" "DEV/DEV_MEASUREGENERIC01B"
+			"uaxis" "[1 0 0 0] 0.25"
+			"vaxis" "[0 -1 0 0] 0.25"
+			"rotation" "0"
+			"lightmapscale" "16"
+			"smoothing_groups" "0"
+			dispinfo
+			{
+				"power" "2"
+				"startposition" "[-64 -64 0]"
+				"elevation" "0"
+				"subdiv" "0"
+				normals
+				{
+					"row0" "0 0 1 0 0 1 0 0 1 0 0 1 0 0 1"
+					"row1" "0 0 1 0 0 1 0 0 1 0 0 1 0 0 1"
+					"row2" "0 0 1 0 0 1 0 0 1 0 0 1 0 0 1"
+					"row3" "0 0 1 0 0 1 0 0 1 0 0 1 0 0 1"
+					"row4" "0 0 1 0 0 1 0 0 1 0 0 1 0 0 1"
+				}
+				distances
+				{
+					"row0" "0 0 0 0 0"
+					"row1" "0 8 8 8 0"
+					"row2" "0 8 32 8 0"
+					"row3" "0 8 8 8 0"
+					"row4" "0 0 0 0 0"
+				}
+			}
+		}
+		side
+		{
+			"id" "2"
+			"plane" "(-64 -64 -16) (64 -64 -16) (64 64 -16)"
+			"material" "TOOLS/TOOLSNODRAW"
+			"uaxis" "[1 0 0 0] 0.25"
+			"vaxis" "[0 -1 0 0] 0.25"
+			"rotation" "0"
+		}
+		side
+		{
+			"id" "3"
+			"plane" "(-64 64 0) (-64 -64 0) (-64 -64 -16)"
+			"material" "TOOLS/TOOLSNODRAW"
+			"uaxis" "[0 1 0 0] 0.25"
+			"vaxis" "[0 0 -1 0] 0.25"
+			"rotation" "0"
+		}
+		side
+		{
+			"id" "4"
+			"plane" "(64 64 -16) (64 -64 -16) (64 -64 0)"
+			"material" "TOOLS/TOOLSNODRAW"
+			"uaxis" "[0 1 0 0] 0.25"
+			"vaxis" "[0 0 -1 0] 0.25"
+			"rotation" "0"
+		}
+		side
+		{
+			"id" "5"
+			"plane" "(64 64 0) (-64 64 0) (-64 64 -16)"
+			"material" "TOOLS/TOOLSNODRAW"
+			"uaxis" "[1 0 0 0] 0.25"
+			"vaxis" "[0 0 -1 0] 0.25"
+			"rotation" "0"
+		}
+		side
+		{
+			"id" "6"
+			"plane" "(64 -64 -16) (-64 -64 -16) (-64 -64 0)"
+			"material" "TOOLS/TOOLSNODRAW"
+			"uaxis" "[1 0 0 0] 0.25"
+			"vaxis" "[0 0 -1 0] 0.25"
+			"rotation" "0"
+		}
+	}
+	hidden
+	{
+		solid
+		{
+			"id" "3"
+			side
+			{
+				"plane" "(-16 16 16) (16 16 16) (16 -16 16)"
+				"material" "TOOLS/TOOLSCLIP"
+				"uaxis" "[1 0 0 0] 0.25"
+				"vaxis" "[0 -1 0 0] 0.25"
+			}
+			side
+			{
+				"plane" "(-16 -16 -16) (16 -16 -16) (16 16 -16)"
+				"material" "concrete/concretefloor001a"
+				"uaxis" "[1 0 0 0] 0.25"
+				"vaxis" "[0 -1 0 0] 0.25"
+			}
+			side
+			{
+				"plane" "(-16 16 16) (-16 -16 16) (-16 -16 -16)"
+				"material" "concrete/concretefloor001a"
+				"uaxis" "[0 1 0 0] 0.25"
+				"vaxis" "[0 0 -1 0] 0.25"
+			}
+			side
+			{
+				"plane" "(16 16 -16) (16 -16 -16) (16 -16 16)"
+				"material" "concrete/concretefloor001a"
+				"uaxis" "[0 1 0 0] 0.25"
+				"vaxis" "[0 0 -1 0] 0.25"
+			}
+			side
+			{
+				"plane" "(16 16 16) (-16 16 16) (-16 16 -16)"
+				"material" "concrete/concretefloor001a"
+				"uaxis" "[1 0 0 0] 0.25"
+				"vaxis" "[0 0 -1 0] 0.25"
+			}
+			side
+			{
+				"plane" "(16 -16 -16) (-16 -16 -16) (-16 -16 16)"
+				"material" "concrete/concretefloor001a"
+				"uaxis" "[1 0 0 0] 0.25"
+				"vaxis" "[0 0 -1 0] 0.25"
+			}
+		}
+	}
+}
+entity
+{
+	"id" "10"
+	"classname" "prop_static"
+	"angles" "0 45 0"
+	"model" "models/props/crate.mdl"
+	"origin" "0 0 64"
+}
+entity
+{
+	"id" "11"
+	"classname" "info_player_terrorist"
+	"angles" "0 0 0"
+	"origin" "32 32 64"
+	connections
+	{
+		"OnUser1" "target,Kill,,0,-1"
+	}
+}
+entity
+{
+	"id" "12"
+	"classname" "env_cubemap"
+	"origin" "0 0 32"
+}
+cameras
+{
+	"activecamera" "-1"
+}
+"#;
+
+    #[test]
+    fn parse() {
+        let vmf = Vmf::from_text(TEST_VMF).unwrap();
+
+        assert_eq!(vmf.nodes.len(), 6);
+
+        let world = vmf.nodes_named("world").next().unwrap();
+
+        assert_eq!(world.get("classname").unwrap(), "worldspawn");
+        assert_eq!(world.children_named("solid").count(), 1);
+        assert_eq!(world.children_named("hidden").count(), 1);
+    }
+
+    #[test]
+    fn material() {
+        assert_eq!(material_to_texture("TOOLS/TOOLSNODRAW"), NULL_TEXTURE);
+        assert_eq!(material_to_texture("tools\\toolsplayerclip"), CLIP_TEXTURE);
+        assert_eq!(
+            material_to_texture("DEV/DEV_MEASUREGENERIC01B"),
+            "DEV_MEASUREGENE"
+        );
+    }
+
+    #[test]
+    fn convert() {
+        let vmf = Vmf::from_text(TEST_VMF).unwrap();
+        let map = vmf2map(&vmf).unwrap();
+
+        // cubemap is dropped
+        assert_eq!(map.entities.len(), 3);
+
+        let worldspawn = &map.entities[0];
+
+        assert_eq!(worldspawn.attributes.get("mapversion").unwrap(), "220");
+        assert!(!worldspawn.attributes.contains_key("id"));
+
+        let brushes = worldspawn.brushes.as_ref().unwrap();
+
+        // 4 x 4 displacement has 32 triangles and the hidden brush is kept
+        assert_eq!(brushes.len(), 33);
+        assert!(brushes[..32].iter().all(|brush| brush.planes.len() == 5));
+        // every face of the triangle brushes is used
+        assert!(brushes[..32]
+            .iter()
+            .all(|brush| (0..5).all(|index| brush_plane_winding(brush, index).is_some())));
+        assert!(brushes[..32]
+            .iter()
+            .all(|brush| brush.planes[0].texture_name == "DEV_MEASUREGENE"));
+
+        // the highest point of the displacement is in the middle
+        let highest = brushes[..32]
+            .iter()
+            .flat_map(|brush| [brush.planes[0].p1, brush.planes[0].p2, brush.planes[0].p3])
+            .fold(f64::MIN, |acc, point| acc.max(point.z));
+
+        assert_eq!(highest, 32.);
+
+        assert_eq!(brushes[32].planes[0].texture_name, CLIP_TEXTURE);
+
+        let prop = &map.entities[1];
+
+        assert_eq!(prop.attributes.get("classname").unwrap(), "cycler_sprite");
+        assert_eq!(prop.attributes.get("angles").unwrap(), "0 135 0");
+
+        let spawn = &map.entities[2];
+
+        assert_eq!(
+            spawn.attributes.get("classname").unwrap(),
+            "info_player_deathmatch"
+        );
+    }
+
+    #[test]
+    fn convert_asymmetric_displacement() {
+        // only row 0 column 1 is raised
+        let vmf = TEST_VMF
+            .replace(r#""row0" "0 0 0 0 0""#, r#""row0" "0 16 0 0 0""#)
+            .replace(r#""row1" "0 8 8 8 0""#, r#""row1" "0 0 0 0 0""#)
+            .replace(r#""row2" "0 8 32 8 0""#, r#""row2" "0 0 0 0 0""#)
+            .replace(r#""row3" "0 8 8 8 0""#, r#""row3" "0 0 0 0 0""#);
+        let vmf = Vmf::from_text(&vmf).unwrap();
+        let map = vmf2map(&vmf).unwrap();
+
+        let brushes = map.entities[0].brushes.as_ref().unwrap();
+        let raised = brushes[..32]
+            .iter()
+            .flat_map(|brush| [brush.planes[0].p1, brush.planes[0].p2, brush.planes[0].p3])
+            .filter(|point| point.z != 0.)
+            .collect::<Vec<_>>();
+
+        // rows go along y from the start position and columns go along x
+        assert!(!raised.is_empty());
+        assert!(raised
+            .iter()
+            .all(|point| point.distance(DVec3::new(-32., -64., 16.)) < 0.001));
+    }
+
+    #[test]
+    fn convert_write_read() {
+        let vmf = Vmf::from_text(TEST_VMF).unwrap();
+        let map = vmf2map(&vmf).unwrap();
+
+        let out = std::env::temp_dir().join("gchimp_vmf2map.map");
+        map.write(&out).unwrap();
+
+        let map2 = Map::from_file(&out).unwrap();
+
+        assert_eq!(map.entities.len(), map2.entities.len());
+        assert_eq!(
+            map.entities[0].brushes.as_ref().unwrap().len(),
+            map2.entities[0].brushes.as_ref().unwrap().len()
+        );
+    }
+}
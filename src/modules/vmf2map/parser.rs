@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_till, take_while1},
+    character::complete::multispace1,
+    combinator::{all_consuming, map},
+    multi::many0,
+    sequence::{delimited, preceded, terminated, tuple},
+    IResult as _IResult,
+};
+
+/// A block in a VMF file, such as `world`, `solid` or `side`.
+#[derive(Debug, Clone, Default)]
+pub struct VmfNode {
+    pub name: String,
+    pub key_values: Vec<(String, String)>,
+    pub children: Vec<VmfNode>,
+}
+
+impl VmfNode {
+    /// First value of the key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.key_values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a VmfNode> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&VmfNode> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Vmf {
+    pub nodes: Vec<VmfNode>,
+}
+
+impl Vmf {
+    pub fn from_text(text: &str) -> eyre::Result<Self> {
+        match parse_vmf(text) {
+            Ok((_, res)) => Ok(res),
+            // Do this to avoid propagating nom's &str into error.
+            Err(_) => Err(eyre::eyre!("Cannot parse VMF.")),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+
+        Self::from_text(&text)
+    }
+
+    pub fn nodes_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a VmfNode> {
+        self.nodes.iter().filter(move |node| node.name == name)
+    }
+}
+
+type IResult<'a, T> = _IResult<&'a str, T>;
+
+enum NodeItem {
+    KeyValue((String, String)),
+    Child(VmfNode),
+}
+
+// whitespaces and comments
+fn skip(i: &str) -> IResult<()> {
+    map(
+        many0(alt((
+            multispace1,
+            preceded(tag("//"), take_till(|c| c == '\n')),
+        ))),
+        |_| (),
+    )(i)
+}
+
+fn quoted_text(i: &str) -> IResult<&str> {
+    delimited(tag("\""), take_till(|c| c == '"'), tag("\""))(i)
+}
+
+fn node_name(i: &str) -> IResult<&str> {
+    alt((
+        quoted_text,
+        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+    ))(i)
+}
+
+fn key_value(i: &str) -> IResult<(String, String)> {
+    map(
+        tuple((quoted_text, skip, quoted_text)),
+        |(key, _, value)| (key.to_string(), value.to_string()),
+    )(i)
+}
+
+fn node_item(i: &str) -> IResult<NodeItem> {
+    alt((
+        map(key_value, NodeItem::KeyValue),
+        map(node, NodeItem::Child),
+    ))(i)
+}
+
+fn node(i: &str) -> IResult<VmfNode> {
+    map(
+        tuple((
+            node_name,
+            skip,
+            tag("{"),
+            skip,
+            many0(terminated(node_item, skip)),
+            tag("}"),
+        )),
+        |(name, _, _, _, items, _)| {
+            let mut res = VmfNode {
+                name: name.to_string(),
+                ..Default::default()
+            };
+
+            items.into_iter().for_each(|item| match item {
+                NodeItem::KeyValue(key_value) => res.key_values.push(key_value),
+                NodeItem::Child(child) => res.children.push(child),
+            });
+
+            res
+        },
+    )(i)
+}
+
+fn parse_vmf(i: &str) -> IResult<Vmf> {
+    map(
+        all_consuming(preceded(skip, many0(terminated(node, skip)))),
+        |nodes| Vmf { nodes },
+    )(i)
+}
@@ -9,15 +9,42 @@ use image::RgbaImage;
 use rayon::prelude::*;
 
 use eyre::eyre;
-use wad::types::{Entry, FileEntry, Wad};
+use wad::{
+    types::{Entry, FileEntry, Font, Image, MipTex, Palette, Wad, WaterFog},
+    QUAKE_PALETTE,
+};
 
-use crate::utils::img_stuffs::{
-    eight_bpp_bitmap_to_png_bytes, generate_mipmaps_from_path, generate_mipmaps_from_rgba_image,
-    write_8bpp_to_file, GenerateMipmapsResult,
+use crate::{
+    modules::wadfont::{pack_characters, FONT_WIDTH, GLYPH_SHEET_GRID_SIZE},
+    utils::{
+        constants::MAX_GOLDSRC_TEXTURE_NAME_LENGTH,
+        img_stuffs::{
+            eight_bpp_bitmap_to_png_bytes, frames_from_gif,
+            generate_mipmaps_from_path_with_options, generate_mipmaps_from_rgba_image_with_options,
+            maybe_resize_due_to_exceeding_max_goldsrc_texture_size, mipmaps_from_8bpp,
+            rgba8_from_path, rgba8_images_to_8bpp, rgba8_to_8bpp_with_options,
+            rgba8_to_8bpp_with_palette, write_8bpp_to_file, GenerateMipmapsResult, GoldSrcBmp,
+            QuantizeOptions,
+        },
+    },
 };
 
+/// Animated texture frames are `+0` to `+9`, alternate frames are `+A` to `+J`.
+const ANIMATED_TEXTURE_FRAMES: &[u8] = b"0123456789";
+const ALTERNATE_ANIMATED_TEXTURE_FRAMES: &[u8] = b"ABCDEFGHIJ";
+
 pub struct Waddy {
     wad: Wad,
+    /// BSP the textures are from.
+    ///
+    /// Textures are still edited through `wad` and they are written back when saving the BSP.
+    bsp: Option<Bsp>,
+    /// Palette of every texture when the WAD is WAD2.
+    ///
+    /// Textures added with their own palette are changed to this palette when saving.
+    wad2_palette: Vec<[u8; 3]>,
+    /// How imported images are converted to 8bpp
+    quantize_options: QuantizeOptions,
 }
 
 impl Default for Waddy {
@@ -28,7 +55,12 @@ impl Default for Waddy {
 
 impl Waddy {
     pub fn new() -> Self {
-        Self { wad: Wad::new() }
+        Self {
+            wad: Wad::new(),
+            bsp: None,
+            wad2_palette: QUAKE_PALETTE.to_vec(),
+            quantize_options: QuantizeOptions::default(),
+        }
     }
 
     pub fn from_wad_file(
@@ -36,13 +68,13 @@ impl Waddy {
     ) -> eyre::Result<Self> {
         let wad = Wad::from_file(path)?;
 
-        Ok(Waddy { wad })
+        Ok(Waddy { wad, ..Self::new() })
     }
 
     pub fn from_wad_bytes(bytes: &[u8]) -> eyre::Result<Self> {
         let wad = Wad::from_bytes(bytes)?;
 
-        Ok(Waddy { wad })
+        Ok(Waddy { wad, ..Self::new() })
     }
 
     pub fn from_bsp_file(
@@ -51,21 +83,67 @@ impl Waddy {
         let mut res = Self::new();
 
         let bsp = Bsp::from_file(path)?;
-        let textures = bsp.textures;
 
-        res.wad.entries = textures
-            .into_iter()
+        res.bsp = Some(bsp);
+        res.update_entries_from_bsp();
+
+        Ok(res)
+    }
+
+    /// Whether the textures are from a BSP.
+    pub fn is_bsp(&self) -> bool {
+        self.bsp.is_some()
+    }
+
+    pub fn bsp(&self) -> Option<&Bsp> {
+        self.bsp.as_ref()
+    }
+
+    fn update_entries_from_bsp(&mut self) {
+        let Some(bsp) = &self.bsp else {
+            return;
+        };
+
+        self.wad.entries = bsp
+            .textures
+            .iter()
             .map(|texture| {
                 let texture_name = texture.texture_name.get_string();
 
                 wad::types::Entry {
                     directory_entry: wad::types::DirectoryEntry::new(texture_name),
-                    file_entry: wad::types::FileEntry::MipTex(texture),
+                    file_entry: wad::types::FileEntry::MipTex(texture.clone()),
                 }
             })
             .collect::<Vec<wad::types::Entry>>();
 
-        Ok(res)
+        self.wad.header.num_dirs = self.wad.entries.len() as i32;
+    }
+
+    /// Writes textures from WAD entries back into the BSP.
+    ///
+    /// Entries are in the same order as BSP textures. New entries are appended.
+    fn update_bsp_from_entries(&mut self) -> eyre::Result<()> {
+        let Some(bsp) = &mut self.bsp else {
+            return Ok(());
+        };
+
+        for (index, entry) in self.wad.entries.iter().enumerate() {
+            let FileEntry::MipTex(miptex) = &entry.file_entry else {
+                return Err(eyre!("{} is not a texture", entry.texture_name()));
+            };
+
+            let mut miptex = miptex.clone();
+            miptex.texture_name.set_name(entry.texture_name())?;
+
+            if index < bsp.textures.len() {
+                bsp.textures[index] = miptex;
+            } else {
+                bsp.add_texture(miptex)?;
+            }
+        }
+
+        Ok(())
     }
 
     fn log(&self, i: impl std::fmt::Display + AsRef<str>) {
@@ -76,6 +154,14 @@ impl Waddy {
         &self.wad
     }
 
+    pub fn quantize_options(&self) -> &QuantizeOptions {
+        &self.quantize_options
+    }
+
+    pub fn quantize_options_mut(&mut self) -> &mut QuantizeOptions {
+        &mut self.quantize_options
+    }
+
     pub fn wad_mut(&mut self) -> &mut Wad {
         &mut self.wad
     }
@@ -101,11 +187,27 @@ impl Waddy {
             .for_each(|(index, entry)| {
                 let (width, height) = entry.file_entry.dimensions();
 
+                let water_fog = match entry.water_fog() {
+                    _ if !entry.is_water() || is_external_entry(entry) => String::new(),
+                    Ok(WaterFog { color, density }) => {
+                        format!(" fog {:?} density {}", color, density)
+                    }
+                    Err(err) => format!(" ({})", err),
+                };
+
                 res += format!(
-                    "{index:<4}: {:<16} {:>3}x{:<3}\n",
+                    "{index:<4}: {:<16} {:>3}x{:<3}{}{}\n",
                     entry.texture_name(),
                     width,
-                    height
+                    height,
+                    match &entry.file_entry {
+                        _ if is_external_entry(entry) => " (external)",
+                        FileEntry::Qpic(_) => " (qpic)",
+                        FileEntry::Font(_) => " (font)",
+                        FileEntry::MipTex(_) => "",
+                        FileEntry::Raw(_) => " (raw)",
+                    },
+                    water_fog
                 )
                 .as_str();
             });
@@ -119,6 +221,9 @@ impl Waddy {
             .entries
             .par_iter()
             .enumerate()
+            .filter(|(_, entry)| {
+                !is_external_entry(entry) && !matches!(entry.file_entry, FileEntry::Raw(_))
+            })
             .filter_map(|(index, entry)| {
                 let (width, height) = entry.file_entry.dimensions();
 
@@ -136,11 +241,18 @@ impl Waddy {
             })
             .collect::<Vec<(usize, Vec<u8>)>>();
 
-        if res.len() != self.wad.header.num_dirs as usize {
+        let embedded_count = self
+            .wad
+            .entries
+            .iter()
+            .filter(|entry| !is_external_entry(entry))
+            .count();
+
+        if res.len() != embedded_count {
             let err_str = format!(
                 "Cannot parse all of textures ({}/{})",
                 res.len(),
-                self.wad.header.num_dirs
+                embedded_count
             );
 
             self.log(&err_str);
@@ -162,33 +274,29 @@ impl Waddy {
             return Err(eyre!("Output folder does not exist"));
         }
 
-        let res = self
-            .wad
-            .entries
-            .get(texture_index)
-            .map(|entry| match &entry.file_entry {
-                FileEntry::Qpic(_) => unimplemented!(),
-                FileEntry::MipTex(miptex) => {
-                    let res = write_8bpp_to_file(
-                        miptex.mip_images[0].data.get_bytes(),
-                        miptex.palette.get_bytes(),
-                        (miptex.width, miptex.height),
-                        out_path_file.as_ref().with_extension("bmp"),
-                    );
-
-                    if let Err(err) = res {
-                        let err_str = format!(
-                            "Error writing {}: {}",
-                            out_path_file.as_ref().display(),
-                            err
-                        );
-                        return Some(err_str);
-                    }
+        let res = self.wad.entries.get(texture_index).map(|entry| {
+            if is_external_entry(entry) {
+                return Some(format!("Texture {} is external", entry.texture_name()));
+            }
 
-                    None
-                }
-                FileEntry::Font(_) => unimplemented!(),
-            });
+            let res = write_8bpp_to_file(
+                entry.file_entry.image(),
+                entry.file_entry.palette(),
+                entry.file_entry.dimensions(),
+                out_path_file.as_ref().with_extension("bmp"),
+            );
+
+            if let Err(err) = res {
+                let err_str = format!(
+                    "Error writing {}: {}",
+                    out_path_file.as_ref().display(),
+                    err
+                );
+                return Some(err_str);
+            }
+
+            None
+        });
 
         if res.is_none() {
             let err_str = format!(
@@ -222,28 +330,25 @@ impl Waddy {
             .wad
             .entries
             .par_iter()
-            .filter_map(|entry| match &entry.file_entry {
-                FileEntry::Qpic(_) => unimplemented!(),
-                FileEntry::MipTex(miptex) => {
-                    let out_path = path
-                        .as_ref()
-                        .join(miptex.texture_name.get_string())
-                        .with_extension("bmp");
-                    let res = write_8bpp_to_file(
-                        miptex.mip_images[0].data.get_bytes(),
-                        miptex.palette.get_bytes(),
-                        (miptex.width, miptex.height),
-                        &out_path,
-                    );
-
-                    if let Err(err) = res {
-                        let err_str = format!("Error writing {}: {}", out_path.display(), err);
-                        return Some(err_str);
-                    }
+            .filter(|entry| !is_external_entry(entry))
+            .filter_map(|entry| {
+                let out_path = path
+                    .as_ref()
+                    .join(entry.texture_name())
+                    .with_extension("bmp");
+                let res = write_8bpp_to_file(
+                    entry.file_entry.image(),
+                    entry.file_entry.palette(),
+                    entry.file_entry.dimensions(),
+                    &out_path,
+                );
 
-                    None
+                if let Err(err) = res {
+                    let err_str = format!("Error writing {}: {}", out_path.display(), err);
+                    return Some(err_str);
                 }
-                FileEntry::Font(_) => unimplemented!(),
+
+                None
             })
             .collect::<Vec<String>>();
 
@@ -268,9 +373,20 @@ impl Waddy {
         self.wad.entries[texture_index].set_name(s)
     }
 
-    pub fn remove_texture(&mut self, texture_index: usize) {
+    /// Removes a texture.
+    ///
+    /// For BSP, textures used by faces cannot be removed.
+    pub fn remove_texture(&mut self, texture_index: usize) -> eyre::Result<()> {
+        if let Some(bsp) = &mut self.bsp {
+            if texture_index < bsp.textures.len() {
+                bsp.remove_texture(texture_index)?;
+            }
+        }
+
         self.wad.header.num_dirs = (self.wad.header.num_dirs - 1).max(0);
         self.wad.entries.remove(texture_index);
+
+        Ok(())
     }
 
     fn add_texture_from_generated_mipmaps(
@@ -303,18 +419,224 @@ impl Waddy {
         texture_name: &str,
         image: RgbaImage,
     ) -> eyre::Result<()> {
-        let res = generate_mipmaps_from_rgba_image(image)?;
+        let res = generate_mipmaps_from_rgba_image_with_options(image, &self.quantize_options)?;
 
         self.add_texture_from_generated_mipmaps(texture_name, res);
 
         Ok(())
     }
 
+    /// Replaces the image of a texture and keeps the name.
+    ///
+    /// Entry type stays the same. Font image must have the same dimensions so the characters still line up.
+    pub fn replace_texture_from_rgba_image(
+        &mut self,
+        texture_index: usize,
+        image: RgbaImage,
+    ) -> eyre::Result<()> {
+        let Some(entry) = self.wad.entries.get(texture_index) else {
+            return Err(eyre!("Index {} out of bound", texture_index));
+        };
+
+        match &entry.file_entry {
+            FileEntry::MipTex(_) => {
+                let res =
+                    generate_mipmaps_from_rgba_image_with_options(image, &self.quantize_options)?;
+
+                self.replace_texture_from_generated_mipmaps(texture_index, res)
+            }
+            FileEntry::Qpic(_) => {
+                let texture_name = entry.texture_name();
+
+                let GoldSrcBmp {
+                    image,
+                    palette,
+                    dimensions,
+                } = rgba8_to_8bpp_with_options(image, &self.quantize_options)?;
+
+                self.wad.entries[texture_index] =
+                    Entry::new_qpic(texture_name, dimensions, &image, palette);
+
+                Ok(())
+            }
+            FileEntry::Font(font) => {
+                if image.dimensions() != (font.width, font.height) {
+                    return Err(eyre!("Font image must be {}x{}", font.width, font.height));
+                }
+
+                let mut font = font.clone();
+                let GoldSrcBmp { image, palette, .. } =
+                    rgba8_to_8bpp_with_options(image, &self.quantize_options)?;
+
+                font.data = Image::new(image);
+                font.colors_used = palette.len() as i16;
+                font.palette = Palette::new(palette);
+
+                self.wad.entries[texture_index].file_entry = FileEntry::Font(font);
+
+                Ok(())
+            }
+            FileEntry::Raw(_) => Err(eyre!(
+                "\"{}\" is a raw lump without image",
+                entry.texture_name()
+            )),
+        }
+    }
+
+    /// Replaces the image of a texture and keeps the name.
+    pub fn replace_texture_from_path(
+        &mut self,
+        texture_index: usize,
+        path: impl AsRef<Path> + Into<PathBuf>,
+    ) -> eyre::Result<()> {
+        let is_miptex = self
+            .wad
+            .entries
+            .get(texture_index)
+            .is_some_and(|entry| matches!(entry.file_entry, FileEntry::MipTex(_)));
+
+        if !is_miptex {
+            let image = image::open(path.as_ref())?.into_rgba8();
+
+            return self.replace_texture_from_rgba_image(texture_index, image);
+        }
+
+        let res = generate_mipmaps_from_path_with_options(path.as_ref(), &self.quantize_options)?;
+
+        self.replace_texture_from_generated_mipmaps(texture_index, res)
+    }
+
+    /// Replaces the image of a texture and maps it onto the texture's palette, or onto `palette` if set.
+    ///
+    /// Palette colors with special meaning such as water fog stay the same.
+    /// Transparent pixels of a `{` texture become index 255.
+    pub fn replace_texture_with_palette_from_rgba_image(
+        &mut self,
+        texture_index: usize,
+        image: RgbaImage,
+        palette: Option<&[[u8; 3]]>,
+    ) -> eyre::Result<()> {
+        let Some(entry) = self.wad.entries.get(texture_index) else {
+            return Err(eyre!("Index {} out of bound", texture_index));
+        };
+
+        let texture_name = entry.texture_name();
+        let palette = palette.unwrap_or(entry.file_entry.palette());
+        let transparent_index = texture_name.starts_with('{').then_some(255);
+
+        match &entry.file_entry {
+            FileEntry::MipTex(_) => {
+                let image = maybe_resize_due_to_exceeding_max_goldsrc_texture_size(image);
+
+                let GoldSrcBmp {
+                    image,
+                    palette,
+                    dimensions,
+                } = rgba8_to_8bpp_with_palette(
+                    image,
+                    palette,
+                    transparent_index,
+                    self.quantize_options.error_metric,
+                )?;
+
+                let mips = mipmaps_from_8bpp(image, dimensions);
+
+                self.replace_texture_from_generated_mipmaps(
+                    texture_index,
+                    GenerateMipmapsResult {
+                        mips,
+                        palette,
+                        dimensions,
+                    },
+                )
+            }
+            FileEntry::Qpic(_) => {
+                let GoldSrcBmp {
+                    image,
+                    palette,
+                    dimensions,
+                } = rgba8_to_8bpp_with_palette(
+                    image,
+                    palette,
+                    transparent_index,
+                    self.quantize_options.error_metric,
+                )?;
+
+                self.wad.entries[texture_index] =
+                    Entry::new_qpic(texture_name, dimensions, &image, palette);
+
+                Ok(())
+            }
+            FileEntry::Font(font) => {
+                if image.dimensions() != (font.width, font.height) {
+                    return Err(eyre!("Font image must be {}x{}", font.width, font.height));
+                }
+
+                let mut font = font.clone();
+                let GoldSrcBmp { image, palette, .. } = rgba8_to_8bpp_with_palette(
+                    image,
+                    palette,
+                    None,
+                    self.quantize_options.error_metric,
+                )?;
+
+                font.data = Image::new(image);
+                font.colors_used = palette.len() as i16;
+                font.palette = Palette::new(palette);
+
+                self.wad.entries[texture_index].file_entry = FileEntry::Font(font);
+
+                Ok(())
+            }
+            FileEntry::Raw(_) => Err(eyre!(
+                "\"{}\" is a raw lump without image",
+                entry.texture_name()
+            )),
+        }
+    }
+
+    /// Same as [`Self::replace_texture_with_palette_from_rgba_image`] with an image file.
+    pub fn replace_texture_with_palette_from_path(
+        &mut self,
+        texture_index: usize,
+        path: impl AsRef<Path> + Into<PathBuf>,
+        palette: Option<&[[u8; 3]]>,
+    ) -> eyre::Result<()> {
+        let image = rgba8_from_path(path)?;
+
+        self.replace_texture_with_palette_from_rgba_image(texture_index, image, palette)
+    }
+
+    fn replace_texture_from_generated_mipmaps(
+        &mut self,
+        texture_index: usize,
+        res: GenerateMipmapsResult,
+    ) -> eyre::Result<()> {
+        let Some(entry) = self.wad.entries.get_mut(texture_index) else {
+            return Err(eyre!("Index {} out of bound", texture_index));
+        };
+
+        let GenerateMipmapsResult {
+            mips: [mip0, mip1, mip2, mip3],
+            palette,
+            dimensions,
+        } = res;
+
+        *entry = Entry::new(
+            entry.texture_name(),
+            dimensions,
+            &[&mip0, &mip1, &mip2, &mip3],
+            palette.as_slice(),
+        );
+
+        Ok(())
+    }
+
     pub fn add_texture_from_path(
         &mut self,
         path: impl AsRef<Path> + Into<PathBuf>,
     ) -> eyre::Result<()> {
-        let res = generate_mipmaps_from_path(path.as_ref())?;
+        let res = generate_mipmaps_from_path_with_options(path.as_ref(), &self.quantize_options)?;
 
         let texture_name = path.as_ref().file_stem().unwrap().to_str().unwrap();
 
@@ -323,9 +645,437 @@ impl Waddy {
         Ok(())
     }
 
+    pub fn water_fog(&self, texture_index: usize) -> eyre::Result<WaterFog> {
+        let Some(entry) = self.wad.entries.get(texture_index) else {
+            return Err(eyre!("Index {} out of bound", texture_index));
+        };
+
+        entry.water_fog()
+    }
+
+    /// Sets fog color and density of a `!` water texture.
+    pub fn set_water_fog(&mut self, texture_index: usize, fog: WaterFog) -> eyre::Result<()> {
+        let Some(entry) = self.wad.entries.get_mut(texture_index) else {
+            return Err(eyre!("Index {} out of bound", texture_index));
+        };
+
+        entry.set_water_fog(fog)
+    }
+
+    /// Adds a texture that uses `palette` instead of a new palette.
+    ///
+    /// Transparent pixels of a `{` texture become index 255.
+    pub fn add_texture_with_palette_from_rgba_image(
+        &mut self,
+        texture_name: &str,
+        image: RgbaImage,
+        palette: &[[u8; 3]],
+    ) -> eyre::Result<()> {
+        let image = maybe_resize_due_to_exceeding_max_goldsrc_texture_size(image);
+        let transparent_index = texture_name.starts_with('{').then_some(255);
+
+        let GoldSrcBmp {
+            image,
+            palette,
+            dimensions,
+        } = rgba8_to_8bpp_with_palette(
+            image,
+            palette,
+            transparent_index,
+            self.quantize_options.error_metric,
+        )?;
+
+        let mips = mipmaps_from_8bpp(image, dimensions);
+
+        self.add_texture_from_generated_mipmaps(
+            texture_name,
+            GenerateMipmapsResult {
+                mips,
+                palette,
+                dimensions,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn add_texture_with_palette_from_path(
+        &mut self,
+        path: impl AsRef<Path> + Into<PathBuf>,
+        palette: &[[u8; 3]],
+    ) -> eyre::Result<()> {
+        let image = rgba8_from_path(path.as_ref())?;
+        let texture_name = path.as_ref().file_stem().unwrap().to_str().unwrap();
+
+        self.add_texture_with_palette_from_rgba_image(texture_name, image, palette)
+    }
+
+    /// Adds animated texture frames `+0name` to `+9name`, or `+Aname` to `+Jname` if `alternate`.
+    ///
+    /// Frames are quantized together so they have the same palette and do not flicker.
+    pub fn add_animated_texture_from_rgba_images(
+        &mut self,
+        texture_name: &str,
+        frames: Vec<RgbaImage>,
+        alternate: bool,
+    ) -> eyre::Result<()> {
+        let frame_names = if alternate {
+            ALTERNATE_ANIMATED_TEXTURE_FRAMES
+        } else {
+            ANIMATED_TEXTURE_FRAMES
+        };
+
+        if frames.is_empty() || frames.len() > frame_names.len() {
+            return Err(eyre!(
+                "Animated texture must have 1 to {} frames but got {}",
+                frame_names.len(),
+                frames.len()
+            ));
+        }
+
+        // name might already be a frame name
+        let texture_name = texture_name
+            .strip_prefix('+')
+            .and_then(|name| {
+                let mut chars = name.chars();
+
+                chars.next().map(|_| chars.as_str())
+            })
+            .unwrap_or(texture_name);
+
+        if texture_name.is_empty() || texture_name.len() + 2 > MAX_GOLDSRC_TEXTURE_NAME_LENGTH {
+            return Err(eyre!(
+                "Animated texture name must have 1 to {} characters",
+                MAX_GOLDSRC_TEXTURE_NAME_LENGTH - 2
+            ));
+        }
+
+        let frames = frames
+            .into_iter()
+            .map(maybe_resize_due_to_exceeding_max_goldsrc_texture_size)
+            .collect::<Vec<RgbaImage>>();
+        let dimensions = frames[0].dimensions();
+
+        let (images, palette) = rgba8_images_to_8bpp(&frames, &self.quantize_options)?;
+
+        for (image, frame_name) in images.into_iter().zip(frame_names) {
+            let mips = mipmaps_from_8bpp(image, dimensions);
+
+            self.add_texture_from_generated_mipmaps(
+                &format!("+{}{}", *frame_name as char, texture_name),
+                GenerateMipmapsResult {
+                    mips,
+                    palette: palette.clone(),
+                    dimensions,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Adds an animated texture from a GIF or from numbered images such as `lava0.png`, `lava1.png`.
+    ///
+    /// Texture name is the file name without the numbers.
+    pub fn add_animated_texture_from_paths(
+        &mut self,
+        paths: &[PathBuf],
+        alternate: bool,
+    ) -> eyre::Result<()> {
+        let (texture_name, frames) = match paths {
+            [] => return Err(eyre!("No image to import")),
+            [path]
+                if path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("gif")) =>
+            {
+                let texture_name = path.file_stem().unwrap().to_str().unwrap().to_string();
+
+                (texture_name, frames_from_gif(path)?)
+            }
+            paths => {
+                let frame_number = |path: &PathBuf| {
+                    let stem = path.file_stem().unwrap().to_str().unwrap();
+                    let name = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+
+                    (name.to_string(), stem[name.len()..].parse::<u32>().ok())
+                };
+
+                let mut paths = paths.to_vec();
+                paths.sort_by_key(|path| frame_number(path).1);
+
+                let texture_name = frame_number(&paths[0])
+                    .0
+                    .trim_end_matches(['_', '-', ' '])
+                    .to_string();
+
+                let frames = paths
+                    .iter()
+                    .map(|path| Ok(image::open(path)?.into_rgba8()))
+                    .collect::<eyre::Result<Vec<RgbaImage>>>()?;
+
+                (texture_name, frames)
+            }
+        };
+
+        self.add_animated_texture_from_rgba_images(&texture_name, frames, alternate)
+    }
+
+    /// Adds a picture entry (qpic) such as HUD images. Image is not resized.
+    pub fn add_qpic_from_rgba_image(
+        &mut self,
+        texture_name: &str,
+        image: RgbaImage,
+    ) -> eyre::Result<()> {
+        let GoldSrcBmp {
+            image,
+            palette,
+            dimensions,
+        } = rgba8_to_8bpp_with_options(image, &self.quantize_options)?;
+
+        self.wad.header.num_dirs += 1;
+        self.wad
+            .entries
+            .push(Entry::new_qpic(texture_name, dimensions, &image, palette));
+
+        Ok(())
+    }
+
+    pub fn add_qpic_from_path(
+        &mut self,
+        path: impl AsRef<Path> + Into<PathBuf>,
+    ) -> eyre::Result<()> {
+        let image = image::open(path.as_ref())?.into_rgba8();
+        let texture_name = path.as_ref().file_stem().unwrap().to_str().unwrap();
+
+        self.add_qpic_from_rgba_image(texture_name, image)
+    }
+
+    /// Adds a font entry from an image with 16x16 characters of the same size.
+    ///
+    /// Characters are in ASCII order from left to right, top to bottom. Image width must be 256.
+    pub fn add_font_from_rgba_image(
+        &mut self,
+        texture_name: &str,
+        image: RgbaImage,
+    ) -> eyre::Result<()> {
+        let (width, height) = image.dimensions();
+
+        if width != FONT_WIDTH || height % GLYPH_SHEET_GRID_SIZE != 0 || height == 0 {
+            return Err(eyre!(
+                "Font image must be {} wide and its height must be divisible by {}",
+                FONT_WIDTH,
+                GLYPH_SHEET_GRID_SIZE
+            ));
+        }
+
+        let char_width = width / GLYPH_SHEET_GRID_SIZE;
+        let row_height = height / GLYPH_SHEET_GRID_SIZE;
+
+        let characters = (0..GLYPH_SHEET_GRID_SIZE * GLYPH_SHEET_GRID_SIZE)
+            .map(|char_index| {
+                let row = char_index / GLYPH_SHEET_GRID_SIZE;
+                let column = char_index % GLYPH_SHEET_GRID_SIZE;
+
+                image::imageops::crop_imm(
+                    &image,
+                    column * char_width,
+                    row * row_height,
+                    char_width,
+                    row_height,
+                )
+                .to_image()
+            })
+            .collect::<Vec<RgbaImage>>();
+
+        // cells already fill the whole width so they are packed back into the same grid
+        let (image, font_info) = pack_characters(&characters, row_height, 0)?;
+        let (width, height) = image.dimensions();
+
+        let GoldSrcBmp { image, palette, .. } =
+            rgba8_to_8bpp_with_options(image, &self.quantize_options)?;
+
+        let font = Font::new(
+            (width, height),
+            (height / row_height, row_height),
+            font_info,
+            &image,
+            palette,
+        )?;
+
+        self.wad.header.num_dirs += 1;
+        self.wad.entries.push(Entry::new_font(texture_name, font));
+
+        Ok(())
+    }
+
+    pub fn add_font_from_path(
+        &mut self,
+        path: impl AsRef<Path> + Into<PathBuf>,
+    ) -> eyre::Result<()> {
+        let image = image::open(path.as_ref())?.into_rgba8();
+        let texture_name = path.as_ref().file_stem().unwrap().to_str().unwrap();
+
+        self.add_font_from_rgba_image(texture_name, image)
+    }
+
+    /// Whether the WAD is a Quake WAD2.
+    pub fn is_wad2(&self) -> bool {
+        self.wad.is_wad2()
+    }
+
+    /// Converts to WAD2 and changes every texture to `palette`, usually [`QUAKE_PALETTE`].
+    pub fn convert_to_wad2(&mut self, palette: &[[u8; 3]]) -> eyre::Result<()> {
+        if self.is_bsp() {
+            return Err(eyre!("Textures are from a BSP"));
+        }
+
+        self.wad = self.wad.to_wad2(palette)?;
+        self.wad2_palette = palette.to_vec();
+
+        Ok(())
+    }
+
+    /// Converts to WAD3 where every texture has its own palette.
+    pub fn convert_to_wad3(&mut self) -> eyre::Result<()> {
+        if self.is_bsp() {
+            return Err(eyre!("Textures are from a BSP"));
+        }
+
+        self.wad = self.wad.to_wad3();
+
+        Ok(())
+    }
+
     pub fn save_to_file(&self, path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<()> {
+        // WAD2 does not store palette so every texture must use the same one
+        if self.wad.is_wad2() {
+            return self.wad.to_wad2(&self.wad2_palette)?.write_to_file(path);
+        }
+
         self.wad.write_to_file(path)
     }
+
+    /// Writes the BSP with the edited textures.
+    pub fn save_bsp_to_file(&mut self, path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<()> {
+        self.update_bsp_from_entries()?;
+
+        let Some(bsp) = &self.bsp else {
+            return Err(eyre!("Textures are not from a BSP"));
+        };
+
+        bsp.write_to_file(path)
+    }
+
+    /// Moves every embedded BSP texture into a new WAD file.
+    ///
+    /// The WAD file name is added to worldspawn "wad" key so the engine can find the textures.
+    pub fn externalize_textures(
+        &mut self,
+        wad_path: impl AsRef<Path> + Into<PathBuf>,
+    ) -> eyre::Result<()> {
+        self.update_bsp_from_entries()?;
+
+        let Some(bsp) = &mut self.bsp else {
+            return Err(eyre!("Textures are not from a BSP"));
+        };
+
+        let textures = bsp.externalize_textures();
+
+        if textures.is_empty() {
+            return Err(eyre!("There is no embedded texture"));
+        }
+
+        let mut wad = Wad::new();
+
+        wad.entries = textures
+            .into_iter()
+            .map(|texture| Entry {
+                directory_entry: wad::types::DirectoryEntry::new(texture.texture_name.get_string()),
+                file_entry: FileEntry::MipTex(texture),
+            })
+            .collect();
+        wad.header.num_dirs = wad.entries.len() as i32;
+
+        wad.write_to_file(wad_path.as_ref())?;
+
+        if let Some(wad_file_name) = wad_path.as_ref().file_name().and_then(|s| s.to_str()) {
+            add_wad_to_worldspawn(bsp, wad_file_name);
+        }
+
+        self.update_entries_from_bsp();
+
+        Ok(())
+    }
+
+    /// Embeds external BSP textures found in the given WAD files.
+    ///
+    /// Returns the names of textures that are not found.
+    pub fn internalize_textures(&mut self, wads: &[Wad]) -> eyre::Result<Vec<String>> {
+        self.update_bsp_from_entries()?;
+
+        let Some(bsp) = &mut self.bsp else {
+            return Err(eyre!("Textures are not from a BSP"));
+        };
+
+        let textures = wads
+            .iter()
+            .flat_map(|wad| wad.entries.iter())
+            .filter_map(|entry| match &entry.file_entry {
+                FileEntry::MipTex(miptex) => {
+                    let mut miptex = miptex.clone();
+
+                    // directory name is more reliable
+                    miptex.texture_name.set_name(entry.texture_name()).ok()?;
+
+                    Some(miptex)
+                }
+                _ => None,
+            })
+            .collect::<Vec<MipTex>>();
+
+        let missing = bsp.internalize_textures(textures.iter());
+
+        self.update_entries_from_bsp();
+
+        Ok(missing)
+    }
+}
+
+fn is_external_entry(entry: &Entry) -> bool {
+    matches!(&entry.file_entry, FileEntry::MipTex(miptex) if miptex.is_external())
+}
+
+/// Appends a WAD file to worldspawn "wad" key if it is not there.
+fn add_wad_to_worldspawn(bsp: &mut Bsp, wad_file_name: &str) {
+    let Some(worldspawn) = bsp.entities.iter_mut().find(|entity| {
+        entity
+            .get("classname")
+            .is_some_and(|classname| classname == "worldspawn")
+    }) else {
+        return;
+    };
+
+    let wads = worldspawn.entry("wad".to_string()).or_default();
+
+    // paths are usually from Windows
+    let already_there = wads.split(';').any(|wad| {
+        wad.trim()
+            .rsplit(['/', '\\'])
+            .next()
+            .is_some_and(|s| s.eq_ignore_ascii_case(wad_file_name))
+    });
+
+    if already_there {
+        return;
+    }
+
+    if !wads.is_empty() && !wads.ends_with(';') {
+        wads.push(';');
+    }
+
+    wads.push_str(wad_file_name);
+    wads.push(';');
 }
 
 #[cfg(test)]
@@ -389,6 +1139,334 @@ mod test {
             .unwrap();
     }
 
+    #[test]
+    fn bsp_rename_save() {
+        let mut waddy = Waddy::from_bsp_file("bsp/src/tests/normal.bsp").unwrap();
+
+        assert!(waddy.is_bsp());
+
+        let texture_count = waddy.wad().entries.len();
+
+        waddy.rename_texture(0, "renamed").unwrap();
+
+        let out = std::env::temp_dir().join("gchimp_waddy_bsp_rename.bsp");
+        waddy.save_bsp_to_file(&out).unwrap();
+
+        let waddy = Waddy::from_bsp_file(&out).unwrap();
+
+        assert_eq!(waddy.wad().entries.len(), texture_count);
+        assert_eq!(waddy.wad().entries[0].texture_name(), "renamed");
+    }
+
+    #[test]
+    fn bsp_externalize_internalize() {
+        let mut waddy = Waddy::from_bsp_file("bsp/src/tests/normal.bsp").unwrap();
+
+        let wad_path = std::env::temp_dir().join("gchimp_waddy_externalize.wad");
+        let bsp_path = std::env::temp_dir().join("gchimp_waddy_externalize.bsp");
+
+        waddy.externalize_textures(&wad_path).unwrap();
+
+        assert!(waddy.wad().entries.iter().all(is_external_entry));
+        assert!(waddy.bsp().unwrap().entities[0]
+            .get("wad")
+            .unwrap()
+            .contains("gchimp_waddy_externalize.wad"));
+
+        waddy.save_bsp_to_file(&bsp_path).unwrap();
+
+        let mut waddy = Waddy::from_bsp_file(&bsp_path).unwrap();
+        let wad = Wad::from_file(&wad_path).unwrap();
+
+        let missing = waddy.internalize_textures(&[wad]).unwrap();
+
+        assert!(missing.is_empty());
+        assert!(!waddy.wad().entries.iter().any(is_external_entry));
+    }
+
+    #[test]
+    fn qpic_and_font() {
+        let mut waddy = Waddy::new();
+
+        let picture = RgbaImage::from_pixel(24, 10, image::Rgba([255, 0, 0, 255]));
+        let font_image = RgbaImage::from_fn(256, 128, |x, _| {
+            image::Rgba([(x % 16 * 16) as u8, 255, 255, 255])
+        });
+
+        waddy.add_qpic_from_rgba_image("hud", picture).unwrap();
+        waddy
+            .add_font_from_rgba_image("font", font_image.clone())
+            .unwrap();
+
+        assert!(waddy
+            .add_font_from_rgba_image("bad_font", RgbaImage::new(100, 128))
+            .is_err());
+        // last row starts past what a signed short offset can hold
+        assert!(waddy
+            .add_font_from_rgba_image("big_font", RgbaImage::new(256, 16 * 9))
+            .is_err());
+
+        // font image must keep its dimensions
+        assert!(waddy
+            .replace_texture_from_rgba_image(1, RgbaImage::new(256, 64))
+            .is_err());
+        waddy
+            .replace_texture_from_rgba_image(0, RgbaImage::new(8, 8))
+            .unwrap();
+
+        let wad_path = std::env::temp_dir().join("gchimp_waddy_qpic_font.wad");
+        waddy.save_to_file(&wad_path).unwrap();
+
+        let waddy = Waddy::from_wad_file(&wad_path).unwrap();
+        let entries = &waddy.wad().entries;
+
+        assert!(matches!(entries[0].file_entry, FileEntry::Qpic(_)));
+        assert_eq!(entries[0].file_entry.dimensions(), (8, 8));
+
+        let FileEntry::Font(font) = &entries[1].file_entry else {
+            panic!("not font");
+        };
+
+        assert_eq!(font.row_count, 16);
+        assert_eq!(font.row_height, 8);
+        assert_eq!(font.font_info[b'A' as usize].charwidth, 16);
+        assert_eq!(font.font_info[17].startoffset, 256 * 8 + 16);
+
+        assert!(waddy.dump_info().contains("(font)"));
+    }
+
+    #[test]
+    fn animated_texture() {
+        let mut waddy = Waddy::new();
+
+        let frames = (0..3)
+            .map(|i| RgbaImage::from_pixel(32, 16, image::Rgba([i * 100, 0, 0, 255])))
+            .collect::<Vec<RgbaImage>>();
+
+        waddy
+            .add_animated_texture_from_rgba_images("+0lava", frames.clone(), false)
+            .unwrap();
+        waddy
+            .add_animated_texture_from_rgba_images("lava", frames.clone(), true)
+            .unwrap();
+
+        let entries = &waddy.wad().entries;
+        let names = entries
+            .iter()
+            .map(|entry| entry.texture_name())
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            names,
+            vec!["+0lava", "+1lava", "+2lava", "+Alava", "+Blava", "+Clava"]
+        );
+
+        // same palette and different pixels
+        assert_eq!(
+            entries[0].file_entry.palette(),
+            entries[2].file_entry.palette()
+        );
+        assert_ne!(entries[0].file_entry.image(), entries[2].file_entry.image());
+
+        let FileEntry::MipTex(miptex) = &entries[1].file_entry else {
+            panic!("not miptex");
+        };
+        assert_eq!(miptex.mip_images[3].data.get_bytes().len(), 4 * 2);
+
+        // limits
+        assert!(waddy
+            .add_animated_texture_from_rgba_images("lava", vec![frames[0].clone(); 11], false)
+            .is_err());
+        assert!(waddy
+            .add_animated_texture_from_rgba_images("a_long_texture", frames.clone(), false)
+            .is_err());
+        assert!(waddy
+            .add_animated_texture_from_rgba_images(
+                "lava",
+                vec![frames[0].clone(), RgbaImage::new(16, 16)],
+                false
+            )
+            .is_err());
+
+        // frame character is not always one byte
+        waddy
+            .add_animated_texture_from_rgba_images("+ébrick", frames.clone(), false)
+            .unwrap();
+
+        assert_eq!(
+            waddy.wad().entries.last().unwrap().texture_name(),
+            "+2brick"
+        );
+    }
+
+    #[test]
+    fn animated_texture_sequence() {
+        let dir = std::env::temp_dir();
+        let paths = [10, 2, 1]
+            .iter()
+            .map(|i| {
+                let path = dir.join(format!("gchimp_water_{}.png", i));
+
+                RgbaImage::from_pixel(16, 16, image::Rgba([0, 0, *i as u8 * 20, 255]))
+                    .save(&path)
+                    .unwrap();
+
+                path
+            })
+            .collect::<Vec<PathBuf>>();
+
+        let mut waddy = Waddy::new();
+        waddy
+            .add_animated_texture_from_paths(&paths, false)
+            .unwrap();
+
+        let entries = &waddy.wad().entries;
+
+        assert_eq!(entries[0].texture_name(), "+0gchimp_water");
+        assert_eq!(entries[2].texture_name(), "+2gchimp_water");
+
+        // sorted by number, not by text
+        let palette = entries[2].file_entry.palette();
+        assert_eq!(
+            palette[entries[2].file_entry.image()[0] as usize],
+            [0, 0, 200]
+        );
+    }
+
+    #[test]
+    fn palette_locked_replace() {
+        let mut waddy = Waddy::new();
+
+        // water fog color and density are palette 3 and 4
+        let mut palette = (0..=255u8).map(|i| [i, 0, 0]).collect::<Vec<[u8; 3]>>();
+        palette[3] = [10, 20, 30];
+        palette[4] = [64, 64, 64];
+
+        waddy
+            .add_texture_with_palette_from_rgba_image(
+                "!water",
+                RgbaImage::from_pixel(16, 16, image::Rgba([100, 0, 0, 255])),
+                &palette,
+            )
+            .unwrap();
+        assert_eq!(waddy.wad().entries[0].file_entry.image()[0], 100);
+
+        waddy
+            .replace_texture_with_palette_from_rgba_image(
+                0,
+                RgbaImage::from_pixel(32, 32, image::Rgba([201, 1, 0, 255])),
+                None,
+            )
+            .unwrap();
+
+        let entry = &waddy.wad().entries[0];
+        assert_eq!(entry.texture_name(), "!water");
+        assert_eq!(entry.file_entry.palette(), &palette);
+        assert_eq!(entry.file_entry.image().len(), 32 * 32);
+        assert_eq!(entry.file_entry.image()[0], 201);
+
+        // another palette
+        let other = vec![[0, 0, 0], [255, 255, 255]];
+
+        waddy
+            .replace_texture_with_palette_from_rgba_image(
+                0,
+                RgbaImage::from_pixel(16, 16, image::Rgba([200, 200, 200, 255])),
+                Some(&other),
+            )
+            .unwrap();
+
+        let entry = &waddy.wad().entries[0];
+        assert_eq!(entry.file_entry.palette(), &other);
+        assert_eq!(entry.file_entry.image()[0], 1);
+    }
+
+    #[test]
+    fn palette_locked_transparency() {
+        let mut waddy = Waddy::new();
+
+        // index 255 is blue and the image has blue that is not transparent
+        let mut palette = (0..=255u8).map(|i| [i, i, i]).collect::<Vec<[u8; 3]>>();
+        palette[255] = [0, 0, 255];
+
+        let image = RgbaImage::from_fn(16, 16, |x, _| {
+            if x < 8 {
+                image::Rgba([0, 0, 0, 0])
+            } else {
+                image::Rgba([0, 0, 255, 255])
+            }
+        });
+
+        waddy
+            .add_texture_with_palette_from_rgba_image("{fence", image.clone(), &palette)
+            .unwrap();
+        waddy
+            .add_texture_with_palette_from_rgba_image("fence", image, &palette)
+            .unwrap();
+
+        let transparent = waddy.wad().entries[0].file_entry.image();
+        assert_eq!(transparent[0], 255);
+        assert_ne!(transparent[8], 255);
+
+        let opaque = waddy.wad().entries[1].file_entry.image();
+        assert_eq!(opaque[0], 0);
+        assert_eq!(opaque[8], 255);
+
+        // `{` needs index 255
+        assert!(waddy
+            .add_texture_with_palette_from_rgba_image(
+                "{short",
+                RgbaImage::new(16, 16),
+                &palette[..16]
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn wad2_conversion() {
+        let mut waddy = Waddy::new();
+
+        waddy
+            .add_qpic_from_rgba_image(
+                "hud",
+                RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255])),
+            )
+            .unwrap();
+        waddy.convert_to_wad2(&QUAKE_PALETTE).unwrap();
+
+        // added after converting so it has its own palette until saving
+        waddy
+            .add_qpic_from_rgba_image(
+                "hud2",
+                RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 255, 255])),
+            )
+            .unwrap();
+
+        let wad_path = std::env::temp_dir().join("gchimp_waddy_wad2.wad");
+        waddy.save_to_file(&wad_path).unwrap();
+
+        let mut waddy = Waddy::from_wad_file(&wad_path).unwrap();
+
+        assert!(waddy.is_wad2());
+        assert_eq!(
+            waddy.wad().entries[0].file_entry.palette()[251],
+            [255, 0, 0]
+        );
+        assert!(waddy.wad().entries[0]
+            .file_entry
+            .image()
+            .iter()
+            .all(|&index| index == 251));
+        assert!(waddy.wad().entries[1]
+            .file_entry
+            .image()
+            .iter()
+            .all(|&index| index == 208));
+
+        waddy.convert_to_wad3().unwrap();
+        assert!(!waddy.is_wad2());
+    }
+
     #[test]
     fn open_bsp() {
         let waddy = Waddy::from_bsp_file("/home/khang/map/bsp/bsp_compile.bsp").unwrap();
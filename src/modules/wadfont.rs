@@ -0,0 +1,350 @@
+//! Makes WAD3 font entries for fonts.wad replacements.
+//!
+//! Characters are packed from left to right into rows of an image that is 256 pixels wide.
+//! Each character has its own width so the font does not have to be monospace.
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{point, Font as _, FontRef, PxScale, ScaleFont};
+use eyre::eyre;
+use image::{Rgba, RgbaImage};
+use wad::types::{CharInfo, Entry, Font, Wad};
+
+use crate::utils::img_stuffs::{rgba8_to_8bpp, GoldSrcBmp};
+
+/// Font image is always this wide.
+pub const FONT_WIDTH: u32 = 256;
+/// Glyph sheet has 16 rows of 16 characters.
+pub const GLYPH_SHEET_GRID_SIZE: u32 = 16;
+const FONT_CHAR_COUNT: u32 = 256;
+/// Pixels between characters so that filtering does not bleed into the next character.
+const CHAR_PADDING: u32 = 1;
+
+/// Packs character images into the GoldSrc font layout with `padding` pixels between characters.
+///
+/// Every character image must be `row_height` tall.
+pub(crate) fn pack_characters(
+    characters: &[RgbaImage],
+    row_height: u32,
+    padding: u32,
+) -> eyre::Result<(RgbaImage, Vec<CharInfo>)> {
+    if characters.len() != FONT_CHAR_COUNT as usize {
+        return Err(eyre!(
+            "Font must have {} characters but got {}",
+            FONT_CHAR_COUNT,
+            characters.len()
+        ));
+    }
+
+    if row_height == 0 {
+        return Err(eyre!("Row height must be larger than 0"));
+    }
+
+    // (row, x) of every character
+    let mut positions = vec![];
+    let mut row = 0;
+    let mut x = 0;
+
+    for character in characters {
+        let width = character.width();
+
+        if width > FONT_WIDTH {
+            return Err(eyre!(
+                "Character is {} wide which is wider than the font image",
+                width
+            ));
+        }
+
+        if x + width > FONT_WIDTH {
+            row += 1;
+            x = 0;
+        }
+
+        positions.push((row, x));
+        x += width + padding;
+    }
+
+    let row_count = row + 1;
+    let height = row_count * row_height;
+
+    // startoffset is a signed short
+    if (FONT_WIDTH * (height - row_height)) as usize > i16::MAX as usize {
+        return Err(eyre!(
+            "Font is too large with {} rows of {} pixels, use a smaller size",
+            row_count,
+            row_height
+        ));
+    }
+
+    let mut image = RgbaImage::from_pixel(FONT_WIDTH, height, Rgba([0, 0, 0, 255]));
+
+    let font_info = characters
+        .iter()
+        .zip(positions)
+        .map(|(character, (row, x))| {
+            let y = row * row_height;
+
+            image::imageops::overlay(&mut image, character, x as i64, y as i64);
+
+            CharInfo::new((y * FONT_WIDTH + x) as i16, character.width() as i16)
+        })
+        .collect::<Vec<CharInfo>>();
+
+    Ok((image, font_info))
+}
+
+fn font_from_packed_image(
+    image: RgbaImage,
+    font_info: Vec<CharInfo>,
+    row_height: u32,
+) -> eyre::Result<Font> {
+    let (width, height) = image.dimensions();
+
+    let GoldSrcBmp { image, palette, .. } = rgba8_to_8bpp(image)?;
+
+    Font::new(
+        (width, height),
+        (height / row_height, row_height),
+        font_info,
+        &image,
+        palette,
+    )
+}
+
+/// Rasterizes a TrueType or OpenType font. `pixel_height` is the height of a row.
+///
+/// Characters are white on black. Character codes are Latin-1 and control characters are empty.
+pub fn font_from_ttf_bytes(bytes: &[u8], pixel_height: f32) -> eyre::Result<Font> {
+    let font = FontRef::try_from_slice(bytes).map_err(|err| eyre!("Cannot read font: {}", err))?;
+
+    if pixel_height < 1. {
+        return Err(eyre!("Font size must be at least 1 pixel"));
+    }
+
+    let scale = PxScale::from(pixel_height);
+    let scaled_font = font.as_scaled(scale);
+    let ascent = scaled_font.ascent();
+    let row_height = (ascent - scaled_font.descent()).ceil() as u32;
+
+    let characters = (0..FONT_CHAR_COUNT)
+        .map(|char_code| {
+            let c = char::from(char_code as u8);
+
+            if c.is_control() {
+                return RgbaImage::new(0, row_height);
+            }
+
+            let glyph_id = font.glyph_id(c);
+            let width = scaled_font.h_advance(glyph_id).ceil() as u32;
+            let glyph = glyph_id.with_scale_and_position(scale, point(0., ascent));
+
+            let mut character = RgbaImage::from_pixel(width, row_height, Rgba([0, 0, 0, 255]));
+
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+
+                outlined.draw(|x, y, coverage| {
+                    let x = x as i32 + bounds.min.x as i32;
+                    let y = y as i32 + bounds.min.y as i32;
+
+                    if x < 0 || y < 0 || x >= width as i32 || y >= row_height as i32 {
+                        return;
+                    }
+
+                    let value = (coverage.clamp(0., 1.) * 255.).round() as u8;
+
+                    character.put_pixel(x as u32, y as u32, Rgba([value, value, value, 255]));
+                });
+            }
+
+            character
+        })
+        .collect::<Vec<RgbaImage>>();
+
+    let (image, font_info) = pack_characters(&characters, row_height, CHAR_PADDING)?;
+
+    font_from_packed_image(image, font_info, row_height)
+}
+
+pub fn font_from_ttf_path(
+    path: impl AsRef<Path> + Into<PathBuf>,
+    pixel_height: f32,
+) -> eyre::Result<Font> {
+    let bytes = std::fs::read(path.as_ref())?;
+
+    font_from_ttf_bytes(&bytes, pixel_height)
+}
+
+/// Makes a font from a glyph sheet with 16 rows of 16 characters in character code order.
+///
+/// Character width is measured from the left of its cell to its last visible column.
+/// Transparent and black pixels are not visible. Empty cells are half a cell wide, for space.
+pub fn font_from_glyph_sheet(sheet: RgbaImage) -> eyre::Result<Font> {
+    let (width, height) = sheet.dimensions();
+
+    if width == 0
+        || height == 0
+        || width % GLYPH_SHEET_GRID_SIZE != 0
+        || height % GLYPH_SHEET_GRID_SIZE != 0
+    {
+        return Err(eyre!(
+            "Glyph sheet dimensions must be divisible by {}",
+            GLYPH_SHEET_GRID_SIZE
+        ));
+    }
+
+    let cell_width = width / GLYPH_SHEET_GRID_SIZE;
+    let row_height = height / GLYPH_SHEET_GRID_SIZE;
+
+    let is_visible = |pixel: &Rgba<u8>| pixel[3] != 0 && pixel.0[..3] != [0, 0, 0];
+
+    let characters = (0..FONT_CHAR_COUNT)
+        .map(|char_index| {
+            let cell_x = char_index % GLYPH_SHEET_GRID_SIZE * cell_width;
+            let cell_y = char_index / GLYPH_SHEET_GRID_SIZE * row_height;
+
+            let char_width = (0..cell_width)
+                .rev()
+                .find(|&x| {
+                    (0..row_height).any(|y| is_visible(sheet.get_pixel(cell_x + x, cell_y + y)))
+                })
+                .map(|x| x + 1)
+                .unwrap_or(cell_width / 2);
+
+            let mut character = RgbaImage::from_pixel(char_width, row_height, Rgba([0, 0, 0, 255]));
+
+            image::imageops::overlay(
+                &mut character,
+                &*image::imageops::crop_imm(&sheet, cell_x, cell_y, char_width, row_height),
+                0,
+                0,
+            );
+
+            character
+        })
+        .collect::<Vec<RgbaImage>>();
+
+    let (image, font_info) = pack_characters(&characters, row_height, CHAR_PADDING)?;
+
+    font_from_packed_image(image, font_info, row_height)
+}
+
+pub fn font_from_glyph_sheet_path(path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<Font> {
+    let sheet = image::open(path.as_ref())?.into_rgba8();
+
+    font_from_glyph_sheet(sheet)
+}
+
+/// Makes a font from a TTF/OTF file or a glyph sheet image and writes it to a WAD with one entry.
+///
+/// `pixel_height` is only used for TTF/OTF.
+pub fn wadfont(
+    input_path: impl AsRef<Path> + Into<PathBuf>,
+    pixel_height: f32,
+    entry_name: &str,
+    output_path: impl AsRef<Path> + Into<PathBuf>,
+) -> eyre::Result<()> {
+    let input_path = input_path.as_ref();
+
+    let is_font_file = input_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf"));
+
+    let font = if is_font_file {
+        font_from_ttf_path(input_path, pixel_height)?
+    } else {
+        font_from_glyph_sheet_path(input_path)?
+    };
+
+    let mut wad = Wad::new();
+
+    wad.header.num_dirs = 1;
+    wad.entries.push(Entry::new_font(entry_name, font));
+
+    wad.write_to_file(output_path)
+}
+
+#[cfg(test)]
+mod test {
+    use wad::types::FileEntry;
+
+    use super::*;
+
+    #[test]
+    fn glyph_sheet() {
+        // 8x8 cells and character N is N % 8 + 1 pixels wide
+        let sheet = RgbaImage::from_fn(128, 128, |x, y| {
+            let char_index = y / 8 * 16 + x / 8;
+
+            if x % 8 <= char_index % 8 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+
+        let font = font_from_glyph_sheet(sheet).unwrap();
+
+        assert_eq!(font.width, FONT_WIDTH);
+        assert_eq!(font.row_height, 8);
+        assert_eq!(font.height, font.row_count * font.row_height);
+        assert_eq!(font.font_info.len(), 256);
+
+        assert_eq!(font.font_info[0].charwidth, 1);
+        assert_eq!(font.font_info[7].charwidth, 8);
+        assert_eq!(font.font_info[8].charwidth, 1);
+        assert_eq!(font.font_info[0].startoffset, 0);
+        assert_eq!(font.font_info[1].startoffset, 2);
+
+        // every character is inside the image and white at its left column
+        font.font_info.iter().for_each(|char_info| {
+            let offset = char_info.startoffset as usize;
+            let x = offset as u32 % FONT_WIDTH;
+
+            assert!(x + char_info.charwidth as u32 <= FONT_WIDTH);
+            assert!(offset < (font.width * font.height) as usize);
+
+            let color = font.palette.get_bytes()[font.data.get_bytes()[offset] as usize];
+            assert_eq!(color, [255, 255, 255]);
+        });
+    }
+
+    #[test]
+    fn glyph_sheet_empty_cell() {
+        let sheet = RgbaImage::new(128, 128);
+        let font = font_from_glyph_sheet(sheet).unwrap();
+
+        assert!(font
+            .font_info
+            .iter()
+            .all(|char_info| char_info.charwidth == 4));
+    }
+
+    #[test]
+    fn bad_input() {
+        assert!(font_from_glyph_sheet(RgbaImage::new(100, 128)).is_err());
+        assert!(font_from_ttf_bytes(&[0, 1, 2, 3], 16.).is_err());
+
+        // startoffset cannot reach past the first 128 rows
+        let sheet = RgbaImage::from_pixel(256, 256 * 16, Rgba([255, 255, 255, 255]));
+        assert!(font_from_glyph_sheet(sheet).is_err());
+    }
+
+    #[test]
+    fn write_wad() {
+        let sheet_path = std::env::temp_dir().join("gchimp_wadfont_sheet.png");
+        let wad_path = std::env::temp_dir().join("gchimp_wadfont.wad");
+
+        RgbaImage::from_pixel(128, 128, Rgba([255, 255, 255, 255]))
+            .save(&sheet_path)
+            .unwrap();
+
+        wadfont(&sheet_path, 0., "font", &wad_path).unwrap();
+
+        let wad = Wad::from_file(&wad_path).unwrap();
+
+        assert_eq!(wad.entries.len(), 1);
+        assert_eq!(wad.entries[0].texture_name(), "font");
+        assert!(matches!(wad.entries[0].file_entry, FileEntry::Font(_)));
+    }
+}
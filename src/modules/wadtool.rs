@@ -0,0 +1,436 @@
+//! Merges WADs and compares them.
+//!
+//! Texture names are compared without case because the game does the same.
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use eyre::eyre;
+use wad::types::{Entry, Wad};
+
+use crate::utils::constants::MAX_GOLDSRC_TEXTURE_NAME_LENGTH;
+
+/// What to do when two textures have the same name but different pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// Keeps the texture from the earlier WAD.
+    #[default]
+    KeepFirst,
+    /// Keeps the texture from the later WAD.
+    KeepLast,
+    /// Keeps both and renames the later one.
+    Rename,
+}
+
+impl TryFrom<&str> for CollisionPolicy {
+    type Error = eyre::Report;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "first" => Ok(Self::KeepFirst),
+            "last" => Ok(Self::KeepLast),
+            "rename" => Ok(Self::Rename),
+            _ => Err(eyre!(
+                "Unknown collision policy \"{}\", use first, last or rename",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    pub collision: CollisionPolicy,
+    /// Removes textures with the same pixels as an earlier texture even when the names are different.
+    pub dedupe: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    /// Same name and same pixels, only one is kept.
+    pub identical: Vec<String>,
+    /// Same name but different pixels, resolved with [`CollisionPolicy`].
+    ///
+    /// (texture name, new name if renamed)
+    pub collisions: Vec<(String, Option<String>)>,
+    /// Different name but same pixels. (removed texture, kept texture)
+    pub duplicates: Vec<(String, String)>,
+}
+
+impl Display for MergeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.identical {
+            writeln!(f, "Identical: {}", name)?;
+        }
+
+        for (name, new_name) in &self.collisions {
+            match new_name {
+                Some(new_name) => writeln!(f, "Collision: {} (renamed to {})", name, new_name)?,
+                None => writeln!(f, "Collision: {}", name)?,
+            }
+        }
+
+        for (removed, kept) in &self.duplicates {
+            writeln!(f, "Duplicate: {} (same as {})", removed, kept)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Hash of dimensions and colors of every pixel.
+///
+/// Colors are hashed instead of palette indices so the same image with a different palette order is still the same.
+pub fn entry_content_hash(entry: &Entry) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let palette = entry.file_entry.palette();
+
+    entry.file_entry.file_type().hash(&mut hasher);
+    entry.file_entry.dimensions().hash(&mut hasher);
+
+    entry
+        .file_entry
+        .image()
+        .iter()
+        .for_each(|&index| palette.get(index as usize).hash(&mut hasher));
+
+    hasher.finish()
+}
+
+/// Finds a name that is not used by adding a number at the end.
+fn unused_name(name: &str, used: &HashMap<String, usize>) -> eyre::Result<String> {
+    (1..1000)
+        .map(|number| {
+            let suffix = format!("_{}", number);
+            let mut base_length = name
+                .len()
+                .min(MAX_GOLDSRC_TEXTURE_NAME_LENGTH - suffix.len());
+
+            // name can come from a WAD with non-ASCII names
+            while !name.is_char_boundary(base_length) {
+                base_length -= 1;
+            }
+
+            format!("{}{}", &name[..base_length], suffix)
+        })
+        .find(|new_name| !used.contains_key(&new_name.to_lowercase()))
+        .ok_or(eyre!("Cannot find a new name for {}", name))
+}
+
+/// Merges WADs in order into a WAD3. WAD2 textures keep the Quake palette.
+pub fn merge_wads(wads: &[Wad], options: &MergeOptions) -> eyre::Result<(Wad, MergeReport)> {
+    let mut res = Wad::new();
+    let mut report = MergeReport::default();
+
+    // lowercase name -> index in res
+    let mut names: HashMap<String, usize> = HashMap::new();
+    // content hash -> index in res
+    let mut hashes: HashMap<u64, usize> = HashMap::new();
+
+    for wad in wads {
+        for entry in wad.to_wad3().entries {
+            let name = entry.texture_name();
+            let hash = entry_content_hash(&entry);
+
+            if let Some(&existing) = names.get(&name.to_lowercase()) {
+                if entry_content_hash(&res.entries[existing]) == hash {
+                    report.identical.push(name);
+                    continue;
+                }
+
+                match options.collision {
+                    CollisionPolicy::KeepFirst => {
+                        report.collisions.push((name, None));
+                    }
+                    CollisionPolicy::KeepLast => {
+                        hashes.retain(|_, index| *index != existing);
+                        hashes.insert(hash, existing);
+
+                        res.entries[existing] = entry;
+                        report.collisions.push((name, None));
+                    }
+                    CollisionPolicy::Rename => {
+                        let new_name = unused_name(&name, &names)?;
+                        let mut entry = entry;
+
+                        entry.set_name(new_name.as_str())?;
+
+                        names.insert(new_name.to_lowercase(), res.entries.len());
+                        hashes.entry(hash).or_insert(res.entries.len());
+
+                        res.entries.push(entry);
+                        report.collisions.push((name, Some(new_name)));
+                    }
+                }
+
+                continue;
+            }
+
+            if options.dedupe {
+                if let Some(&existing) = hashes.get(&hash) {
+                    report
+                        .duplicates
+                        .push((name, res.entries[existing].texture_name()));
+                    continue;
+                }
+            }
+
+            names.insert(name.to_lowercase(), res.entries.len());
+            hashes.entry(hash).or_insert(res.entries.len());
+
+            res.entries.push(entry);
+        }
+    }
+
+    res.header.num_dirs = res.entries.len() as i32;
+
+    Ok((res, report))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureChange {
+    pub name: String,
+    /// (old, new) if the dimensions are different. Pixels are not compared then.
+    pub dimensions: Option<((u32, u32), (u32, u32))>,
+    /// Number of pixels with a different color.
+    pub changed_pixels: usize,
+    pub palette_changed: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WadDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<TextureChange>,
+}
+
+impl WadDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Display for WadDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.added {
+            writeln!(f, "+ {}", name)?;
+        }
+
+        for name in &self.removed {
+            writeln!(f, "- {}", name)?;
+        }
+
+        for change in &self.changed {
+            write!(f, "~ {}:", change.name)?;
+
+            if let Some(((old_width, old_height), (new_width, new_height))) = change.dimensions {
+                write!(
+                    f,
+                    " {}x{} -> {}x{}",
+                    old_width, old_height, new_width, new_height
+                )?;
+            } else if change.changed_pixels != 0 {
+                write!(f, " {} pixels", change.changed_pixels)?;
+            }
+
+            if change.palette_changed {
+                write!(f, " palette")?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares textures with the same name. Textures only in `new` are added and only in `old` are removed.
+pub fn diff_wads(old: &Wad, new: &Wad) -> WadDiff {
+    let mut res = WadDiff::default();
+
+    let old_entries = old
+        .entries
+        .iter()
+        .map(|entry| (entry.texture_name().to_lowercase(), entry))
+        .collect::<HashMap<String, &Entry>>();
+    let new_names = new
+        .entries
+        .iter()
+        .map(|entry| entry.texture_name().to_lowercase())
+        .collect::<Vec<String>>();
+
+    res.removed = old
+        .entries
+        .iter()
+        .map(|entry| entry.texture_name())
+        .filter(|name| !new_names.contains(&name.to_lowercase()))
+        .collect();
+
+    for new_entry in &new.entries {
+        let name = new_entry.texture_name();
+
+        let Some(old_entry) = old_entries.get(&name.to_lowercase()) else {
+            res.added.push(name);
+            continue;
+        };
+
+        let (old_file, new_file) = (&old_entry.file_entry, &new_entry.file_entry);
+        let palette_changed = old_file.palette() != new_file.palette();
+
+        if old_file.dimensions() != new_file.dimensions() {
+            res.changed.push(TextureChange {
+                name,
+                dimensions: Some((old_file.dimensions(), new_file.dimensions())),
+                changed_pixels: 0,
+                palette_changed,
+            });
+
+            continue;
+        }
+
+        let changed_pixels = old_file
+            .image()
+            .iter()
+            .zip(new_file.image())
+            .filter(|(&old_index, &new_index)| {
+                old_file.palette().get(old_index as usize)
+                    != new_file.palette().get(new_index as usize)
+            })
+            .count();
+
+        if changed_pixels != 0 || palette_changed {
+            res.changed.push(TextureChange {
+                name,
+                dimensions: None,
+                changed_pixels,
+                palette_changed,
+            });
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wad_with(textures: &[(&str, u8)]) -> Wad {
+        let mut wad = Wad::new();
+
+        textures.iter().for_each(|&(name, color)| {
+            let image = vec![0u8; 16 * 16];
+            let mip1 = vec![0u8; 8 * 8];
+            let mip2 = vec![0u8; 4 * 4];
+            let mip3 = vec![0u8; 2 * 2];
+
+            wad.entries.push(Entry::new(
+                name,
+                (16, 16),
+                &[&image, &mip1, &mip2, &mip3],
+                vec![[color, color, color]; 256],
+            ))
+        });
+
+        wad
+    }
+
+    #[test]
+    fn merge() {
+        let a = wad_with(&[("white", 255), ("black", 0)]);
+        let b = wad_with(&[("WHITE", 255), ("black", 1), ("dark", 0)]);
+
+        let (res, report) = merge_wads(&[a, b], &MergeOptions::default()).unwrap();
+
+        assert_eq!(res.entries.len(), 3);
+        assert_eq!(report.identical, vec!["WHITE"]);
+        assert_eq!(report.collisions, vec![("black".to_string(), None)]);
+        assert!(report.duplicates.is_empty());
+        assert_eq!(res.entries[1].file_entry.palette()[0], [0, 0, 0]);
+    }
+
+    #[test]
+    fn merge_policies() {
+        let a = wad_with(&[("white", 255), ("black", 0)]);
+        let b = wad_with(&[("black", 1), ("dark", 0)]);
+
+        let options = MergeOptions {
+            collision: CollisionPolicy::KeepLast,
+            dedupe: true,
+        };
+        let (res, report) = merge_wads(&[a.to_wad3(), b.to_wad3()], &options).unwrap();
+
+        // "dark" is not a duplicate anymore because "black" is replaced
+        assert_eq!(res.entries.len(), 3);
+        assert_eq!(res.entries[1].file_entry.palette()[0], [1, 1, 1]);
+        assert!(report.duplicates.is_empty());
+
+        let options = MergeOptions {
+            collision: CollisionPolicy::Rename,
+            dedupe: true,
+        };
+        let (res, report) = merge_wads(&[a, b], &options).unwrap();
+
+        assert_eq!(res.entries.len(), 3);
+        assert_eq!(res.entries[2].texture_name(), "black_1");
+        assert_eq!(
+            report.duplicates,
+            vec![("dark".to_string(), "black".to_string())]
+        );
+    }
+
+    #[test]
+    fn rename_long_name() {
+        let mut used = HashMap::new();
+        used.insert("a_long_texture_".to_string(), 0);
+
+        let new_name = unused_name("a_long_texture_", &used).unwrap();
+
+        assert_eq!(new_name, "a_long_textur_1");
+        assert!(new_name.len() <= MAX_GOLDSRC_TEXTURE_NAME_LENGTH);
+    }
+
+    #[test]
+    fn rename_non_ascii_name() {
+        let mut used = HashMap::new();
+        used.insert("texture_abcdé".to_string(), 0);
+
+        // 13 bytes would cut "é" in half
+        let new_name = unused_name("texture_abcdé", &used).unwrap();
+
+        assert_eq!(new_name, "texture_abcd_1");
+        assert!(new_name.len() <= MAX_GOLDSRC_TEXTURE_NAME_LENGTH);
+    }
+
+    #[test]
+    fn diff() {
+        let old = wad_with(&[("white", 255), ("black", 0), ("gone", 0)]);
+        let mut new = wad_with(&[("white", 255), ("BLACK", 1), ("new", 0)]);
+
+        let diff = diff_wads(&old, &new);
+
+        assert_eq!(diff.added, vec!["new"]);
+        assert_eq!(diff.removed, vec!["gone"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].changed_pixels, 256);
+        assert!(diff.changed[0].palette_changed);
+
+        // same colors with a different palette order
+        let wad::types::FileEntry::MipTex(miptex) = &mut new.entries[0].file_entry else {
+            unreachable!()
+        };
+        miptex.palette.0[0] = [9, 9, 9];
+        miptex.mip_images[0].data.0.fill(1);
+
+        let diff = diff_wads(&old, &new);
+
+        assert_eq!(diff.changed.len(), 2);
+        assert_eq!(diff.changed[0].name, "white");
+        assert_eq!(diff.changed[0].changed_pixels, 0);
+        assert!(diff.changed[0].palette_changed);
+
+        assert!(diff_wads(&old, &old).is_empty());
+    }
+}
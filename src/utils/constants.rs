@@ -1,4 +1,5 @@
 pub static MAX_GOLDSRC_TEXTURE_SIZE: u32 = 512;
+pub static MAX_GOLDSRC_TEXTURE_NAME_LENGTH: usize = 15;
 
 // divided by 2 just to be safe
 // divided by 2 again because what the fuck
@@ -13,11 +14,12 @@ pub static PALETTE_TRANSPARENT_COLOR: [u8; 3] = [0, 255, 0];
 pub static PALETTE_TRANSPARENT_COLOR2: [u8; 3] = [0, 0, 255];
 
 pub static ORIGIN_TEXTURE: &str = "ORIGIN";
+pub static NULL_TEXTURE: &str = "NULL";
 pub static CLIP_TEXTURE: &str = "CLIP";
 pub static CONTENTWATER_TEXTURE: &str = "CONTENTWATER";
 
 pub static NO_RENDER_TEXTURE: &[&str] = &[
-    "NULL",
+    NULL_TEXTURE,
     "HINT",
     "AAATRIGGER",
     "SKIP",
@@ -7,9 +7,13 @@ use std::{
 
 use eyre::eyre;
 use image::{
-    codecs::bmp::BmpDecoder, imageops, GenericImageView, ImageDecoder, RgbImage, RgbaImage,
+    codecs::{bmp::BmpDecoder, gif::GifDecoder},
+    imageops, AnimationDecoder, GenericImageView, ImageDecoder, RgbImage, RgbaImage,
+};
+use quantette::{
+    palette::{IntoColor, Oklab, Srgb},
+    ColorSpace, ImagePipeline, QuantizeMethod,
 };
-use quantette::{ColorSpace, ImagePipeline, QuantizeMethod};
 use rayon::prelude::*;
 use vtf::Vtf;
 
@@ -21,23 +25,159 @@ use crate::err;
 
 type Palette = Vec<quantette::palette::rgb::Rgb<quantette::palette::encoding::Srgb, u8>>;
 
+/// Default Floyd-Steinberg error diffusion, same as quantette.
+pub const DEFAULT_DITHER_STRENGTH: f32 = 7. / 8.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantizer {
+    /// Slower but better colors
+    KMeans,
+    /// Faster
+    Wu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dithering {
+    None,
+    /// Strength is from 0 to 1.
+    FloydSteinberg(f32),
+}
+
+/// Color space where colors are quantized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantizeColorSpace {
+    Srgb,
+    /// Perceptually uniform, better for gradients
+    Oklab,
+}
+
+/// How close two colors are when pixels are matched to palette colors.
+///
+/// Used when there is no dithering and when importing with a fixed palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorMetric {
+    /// Euclidean distance in sRGB
+    Rgb,
+    /// sRGB distance weighted by how sensitive eyes are to each channel ("redmean")
+    WeightedRgb,
+    /// Euclidean distance in Oklab
+    Oklab,
+}
+
+impl ErrorMetric {
+    pub fn distance(&self, a: [u8; 3], b: [u8; 3]) -> f32 {
+        match self {
+            Self::Rgb => (0..3).map(|i| (a[i] as f32 - b[i] as f32).powi(2)).sum(),
+            Self::WeightedRgb => {
+                let red_mean = (a[0] as f32 + b[0] as f32) / 2.;
+                let [dr, dg, db] = [0, 1, 2].map(|i| a[i] as f32 - b[i] as f32);
+
+                (2. + red_mean / 256.) * dr * dr
+                    + 4. * dg * dg
+                    + (2. + (255. - red_mean) / 256.) * db * db
+            }
+            Self::Oklab => {
+                let to_oklab = |color: [u8; 3]| -> Oklab {
+                    Srgb::new(color[0], color[1], color[2])
+                        .into_format::<f32>()
+                        .into_color()
+                };
+
+                let (a, b) = (to_oklab(a), to_oklab(b));
+
+                (a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)
+            }
+        }
+    }
+
+    /// Index of the closest color in `palette`, skipping `skip_index`.
+    pub fn closest_index(
+        &self,
+        color: [u8; 3],
+        palette: &[[u8; 3]],
+        skip_index: Option<u8>,
+    ) -> Option<u8> {
+        palette
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index as u8) != skip_index)
+            .map(|(index, other)| (index, self.distance(color, *other)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index as u8)
+    }
+}
+
+/// Options for converting images to 8bpp.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizeOptions {
+    pub quantizer: Quantizer,
+    pub dithering: Dithering,
+    pub colorspace: QuantizeColorSpace,
+    pub error_metric: ErrorMetric,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        Self {
+            quantizer: Quantizer::KMeans,
+            dithering: Dithering::FloydSteinberg(DEFAULT_DITHER_STRENGTH),
+            colorspace: QuantizeColorSpace::Oklab,
+            error_metric: ErrorMetric::Rgb,
+        }
+    }
+}
+
 /// The pixels are quantized with following palette.
 ///
 /// ## Must convert image to 8bpp with the palette.
-fn quantize_image(img: RgbImage) -> eyre::Result<(RgbImage, Palette)> {
+fn quantize_image(img: RgbImage, options: &QuantizeOptions) -> eyre::Result<(RgbImage, Palette)> {
     let pipeline = ImagePipeline::try_from(&img)?
         .palette_size(255)
-        .dither(true)
-        .colorspace(ColorSpace::Oklab)
-        .quantize_method(QuantizeMethod::kmeans());
+        .colorspace(match options.colorspace {
+            QuantizeColorSpace::Srgb => ColorSpace::Srgb,
+            QuantizeColorSpace::Oklab => ColorSpace::Oklab,
+        })
+        .quantize_method(match options.quantizer {
+            Quantizer::KMeans => QuantizeMethod::kmeans(),
+            Quantizer::Wu => QuantizeMethod::Wu,
+        });
+
+    let pipeline = match options.dithering {
+        Dithering::None => pipeline.dither(false),
+        Dithering::FloydSteinberg(strength) => pipeline
+            .dither(true)
+            .dither_error_diffusion(strength.clamp(0., 1.)),
+    };
+
+    let palette: Palette = pipeline.clone().palette_par();
 
-    let img = pipeline.clone().quantized_rgbimage_par();
-    let palette: Palette = pipeline.palette_par();
+    // without dithering, pixels go to the closest color by our own metric
+    let img = if options.dithering == Dithering::None {
+        let palette = format_quantette_palette(palette.clone());
+        let mut cache: HashMap<[u8; 3], [u8; 3]> = HashMap::new();
+        let mut img = img;
+
+        img.pixels_mut().for_each(|pixel| {
+            pixel.0 = *cache.entry(pixel.0).or_insert_with(|| {
+                // palette is never empty
+                let index = options
+                    .error_metric
+                    .closest_index(pixel.0, &palette, None)
+                    .unwrap();
+
+                palette[index as usize]
+            });
+        });
+
+        img
+    } else {
+        pipeline.quantized_rgbimage_par()
+    };
 
     Ok((img, palette))
 }
 
-fn maybe_resize_due_to_exceeding_max_goldsrc_texture_size(img: RgbaImage) -> RgbaImage {
+pub fn maybe_resize_due_to_exceeding_max_goldsrc_texture_size(img: RgbaImage) -> RgbaImage {
     let (width, height) = img.dimensions();
 
     let bigger_side = if width >= height { width } else { height };
@@ -183,9 +323,16 @@ pub fn png_to_bmp_folder(paths: &[PathBuf]) -> eyre::Result<()> {
 }
 
 pub fn rgba8_to_8bpp(rgb8a: RgbaImage) -> eyre::Result<GoldSrcBmp> {
+    rgba8_to_8bpp_with_options(rgb8a, &QuantizeOptions::default())
+}
+
+pub fn rgba8_to_8bpp_with_options(
+    rgb8a: RgbaImage,
+    options: &QuantizeOptions,
+) -> eyre::Result<GoldSrcBmp> {
     // TODO convert totally opaque pixel into transparent pixel
     let rgb8 = rgba8_to_rgb8(rgb8a)?;
-    let (rgb8, palette_color) = quantize_image(rgb8)?;
+    let (rgb8, palette_color) = quantize_image(rgb8, options)?;
 
     let dimension = rgb8.dimensions();
 
@@ -199,6 +346,160 @@ pub fn rgba8_to_8bpp(rgb8a: RgbaImage) -> eyre::Result<GoldSrcBmp> {
     })
 }
 
+/// Maps every pixel to the closest color of `palette` instead of making a new palette.
+///
+/// Fully transparent pixels become `transparent_index` and other pixels never use it.
+/// Without `transparent_index`, transparency is blended to black.
+pub fn rgba8_to_8bpp_with_palette(
+    img: RgbaImage,
+    palette: &[[u8; 3]],
+    transparent_index: Option<u8>,
+    error_metric: ErrorMetric,
+) -> eyre::Result<GoldSrcBmp> {
+    if palette.is_empty() || palette.len() > 256 {
+        return Err(eyre!(
+            "Palette must have 1 to 256 colors but got {}",
+            palette.len()
+        ));
+    }
+
+    if let Some(transparent_index) = transparent_index {
+        if transparent_index as usize >= palette.len() {
+            return Err(eyre!(
+                "Palette does not have transparent index {}",
+                transparent_index
+            ));
+        }
+    }
+
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    let dimensions = img.dimensions();
+
+    let image = img
+        .pixels()
+        .map(|pixel| {
+            if pixel[3] == 0 {
+                if let Some(transparent_index) = transparent_index {
+                    return Ok(transparent_index);
+                }
+            }
+
+            let opacity = pixel[3] as f32 / 255.;
+            let color = [0, 1, 2].map(|i| (pixel[i] as f32 * opacity).round() as u8);
+
+            if let Some(index) = cache.get(&color) {
+                return Ok(*index);
+            }
+
+            let index = error_metric
+                .closest_index(color, palette, transparent_index)
+                .ok_or(eyre!("Palette has no color other than transparent"))?;
+            cache.insert(color, index);
+
+            Ok(index)
+        })
+        .collect::<eyre::Result<Vec<u8>>>()?;
+
+    Ok(GoldSrcBmp {
+        image,
+        palette: palette.to_vec(),
+        dimensions,
+    })
+}
+
+/// Reads a palette from a palette lump (.lmp) or an indexed bitmap.
+pub fn palette_from_path(path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<Vec<[u8; 3]>> {
+    let path = path.as_ref();
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("lmp") => {
+            let bytes = std::fs::read(path)?;
+
+            Ok(wad::types::Palette::from_lmp(&bytes)?.0)
+        }
+        Some("bmp") => {
+            let file = OpenOptions::new().read(true).open(path)?;
+            let decoder = BmpDecoder::new(BufReader::new(file))?;
+
+            decoder
+                .get_palette()
+                .map(|palette| palette.to_vec())
+                .ok_or(eyre!("{} is not an indexed bitmap", path.display()))
+        }
+        _ => Err(eyre!("{} is not a .lmp or .bmp", path.display())),
+    }
+}
+
+/// Indexed images and their shared palette
+pub type SharedPaletteImages = (Vec<Vec<u8>>, Vec<[u8; 3]>);
+
+/// Quantizes images with the same dimensions into one palette.
+///
+/// Images are stacked into one image so every image gets the same colors.
+pub fn rgba8_images_to_8bpp(
+    images: &[RgbaImage],
+    options: &QuantizeOptions,
+) -> eyre::Result<SharedPaletteImages> {
+    let Some(first) = images.first() else {
+        return Err(eyre!("No image to convert"));
+    };
+
+    let (width, height) = first.dimensions();
+
+    if images
+        .iter()
+        .any(|image| image.dimensions() != (width, height))
+    {
+        return Err(eyre!("Images must have the same dimensions"));
+    }
+
+    let mut stacked = RgbaImage::new(width, height * images.len() as u32);
+
+    images.iter().enumerate().for_each(|(index, image)| {
+        imageops::replace(&mut stacked, image, 0, (height * index as u32) as i64)
+    });
+
+    let GoldSrcBmp { image, palette, .. } = rgba8_to_8bpp_with_options(stacked, options)?;
+
+    let images = image
+        .chunks_exact((width * height) as usize)
+        .map(|image| image.to_vec())
+        .collect();
+
+    Ok((images, palette))
+}
+
+/// Every frame of a GIF, in full size.
+pub fn frames_from_gif(path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<Vec<RgbaImage>> {
+    let file = OpenOptions::new().read(true).open(path.as_ref())?;
+    let decoder = GifDecoder::new(BufReader::new(file))?;
+
+    let frames = decoder
+        .into_frames()
+        .collect_frames()?
+        .into_iter()
+        .map(|frame| frame.into_buffer())
+        .collect();
+
+    Ok(frames)
+}
+
+/// Mipmaps from an indexed image, taking every other pixel.
+pub fn mipmaps_from_8bpp(image: Vec<u8>, (width, height): (u32, u32)) -> [Vec<u8>; 4] {
+    let mip = |level: u32| {
+        let (mip_width, mip_height) = (width >> level, height >> level);
+
+        (0..mip_height)
+            .flat_map(|y| (0..mip_width).map(move |x| (x, y)))
+            .map(|(x, y)| image[((y << level) * width + (x << level)) as usize])
+            .collect::<Vec<u8>>()
+    };
+
+    let (mip1, mip2, mip3) = (mip(1), mip(2), mip(3));
+
+    [image, mip1, mip2, mip3]
+}
+
 /// `file_name` should have .bmp have extension
 pub fn write_8bpp_to_file(
     img: &[u8],
@@ -387,6 +688,13 @@ fn _generate_mipmaps_indexed_bmp(
 
 // TODO: better mipmaps generation because this is very SHIT
 pub fn generate_mipmaps_from_rgba_image(img: RgbaImage) -> eyre::Result<GenerateMipmapsResult> {
+    generate_mipmaps_from_rgba_image_with_options(img, &QuantizeOptions::default())
+}
+
+pub fn generate_mipmaps_from_rgba_image_with_options(
+    img: RgbaImage,
+    options: &QuantizeOptions,
+) -> eyre::Result<GenerateMipmapsResult> {
     let mip0 = maybe_resize_due_to_exceeding_max_goldsrc_texture_size(img);
 
     let mip0 = rgba8_to_rgb8(mip0);
@@ -397,7 +705,7 @@ pub fn generate_mipmaps_from_rgba_image(img: RgbaImage) -> eyre::Result<Generate
 
     let mip0 = mip0.unwrap();
 
-    let quantize_res = quantize_image(mip0);
+    let quantize_res = quantize_image(mip0, options);
 
     if let Err(err) = quantize_res {
         return err!("Cannot quantize image: {}", err);
@@ -437,6 +745,13 @@ pub fn generate_mipmaps_from_rgba_image(img: RgbaImage) -> eyre::Result<Generate
 
 pub fn generate_mipmaps_from_path(
     img_path: impl AsRef<Path> + Into<PathBuf>,
+) -> eyre::Result<GenerateMipmapsResult> {
+    generate_mipmaps_from_path_with_options(img_path, &QuantizeOptions::default())
+}
+
+pub fn generate_mipmaps_from_path_with_options(
+    img_path: impl AsRef<Path> + Into<PathBuf>,
+    options: &QuantizeOptions,
 ) -> eyre::Result<GenerateMipmapsResult> {
     let ext = img_path.as_ref().extension().unwrap();
 
@@ -452,15 +767,20 @@ pub fn generate_mipmaps_from_path(
         }
     };
 
-    let img = if ext == "vtf" {
-        Vtf::from_file(img_path.as_ref())?
-            .get_high_res_image()?
-            .into_rgba8()
-    } else {
-        image::open(img_path.as_ref())?.into_rgba8()
-    };
+    let img = rgba8_from_path(img_path)?;
+
+    generate_mipmaps_from_rgba_image_with_options(img, options)
+}
+
+/// Opens an image, including VTF.
+pub fn rgba8_from_path(img_path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<RgbaImage> {
+    let img_path = img_path.as_ref();
 
-    generate_mipmaps_from_rgba_image(img)
+    if img_path.extension().is_some_and(|ext| ext == "vtf") {
+        Ok(Vtf::from_file(img_path)?.get_high_res_image()?.into_rgba8())
+    } else {
+        Ok(image::open(img_path)?.into_rgba8())
+    }
 }
 
 #[derive(Debug)]
@@ -469,3 +789,60 @@ pub struct GoldSrcBmp {
     pub palette: Vec<[u8; 3]>,
     pub dimensions: (u32, u32),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_metric() {
+        // dark blue is closer in rgb but dark red is closer to eyes
+        let palette = [[0, 0, 30], [32, 0, 0]];
+
+        assert_eq!(
+            ErrorMetric::Rgb.closest_index([0, 0, 0], &palette, None),
+            Some(0)
+        );
+        assert_eq!(
+            ErrorMetric::WeightedRgb.closest_index([0, 0, 0], &palette, None),
+            Some(1)
+        );
+
+        let palette = [[255, 255, 255], [10, 10, 10], [0, 0, 0]];
+
+        assert_eq!(
+            ErrorMetric::Oklab.closest_index([0, 0, 0], &palette, None),
+            Some(2)
+        );
+        assert_eq!(
+            ErrorMetric::Oklab.closest_index([0, 0, 0], &palette, Some(2)),
+            Some(1)
+        );
+        assert_eq!(
+            ErrorMetric::Rgb.closest_index([0, 0, 0], &[[1, 1, 1]], Some(0)),
+            None
+        );
+    }
+
+    #[test]
+    fn no_dithering() {
+        let img = RgbaImage::from_fn(16, 16, |x, y| image::Rgba([x as u8 * 16, y as u8, 0, 255]));
+
+        let options = QuantizeOptions {
+            quantizer: Quantizer::Wu,
+            dithering: Dithering::None,
+            colorspace: QuantizeColorSpace::Srgb,
+            error_metric: ErrorMetric::WeightedRgb,
+        };
+
+        let GoldSrcBmp {
+            image,
+            palette,
+            dimensions,
+        } = rgba8_to_8bpp_with_options(img, &options).unwrap();
+
+        assert_eq!(dimensions, (16, 16));
+        assert!(palette.len() <= 256);
+        assert!(image.iter().all(|&index| (index as usize) < palette.len()));
+    }
+}
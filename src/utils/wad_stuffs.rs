@@ -127,28 +127,24 @@ pub fn export_texture(
         .entries
         .iter()
         .find(|entry| entry.texture_name() == texture_name)
-        .map(|entry| match &entry.file_entry {
-            FileEntry::Qpic(_) => unimplemented!(),
-            FileEntry::MipTex(miptex) => {
-                let res = write_8bpp_to_file(
-                    miptex.mip_images[0].data.get_bytes(),
-                    miptex.palette.get_bytes(),
-                    (miptex.width, miptex.height),
-                    out_path_file.as_ref().with_extension("bmp"),
+        .map(|entry| {
+            let res = write_8bpp_to_file(
+                entry.file_entry.image(),
+                entry.file_entry.palette(),
+                entry.file_entry.dimensions(),
+                out_path_file.as_ref().with_extension("bmp"),
+            );
+
+            if let Err(err) = res {
+                let err_str = format!(
+                    "Error writing {}: {}",
+                    out_path_file.as_ref().display(),
+                    err
                 );
-
-                if let Err(err) = res {
-                    let err_str = format!(
-                        "Error writing {}: {}",
-                        out_path_file.as_ref().display(),
-                        err
-                    );
-                    return Some(err_str);
-                }
-
-                None
+                return Some(err_str);
             }
-            FileEntry::Font(_) => unimplemented!(),
+
+            None
         });
 
     if res.is_none() {
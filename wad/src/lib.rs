@@ -1,11 +1,14 @@
 //! WAD file parsing
 //!
 //! Based of specification from this webpage: https://twhl.info/wiki/page/Specification%3A_WAD3
+//!
+//! Quake WAD2 is also supported. Its textures use [`QUAKE_PALETTE`] instead of their own palette.
 mod constants;
 mod parser;
 pub mod types;
 
-pub use parser::{parse_miptex, parse_wad};
+pub use constants::{QUAKE_PALETTE, WATER_FOG_COLOR_INDEX, WATER_FOG_DENSITY_INDEX};
+pub use parser::{parse_miptex, parse_quake_miptex, parse_wad};
 
 #[cfg(test)]
 mod test {
@@ -135,6 +138,259 @@ mod test {
         assert!(res.is_ok());
     }
 
+    #[test]
+    fn write_qpic() {
+        let mut wad = Wad::new();
+
+        let image = (0..6 * 3).collect::<Vec<u8>>();
+        let palette = (0..18).map(|x| [x, x, x]).collect::<Vec<[u8; 3]>>();
+
+        wad.entries
+            .push(types::Entry::new_qpic("hud", (6, 3), &image, palette));
+
+        let wad = Wad::from_bytes(&wad.write_to_bytes()).unwrap();
+
+        let entry = &wad.entries[0];
+
+        assert_eq!(entry.directory_entry.file_type, 0x42);
+        assert_eq!(entry.texture_name(), "hud");
+
+        let FileEntry::Qpic(qpic) = &entry.file_entry else {
+            panic!("not qpic");
+        };
+
+        assert_eq!((qpic.width, qpic.height), (6, 3));
+        assert_eq!(qpic.data.get_bytes(), &image);
+        assert_eq!(qpic.palette.get_bytes().len(), 18);
+    }
+
+    #[test]
+    fn write_font() {
+        let mut wad = Wad::new();
+
+        let (width, height) = (256, 8);
+        let image = vec![1u8; width * height];
+        let font_info = (0..256)
+            .map(|x| types::CharInfo::new((x % 32 * 8) as i16, 8))
+            .collect::<Vec<types::CharInfo>>();
+
+        let font = types::Font::new(
+            (width as u32, height as u32),
+            (1, 8),
+            font_info,
+            &image,
+            vec![[0, 0, 0], [255, 255, 255]],
+        )
+        .unwrap();
+
+        // a qpic in between to check alignment
+        wad.entries.push(types::Entry::new_qpic(
+            "odd",
+            (3, 3),
+            &[0; 9],
+            vec![[0, 0, 0]],
+        ));
+        wad.entries.push(types::Entry::new_font("font", font));
+
+        let bytes = wad.write_to_bytes();
+        let wad = Wad::from_bytes(&bytes).unwrap();
+
+        let entry = &wad.entries[1];
+
+        assert_eq!(entry.directory_entry.file_type, 0x45);
+        assert_eq!(entry.directory_entry.entry_offset % 4, 0);
+
+        let FileEntry::Font(font) = &entry.file_entry else {
+            panic!("not font");
+        };
+
+        assert_eq!(font.row_height, 8);
+        assert_eq!(font.font_info[33].startoffset, 8);
+        assert_eq!(font.data.get_bytes(), &image);
+
+        // writing again gives the same bytes
+        assert_eq!(wad.write_to_bytes(), bytes);
+    }
+
+    #[test]
+    fn font_size_mismatch() {
+        assert!(types::Font::new((256, 8), (1, 8), vec![], &[0; 256 * 8], vec![]).is_err());
+    }
+
+    #[test]
+    fn wad3_to_wad2() {
+        let wad = Wad::from_file("test/wad_test2.wad").unwrap();
+        let wad2 = wad.to_wad2(&QUAKE_PALETTE).unwrap();
+
+        assert!(wad2.is_wad2());
+
+        let bytes = wad2.write_to_bytes();
+        assert_eq!(&bytes[..4], b"WAD2");
+
+        let wad2 = Wad::from_bytes(&bytes).unwrap();
+
+        assert_eq!(wad2.entries.len(), 2);
+        assert_eq!(wad2.entries[0].directory_entry.file_type, 0x44);
+        assert_eq!(wad2.entries[1].texture_name(), "black");
+
+        // white and black are in the Quake palette but white is fullbright
+        let FileEntry::MipTex(white) = &wad2.entries[0].file_entry else {
+            panic!("not miptex");
+        };
+        let FileEntry::MipTex(black) = &wad2.entries[1].file_entry else {
+            panic!("not miptex");
+        };
+
+        assert!(white.mip_images[0]
+            .data
+            .get_bytes()
+            .iter()
+            .all(|&index| index == 15));
+        assert_eq!(black.to_rgb().0[..3], [0, 0, 0]);
+        assert_eq!(white.mip_images.len(), 4);
+
+        // writing again gives the same bytes
+        assert_eq!(wad2.write_to_bytes(), bytes);
+    }
+
+    #[test]
+    fn wad2_keep_lumpy() {
+        // like gfx.wad, a picture, the palette as a raw lump and conchars as miptex without header
+        let mut bytes = b"WAD2".to_vec();
+        bytes.extend(3i32.to_le_bytes());
+        bytes.extend((12 + 12 + 768 + 64i32).to_le_bytes());
+
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend([15, 0, 0, 0]);
+        bytes.extend(QUAKE_PALETTE.iter().flatten());
+        bytes.extend([0u8; 64]);
+
+        [
+            (12, 12, 0x42, "pic"),
+            (24, 768, 0x40, "palette"),
+            (792, 64, 0x44, "conchars"),
+        ]
+        .into_iter()
+        .for_each(|(offset, size, file_type, name): (i32, i32, i8, &str)| {
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend(size.to_le_bytes());
+            bytes.extend(size.to_le_bytes());
+            bytes.extend([file_type as u8, 0, 0, 0]);
+            bytes.extend(name.as_bytes());
+            bytes.extend(vec![0u8; 16 - name.len()]);
+        });
+
+        let wad = Wad::from_bytes(&bytes).unwrap();
+
+        assert_eq!(wad.entries.len(), 3);
+        assert_eq!(wad.entries[0].file_entry.dimensions(), (2, 1));
+
+        let FileEntry::Raw(palette) = &wad.entries[1].file_entry else {
+            panic!("not raw");
+        };
+
+        assert_eq!(palette.file_type, 0x40);
+        assert_eq!(palette.data, QUAKE_PALETTE.concat());
+
+        let FileEntry::Raw(conchars) = &wad.entries[2].file_entry else {
+            panic!("not raw");
+        };
+
+        assert_eq!(conchars.file_type, 0x44);
+        assert_eq!(conchars.data.len(), 64);
+
+        assert_eq!(wad.write_to_bytes(), bytes);
+    }
+
+    #[test]
+    fn wad2_to_wad3() {
+        let wad = Wad::from_file("test/wad_test.wad").unwrap();
+        let wad2 = Wad::from_bytes(&wad.to_wad2(&QUAKE_PALETTE).unwrap().write_to_bytes()).unwrap();
+
+        let wad3 = wad2.to_wad3();
+
+        assert!(!wad3.is_wad2());
+
+        let wad3 = Wad::from_bytes(&wad3.write_to_bytes()).unwrap();
+
+        assert_eq!(wad3.entries[0].directory_entry.file_type, 0x43);
+
+        // same image because WAD3 keeps the Quake palette
+        let FileEntry::MipTex(before) = &wad2.entries[0].file_entry else {
+            panic!("not miptex");
+        };
+        let FileEntry::MipTex(after) = &wad3.entries[0].file_entry else {
+            panic!("not miptex");
+        };
+
+        assert_eq!(before.to_rgb(), after.to_rgb());
+    }
+
+    #[test]
+    fn wad2_no_font() {
+        let mut wad = Wad::new();
+
+        let font_info = (0..256)
+            .map(|_| types::CharInfo::new(0, 1))
+            .collect::<Vec<types::CharInfo>>();
+        let font =
+            types::Font::new((256, 1), (1, 1), font_info, &[0; 256], vec![[0, 0, 0]]).unwrap();
+
+        wad.entries.push(types::Entry::new_font("font", font));
+
+        assert!(wad.to_wad2(&QUAKE_PALETTE).is_err());
+    }
+
+    #[test]
+    fn water_fog() {
+        let mut palette = (0..=255u8).map(|i| [i, i, i]).collect::<Vec<[u8; 3]>>();
+        palette[4] = [40, 0, 0];
+
+        // pixels use the fog color index
+        let mip0 = vec![3u8; 16 * 16];
+        let mips = [&mip0[..], &[3; 64], &[3; 16], &[3; 4]];
+
+        let mut entry = types::Entry::new("!water", (16, 16), &mips, palette.as_slice());
+
+        assert_eq!(
+            entry.water_fog().unwrap(),
+            types::WaterFog {
+                color: [3, 3, 3],
+                density: 40
+            }
+        );
+
+        let fog = types::WaterFog {
+            color: [0, 64, 128],
+            density: 10,
+        };
+        entry.set_water_fog(fog).unwrap();
+
+        assert_eq!(entry.water_fog().unwrap(), fog);
+
+        let FileEntry::MipTex(miptex) = &entry.file_entry else {
+            panic!()
+        };
+
+        // same colors, different index
+        assert_eq!(
+            miptex.palette.get_bytes()[WATER_FOG_COLOR_INDEX],
+            [0, 64, 128]
+        );
+        assert!(miptex.mip_images.iter().all(|mip| mip
+            .data
+            .get_bytes()
+            .iter()
+            .all(|&index| index == 2)));
+
+        let mut not_water = types::Entry::new("water", (16, 16), &mips, palette.as_slice());
+        assert!(not_water.set_water_fog(fog).is_err());
+
+        let short_palette = types::Entry::new("!water", (16, 16), &mips, &palette[..16]);
+        assert!(short_palette.water_fog().is_err());
+    }
+
     #[test]
     fn parse_big() {
         let _wad = Wad::from_file("/home/khang/map_compiler/cso_normal_pack.wad").unwrap();
@@ -8,9 +8,15 @@ use nom::{
     IResult as _IResult,
 };
 
-use crate::types::{
-    CharInfo, DirectoryEntry, Entry, FileEntry, Font, Header, Image, MipMap, MipTex, Palette, Qpic,
-    TextureName, Wad,
+use crate::{
+    constants::{
+        FONT_CHAR_COUNT, FONT_FILE_TYPE, MIPTEX_FILE_TYPE, QPIC_FILE_TYPE, QUAKE_PALETTE,
+        WAD2_MIPTEX_FILE_TYPE,
+    },
+    types::{
+        CharInfo, DirectoryEntry, Entry, FileEntry, Font, Header, Image, MipMap, MipTex, Palette,
+        Qpic, RawLump, TextureName, Wad,
+    },
 };
 
 type IResult<'a, T> = _IResult<&'a [u8], T>;
@@ -51,6 +57,14 @@ fn parse_directory_entry(i: &[u8]) -> IResult<DirectoryEntry> {
     )(i)
 }
 
+/// WAD2 picture does not have palette so it gets the Quake palette.
+fn parse_wad2_qpic(i: &[u8]) -> IResult<Qpic> {
+    let (i, (width, height)) = tuple((le_u32, le_u32))(i)?;
+    let (i, data) = count(le_u8, (width * height) as usize)(i)?;
+
+    Ok((i, Qpic::new((width, height), &data, QUAKE_PALETTE)))
+}
+
 fn parse_qpic(i: &[u8]) -> IResult<Qpic> {
     let (i, (width, height)) = tuple((le_u32, le_u32))(i)?;
     let (i, data) = count(le_u8, (width * height) as usize)(i)?;
@@ -146,6 +160,40 @@ pub fn parse_miptex(i: &[u8]) -> IResult<MipTex> {
     ))
 }
 
+/// Quake textures do not have palette so they get the Quake palette.
+///
+/// Used for WAD2 and Quake BSP.
+pub fn parse_quake_miptex(i: &[u8]) -> IResult<MipTex> {
+    let struct_start = i;
+
+    let (i, texture_name) = count(le_u8, 16)(i)?;
+    let (i, (width, height)) = tuple((le_u32, le_u32))(i)?;
+    let (i, mip_offsets) = count(le_u32, 4)(i)?;
+
+    let mut res = MipTex::new_external("", (width, height));
+    res.texture_name = TextureName(texture_name);
+
+    if mip_offsets[0] == 0 {
+        return Ok((i, res));
+    }
+
+    let mut mip_images = vec![];
+
+    for (mip_level, &mip_offset) in mip_offsets.iter().enumerate() {
+        let pixel_count = (width * height) as usize >> (mip_level * 2);
+        let (_, image) = count(le_u8, pixel_count)(&struct_start[(mip_offset as usize)..])?;
+
+        mip_images.push(MipMap::new(image));
+    }
+
+    res.mip_offsets = mip_offsets;
+    res.mip_images = mip_images;
+    res.colors_used = 256;
+    res.palette = Palette::new(QUAKE_PALETTE);
+
+    Ok((i, res))
+}
+
 fn parse_font(i: &[u8]) -> IResult<Font> {
     let (i, (width, height)) = tuple((le_u32, le_u32))(i)?;
     let (i, (row_count, row_height)) = tuple((le_u32, le_u32))(i)?;
@@ -157,7 +205,7 @@ fn parse_font(i: &[u8]) -> IResult<Font> {
                 charwidth,
             }
         }),
-        256,
+        FONT_CHAR_COUNT,
     )(i)?;
 
     let (i, data) = count(le_u8, (width * height) as usize)(i)?;
@@ -182,7 +230,14 @@ fn parse_font(i: &[u8]) -> IResult<Font> {
     ))
 }
 
-static FILE_TYPES: &[i8] = &[0x42, 0x43, 0x45];
+fn parse_raw_lump<'a>(i: &'a [u8], directory_entry: &DirectoryEntry) -> IResult<'a, RawLump> {
+    map(take(directory_entry.disk_size as usize), |data: &[u8]| {
+        RawLump::new(directory_entry.file_type, data)
+    })(i)
+}
+
+static FILE_TYPES: &[i8] = &[QPIC_FILE_TYPE, MIPTEX_FILE_TYPE, FONT_FILE_TYPE];
+static WAD2_FILE_TYPES: &[i8] = &[QPIC_FILE_TYPE, WAD2_MIPTEX_FILE_TYPE];
 
 pub fn parse_wad(i: &[u8]) -> IResult<Wad> {
     let file_start = i;
@@ -208,27 +263,39 @@ pub fn parse_wad(i: &[u8]) -> IResult<Wad> {
         return context(err_str, fail)(b"");
     }
 
-    if directory_entries
-        .iter()
-        .any(|entry| !FILE_TYPES.contains(&entry.file_type))
-    {
-        let err_str = "Unknown texture file type.";
-
-        println!("{}", err_str);
-
-        return context(err_str, fail)(b"");
-    };
+    let is_wad2 = header.is_wad2();
+    let file_types = if is_wad2 { WAD2_FILE_TYPES } else { FILE_TYPES };
 
     let file_entries = directory_entries
         .iter()
         .filter_map(|directory_entry| {
             // the actual WAD data is from the beginning of the file, not the beginning of the directory entry
             let file_entry_start = &file_start[directory_entry.entry_offset as usize..];
+            let raw_lump = || {
+                parse_raw_lump(file_entry_start, directory_entry)
+                    .ok()
+                    .map(|(_, raw)| FileEntry::Raw(raw))
+            };
+
+            // raw lumps like the Quake palette and lumps we do not know are kept as they are
+            if !file_types.contains(&directory_entry.file_type) {
+                return raw_lump();
+            }
 
             let file_entry = match directory_entry.file_type {
-                0x42 => FileEntry::Qpic(parse_qpic(file_entry_start).ok()?.1),
-                0x43 => FileEntry::MipTex(parse_miptex(file_entry_start).ok()?.1),
-                0x45 => FileEntry::Font(parse_font(file_entry_start).ok()?.1),
+                QPIC_FILE_TYPE if is_wad2 => {
+                    FileEntry::Qpic(parse_wad2_qpic(file_entry_start).ok()?.1)
+                }
+                // conchars in Quake gfx.wad is marked as miptex but it is only pixels
+                WAD2_MIPTEX_FILE_TYPE => match parse_quake_miptex(file_entry_start) {
+                    Ok((_, miptex)) if miptex.width != 0 && miptex.height != 0 => {
+                        FileEntry::MipTex(miptex)
+                    }
+                    _ => raw_lump()?,
+                },
+                QPIC_FILE_TYPE => FileEntry::Qpic(parse_qpic(file_entry_start).ok()?.1),
+                MIPTEX_FILE_TYPE => FileEntry::MipTex(parse_miptex(file_entry_start).ok()?.1),
+                FONT_FILE_TYPE => FileEntry::Font(parse_font(file_entry_start).ok()?.1),
                 _ => unreachable!(""),
             };
 
@@ -11,7 +11,11 @@ use byte_writer::ByteWriter;
 use eyre::eyre;
 
 use crate::{
-    constants::{MAX_TEXTURE_NAME_LENGTH, MIPTEX_HEADER_LENGTH},
+    constants::{
+        FONT_CHAR_COUNT, FONT_FILE_TYPE, MAX_TEXTURE_NAME_LENGTH, MIPTEX_FILE_TYPE,
+        MIPTEX_HEADER_LENGTH, QPIC_FILE_TYPE, QUAKE_FULLBRIGHT_START, WAD2_MAGIC,
+        WAD2_MIPTEX_FILE_TYPE, WAD3_MAGIC, WATER_FOG_COLOR_INDEX, WATER_FOG_DENSITY_INDEX,
+    },
     parser::parse_wad,
 };
 
@@ -25,11 +29,23 @@ pub struct Header {
 impl Header {
     pub fn new() -> Self {
         Self {
-            magic: "WAD3".as_bytes().to_owned(),
+            magic: WAD3_MAGIC.to_owned(),
             num_dirs: 0,
             dir_offset: 0,
         }
     }
+
+    pub fn new_wad2() -> Self {
+        Self {
+            magic: WAD2_MAGIC.to_owned(),
+            ..Self::new()
+        }
+    }
+
+    /// Quake WAD, where textures do not have palette.
+    pub fn is_wad2(&self) -> bool {
+        self.magic == WAD2_MAGIC
+    }
 }
 
 impl Default for Header {
@@ -57,7 +73,7 @@ impl DirectoryEntry {
             entry_offset: 0,
             disk_size: 0,
             entry_size: 0,
-            file_type: 0x43,
+            file_type: MIPTEX_FILE_TYPE,
             compressed: false,
             padding: 256,
             texture_name: TextureName::from_string(s),
@@ -152,6 +168,55 @@ impl Palette {
     pub fn get_bytes(&self) -> &Vec<[u8; 3]> {
         &self.0
     }
+
+    /// Reads a palette lump such as Quake `gfx/palette.lmp`, 256 RGB colors.
+    pub fn from_lmp(bytes: &[u8]) -> eyre::Result<Self> {
+        if bytes.len() != 256 * 3 {
+            return Err(eyre!(
+                "Palette must be {} bytes but got {}",
+                256 * 3,
+                bytes.len()
+            ));
+        }
+
+        Ok(Self(
+            bytes
+                .chunks_exact(3)
+                .map(|color| [color[0], color[1], color[2]])
+                .collect(),
+        ))
+    }
+}
+
+/// For every color of `source`, finds the closest color of `target` within `target_range`.
+///
+/// Index 255 stays 255 if `keep_last` is set, for transparent textures.
+fn palette_lookup(
+    source: &[[u8; 3]],
+    target: &[[u8; 3]],
+    target_range: std::ops::Range<usize>,
+    keep_last: bool,
+) -> Vec<u8> {
+    (0..256)
+        .map(|index| {
+            let color = source.get(index).unwrap_or(&[0, 0, 0]);
+
+            if keep_last && index == 255 {
+                return 255;
+            }
+
+            target_range
+                .clone()
+                .min_by_key(|&target_index| {
+                    let other = target[target_index];
+
+                    (0..3)
+                        .map(|i| (color[i] as i32 - other[i] as i32).pow(2))
+                        .sum::<i32>()
+                })
+                .unwrap_or(0) as u8
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -165,6 +230,57 @@ pub struct Qpic {
     pub palette: Palette,
 }
 
+impl Qpic {
+    pub fn new(
+        (width, height): (u32, u32),
+        image: &[u8],
+        palette: impl Into<Vec<[u8; 3]>>,
+    ) -> Self {
+        let palette = Palette::new(palette);
+
+        Self {
+            width,
+            height,
+            data: Image::new(image),
+            colors_used: palette.get_bytes().len() as i16,
+            palette,
+        }
+    }
+
+    pub fn write(&self, writer: &mut ByteWriter) {
+        self.write_without_palette(writer);
+
+        write_palette(self.palette.get_bytes(), writer);
+    }
+
+    /// Writes the picture the way WAD2 has it.
+    pub fn write_without_palette(&self, writer: &mut ByteWriter) {
+        writer.append_u32(self.width);
+        writer.append_u32(self.height);
+
+        writer.append_u8_slice(self.data.get_bytes());
+    }
+
+    fn remap(&mut self, lookup: &[u8], palette: &[[u8; 3]]) {
+        self.data
+            .0
+            .iter_mut()
+            .for_each(|index| *index = lookup[*index as usize]);
+
+        self.colors_used = palette.len() as i16;
+        self.palette = Palette::new(palette);
+    }
+}
+
+/// Writes colors used and then the palette.
+fn write_palette(palette: &[[u8; 3]], writer: &mut ByteWriter) {
+    writer.append_i16(palette.len() as i16);
+
+    for row in palette {
+        writer.append_u8_slice(row);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MipMap {
     // [[u8; width]; height]
@@ -228,6 +344,26 @@ impl MipTex {
         }
     }
 
+    /// Creates a texture without image data.
+    ///
+    /// This is how BSP refers to a texture from a WAD file.
+    pub fn new_external(s: impl AsRef<str> + Into<String>, (width, height): (u32, u32)) -> Self {
+        Self {
+            texture_name: TextureName::from_string(s),
+            width,
+            height,
+            mip_offsets: vec![0; 4],
+            mip_images: vec![],
+            colors_used: 0,
+            palette: Palette::new(vec![]),
+        }
+    }
+
+    /// Whether the texture has no image data, which means it is in a WAD file instead.
+    pub fn is_external(&self) -> bool {
+        self.mip_images.is_empty()
+    }
+
     /// Returns RGB image and dimensions
     pub fn to_rgb(&self) -> (Vec<u8>, (u32, u32)) {
         let image = self.mip_images[0]
@@ -256,6 +392,25 @@ impl MipTex {
     }
 
     pub fn write(&self, writer: &mut ByteWriter) {
+        self.write_without_palette(writer);
+
+        if self.is_external() {
+            return;
+        }
+
+        // colors_used
+        writer.append_i16(256);
+
+        for row in self.palette.get_bytes() {
+            writer.append_u8_slice(row);
+        }
+
+        // pad palette to correctly have 256 colors
+        writer.append_u8_slice(&vec![0u8; (256 - self.palette.get_bytes().len()) * 3]);
+    }
+
+    /// Writes the texture the way WAD2 and Quake BSP have it.
+    pub fn write_without_palette(&self, writer: &mut ByteWriter) {
         let texture_name_bytes = self.texture_name.get_bytes();
         writer.append_u8_slice(texture_name_bytes);
         writer.append_u8_slice(&vec![0u8; 16 - texture_name_bytes.len()]);
@@ -263,6 +418,12 @@ impl MipTex {
         writer.append_u32(self.width);
         writer.append_u32(self.height);
 
+        // external texture has zero offsets and nothing else
+        if self.is_external() {
+            writer.append_u8_slice(&[0u8; 16]);
+            return;
+        }
+
         // mip_offsets
         writer.append_u32(MIPTEX_HEADER_LENGTH);
         writer.append_u32(MIPTEX_HEADER_LENGTH + self.width * self.height);
@@ -276,28 +437,35 @@ impl MipTex {
                 + (self.width * self.height) / 4 / 4,
         );
 
-        // if no mipimages then don't write anything more
-        if self.mip_images.is_empty() {
-            return;
-        }
-
         // mip images
         for image in &self.mip_images {
             writer.append_u8_slice(image.data.get_bytes());
         }
+    }
 
-        // colors_used
-        writer.append_i16(256);
-
-        for row in self.palette.get_bytes() {
-            writer.append_u8_slice(row);
-        }
-
-        // pad palette to correctly have 256 colors
-        writer.append_u8_slice(&vec![0u8; (256 - self.palette.get_bytes().len()) * 3]);
+    /// Changes palette indices with a lookup table from [`palette_lookup`].
+    fn remap(&mut self, lookup: &[u8], palette: &[[u8; 3]]) {
+        self.mip_images.iter_mut().for_each(|mip_image| {
+            mip_image
+                .data
+                .0
+                .iter_mut()
+                .for_each(|index| *index = lookup[*index as usize]);
+        });
+
+        self.colors_used = palette.len() as i16;
+        self.palette = Palette::new(palette);
     }
 }
 
+/// Fog of a `!` water texture, stored in its palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaterFog {
+    pub color: [u8; 3],
+    /// Higher is thicker.
+    pub density: u8,
+}
+
 #[derive(Debug, Clone)]
 pub struct CharInfo {
     pub startoffset: i16,
@@ -317,6 +485,99 @@ pub struct Font {
     pub palette: Palette,
 }
 
+impl CharInfo {
+    pub fn new(startoffset: i16, charwidth: i16) -> Self {
+        Self {
+            startoffset,
+            charwidth,
+        }
+    }
+}
+
+impl Font {
+    /// `font_info` must have 256 characters.
+    ///
+    /// `startoffset` of a character is the index of its top left pixel in the image.
+    /// Rows are `row_height` tall and there are `row_count` rows.
+    pub fn new(
+        (width, height): (u32, u32),
+        (row_count, row_height): (u32, u32),
+        font_info: Vec<CharInfo>,
+        image: &[u8],
+        palette: impl Into<Vec<[u8; 3]>>,
+    ) -> eyre::Result<Self> {
+        if font_info.len() != FONT_CHAR_COUNT {
+            return Err(eyre!(
+                "Font must have {} characters but got {}.",
+                FONT_CHAR_COUNT,
+                font_info.len()
+            ));
+        }
+
+        if image.len() != (width * height) as usize {
+            return Err(eyre!(
+                "Font image must have {} pixels but got {}.",
+                width * height,
+                image.len()
+            ));
+        }
+
+        let palette = Palette::new(palette);
+
+        Ok(Self {
+            width,
+            height,
+            row_count,
+            row_height,
+            font_info,
+            data: Image::new(image),
+            colors_used: palette.get_bytes().len() as i16,
+            palette,
+        })
+    }
+
+    pub fn write(&self, writer: &mut ByteWriter) {
+        writer.append_u32(self.width);
+        writer.append_u32(self.height);
+        writer.append_u32(self.row_count);
+        writer.append_u32(self.row_height);
+
+        self.font_info.iter().for_each(|char_info| {
+            writer.append_i16(char_info.startoffset);
+            writer.append_i16(char_info.charwidth);
+        });
+
+        writer.append_u8_slice(self.data.get_bytes());
+
+        write_palette(self.palette.get_bytes(), writer);
+    }
+}
+
+/// Lump that is not an image, such as the palette and colormap in Quake `gfx.wad`.
+///
+/// Kept as it is so writing the WAD back does not lose it.
+#[derive(Debug, Clone)]
+pub struct RawLump {
+    pub file_type: i8,
+    pub data: Vec<u8>,
+}
+
+impl RawLump {
+    pub fn new(file_type: i8, data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            file_type,
+            data: data.into(),
+        }
+    }
+
+    pub fn write(&self, writer: &mut ByteWriter) {
+        writer.append_u8_slice(&self.data);
+    }
+}
+
+/// Raw lumps do not have a palette.
+static EMPTY_PALETTE: Vec<[u8; 3]> = Vec::new();
+
 // this is not how it looks in file
 #[derive(Debug, Clone)]
 pub struct Entry {
@@ -337,10 +598,123 @@ impl Entry {
         }
     }
 
+    pub fn new_qpic(
+        texture_name: impl AsRef<str> + Into<String>,
+        dimensions: (u32, u32),
+        image: &[u8],
+        palette: impl Into<Vec<[u8; 3]>>,
+    ) -> Self {
+        let mut directory_entry = DirectoryEntry::new(texture_name);
+        directory_entry.file_type = QPIC_FILE_TYPE;
+
+        Self {
+            directory_entry,
+            file_entry: FileEntry::Qpic(Qpic::new(dimensions, image, palette)),
+        }
+    }
+
+    pub fn new_font(texture_name: impl AsRef<str> + Into<String>, font: Font) -> Self {
+        let mut directory_entry = DirectoryEntry::new(texture_name);
+        directory_entry.file_type = FONT_FILE_TYPE;
+
+        Self {
+            directory_entry,
+            file_entry: FileEntry::Font(font),
+        }
+    }
+
     pub fn texture_name(&self) -> String {
         self.directory_entry.texture_name.get_string()
     }
 
+    /// Water textures start with `!`.
+    pub fn is_water(&self) -> bool {
+        self.texture_name().starts_with('!')
+    }
+
+    /// Checks that the entry is a water texture with a full palette for the fog colors.
+    pub fn validate_water_palette(&self) -> eyre::Result<&MipTex> {
+        if !self.is_water() {
+            return Err(eyre!(
+                "Texture \"{}\" is not water, name must start with \"!\"",
+                self.texture_name()
+            ));
+        }
+
+        let FileEntry::MipTex(miptex) = &self.file_entry else {
+            return Err(eyre!(
+                "Water texture \"{}\" is not a miptex",
+                self.texture_name()
+            ));
+        };
+
+        if miptex.is_external() {
+            return Err(eyre!(
+                "Water texture \"{}\" is not embedded",
+                self.texture_name()
+            ));
+        }
+
+        if miptex.palette.get_bytes().len() != 256 {
+            return Err(eyre!(
+                "Water texture \"{}\" must have 256 colors but has {}",
+                self.texture_name(),
+                miptex.palette.get_bytes().len()
+            ));
+        }
+
+        Ok(miptex)
+    }
+
+    pub fn water_fog(&self) -> eyre::Result<WaterFog> {
+        let palette = self.validate_water_palette()?.palette.get_bytes();
+
+        Ok(WaterFog {
+            color: palette[WATER_FOG_COLOR_INDEX],
+            density: palette[WATER_FOG_DENSITY_INDEX][0],
+        })
+    }
+
+    /// Sets the fog of a water texture.
+    ///
+    /// Pixels using the fog palette indices are changed to the closest other color so the image stays the same.
+    pub fn set_water_fog(&mut self, fog: WaterFog) -> eyre::Result<()> {
+        self.validate_water_palette()?;
+
+        let FileEntry::MipTex(miptex) = &mut self.file_entry else {
+            unreachable!()
+        };
+
+        let fog_indices = [WATER_FOG_COLOR_INDEX, WATER_FOG_DENSITY_INDEX];
+        let mut palette = miptex.palette.get_bytes().clone();
+
+        let lookup = (0..256)
+            .map(|index| {
+                if !fog_indices.contains(&index) {
+                    return index as u8;
+                }
+
+                let color = palette[index];
+
+                (0..256)
+                    .filter(|other| !fog_indices.contains(other))
+                    .min_by_key(|&other| {
+                        (0..3)
+                            .map(|i| (color[i] as i32 - palette[other][i] as i32).pow(2))
+                            .sum::<i32>()
+                    })
+                    .unwrap() as u8
+            })
+            .collect::<Vec<u8>>();
+
+        palette[WATER_FOG_COLOR_INDEX] = fog.color;
+        palette[WATER_FOG_DENSITY_INDEX][0] = fog.density;
+
+        miptex.remap(&lookup, &palette);
+
+        Ok(())
+    }
+
     pub fn set_name(&mut self, s: impl AsRef<str> + Into<String> + Clone) -> eyre::Result<()> {
         self.directory_entry.texture_name.set_name(s.clone())?;
 
@@ -348,6 +722,7 @@ impl Entry {
             FileEntry::Qpic(_) => (),
             FileEntry::MipTex(miptex) => miptex.texture_name.set_name(s)?,
             FileEntry::Font(_) => (),
+            FileEntry::Raw(_) => (),
         }
 
         Ok(())
@@ -359,6 +734,7 @@ pub enum FileEntry {
     Qpic(Qpic),
     MipTex(MipTex),
     Font(Font),
+    Raw(RawLump),
 }
 
 impl FileEntry {
@@ -371,19 +747,40 @@ impl FileEntry {
         Self::MipTex(MipTex::new(texture_name, dimensions, images, palette))
     }
 
+    /// File type in the directory entry
+    pub fn file_type(&self) -> i8 {
+        match &self {
+            Self::Qpic(_) => QPIC_FILE_TYPE,
+            Self::MipTex(_) => MIPTEX_FILE_TYPE,
+            Self::Font(_) => FONT_FILE_TYPE,
+            Self::Raw(raw) => raw.file_type,
+        }
+    }
+
+    /// File type in the directory entry of a WAD2
+    pub fn wad2_file_type(&self) -> i8 {
+        match &self {
+            Self::MipTex(_) => WAD2_MIPTEX_FILE_TYPE,
+            _ => self.file_type(),
+        }
+    }
+
     pub fn dimensions(&self) -> (u32, u32) {
         match &self {
             Self::Qpic(qpic) => (qpic.width, qpic.height),
             Self::MipTex(miptex) => (miptex.width, miptex.height),
             Self::Font(font) => (font.width, font.height),
+            Self::Raw(_) => (0, 0),
         }
     }
 
+    /// Image data, or the bytes of a raw lump as they are in the file.
     pub fn image(&self) -> &Vec<u8> {
         match &self {
             Self::Qpic(qpic) => qpic.data.get_bytes(),
             Self::MipTex(miptex) => miptex.mip_images[0].data.get_bytes(),
             Self::Font(font) => font.data.get_bytes(),
+            Self::Raw(raw) => &raw.data,
         }
     }
 
@@ -392,6 +789,7 @@ impl FileEntry {
             Self::Qpic(qpic) => qpic.palette.get_bytes(),
             Self::MipTex(miptex) => miptex.palette.get_bytes(),
             Self::Font(font) => font.palette.get_bytes(),
+            Self::Raw(_) => &EMPTY_PALETTE,
         }
     }
 }
@@ -417,6 +815,95 @@ impl Wad {
         }
     }
 
+    /// Creates a new Quake WAD file without any information
+    pub fn new_wad2() -> Self {
+        Self {
+            header: Header::new_wad2(),
+            entries: vec![],
+        }
+    }
+
+    pub fn is_wad2(&self) -> bool {
+        self.header.is_wad2()
+    }
+
+    /// Converts to WAD2 where every texture uses `palette`, usually [`crate::QUAKE_PALETTE`].
+    ///
+    /// Textures with a different palette are changed to the closest colors of `palette`.
+    /// Textures do not use the fullbright colors of the Quake palette so they are still affected by light.
+    /// Fonts are not in WAD2.
+    pub fn to_wad2(&self, palette: &[[u8; 3]]) -> eyre::Result<Self> {
+        if palette.len() != 256 {
+            return Err(eyre!("WAD2 palette must have 256 colors"));
+        }
+
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut entry = entry.clone();
+                // transparent textures and pictures use the last color
+                let is_transparent = entry.texture_name().starts_with('{');
+
+                match &mut entry.file_entry {
+                    FileEntry::MipTex(miptex) => {
+                        if !miptex.is_external() && miptex.palette.get_bytes() != palette {
+                            let lookup = palette_lookup(
+                                miptex.palette.get_bytes(),
+                                palette,
+                                0..QUAKE_FULLBRIGHT_START,
+                                is_transparent,
+                            );
+
+                            miptex.remap(&lookup, palette);
+                        }
+                    }
+                    FileEntry::Qpic(qpic) => {
+                        if qpic.palette.get_bytes() != palette {
+                            let lookup =
+                                palette_lookup(qpic.palette.get_bytes(), palette, 0..256, true);
+
+                            qpic.remap(&lookup, palette);
+                        }
+                    }
+                    FileEntry::Font(_) => {
+                        return Err(eyre!("Font \"{}\" cannot be in WAD2", entry.texture_name()))
+                    }
+                    FileEntry::Raw(_) => (),
+                }
+
+                entry.directory_entry.file_type = entry.file_entry.wad2_file_type();
+
+                Ok(entry)
+            })
+            .collect::<eyre::Result<Vec<Entry>>>()?;
+
+        Ok(Self {
+            header: Header::new_wad2(),
+            entries,
+        })
+    }
+
+    /// Converts to WAD3 where every texture has its own palette.
+    ///
+    /// Textures keep the palette they have, which is the Quake palette for textures from WAD2, so nothing is lost.
+    pub fn to_wad3(&self) -> Self {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut entry = entry.clone();
+                entry.directory_entry.file_type = entry.file_entry.file_type();
+                entry
+            })
+            .collect();
+
+        Self {
+            header: Header::new(),
+            entries,
+        }
+    }
+
     pub fn from_bytes(bytes: &[u8]) -> eyre::Result<Self> {
         match parse_wad(bytes) {
             Ok((_, res)) => Ok(res),
@@ -455,6 +942,7 @@ impl Wad {
 
         // write header
         let header = &self.header;
+        let is_wad2 = header.is_wad2();
 
         writer.append_u8_slice(&header.magic);
 
@@ -479,21 +967,40 @@ impl Wad {
 
                 // write file entry
                 match file_entry {
-                    FileEntry::Qpic(_) => unimplemented!(),
+                    FileEntry::Qpic(qpic) if is_wad2 => {
+                        qpic.write_without_palette(&mut writer);
+                    }
+                    FileEntry::Qpic(qpic) => {
+                        qpic.write(&mut writer);
+                    }
+                    FileEntry::MipTex(miptex) if is_wad2 => {
+                        miptex.write_without_palette(&mut writer);
+                    }
                     FileEntry::MipTex(miptex) => {
                         miptex.write(&mut writer);
                     }
-                    FileEntry::Font(_) => todo!(),
+                    FileEntry::Font(font) => {
+                        font.write(&mut writer);
+                    }
+                    FileEntry::Raw(raw) => {
+                        raw.write(&mut writer);
+                    }
                 }
 
                 // apparently, if we want compatibility with Wally, we need to align the bytes
-                let offset_bytes_needed = writer.get_offset() % 4;
+                let offset_bytes_needed = (4 - writer.get_offset() % 4) % 4;
 
                 for _ in 0..offset_bytes_needed {
                     writer.append_u8(0);
                 }
 
-                (file_entry_offset, writer.get_offset() - file_entry_offset)
+                // raw lumps keep their size so reading them back gives the same bytes
+                let length = match file_entry {
+                    FileEntry::Raw(raw) => raw.data.len(),
+                    _ => writer.get_offset() - file_entry_offset,
+                };
+
+                (file_entry_offset, length)
             })
             .collect::<Vec<(usize, usize)>>();
 
@@ -509,7 +1016,7 @@ impl Wad {
                     entry_offset: _,
                     disk_size: _,
                     entry_size: _,
-                    file_type,
+                    file_type: _,
                     compressed: _,
                     padding: _,
                     texture_name,
@@ -519,9 +1026,16 @@ impl Wad {
                 writer.append_i32(offset as i32);
                 writer.append_i32(length as i32);
                 writer.append_i32(length as i32);
-                writer.append_i8(*file_type);
-                writer.append_i8(0); // not compressed
-                writer.append_i16(256); // hard coded number of colors
+                // file type follows the entry so changing entry type does not need updating directory
+                if is_wad2 {
+                    writer.append_i8(entry.file_entry.wad2_file_type());
+                    writer.append_i8(0); // not compressed
+                    writer.append_i16(0);
+                } else {
+                    writer.append_i8(entry.file_entry.file_type());
+                    writer.append_i8(0); // not compressed
+                    writer.append_i16(256); // hard coded number of colors
+                }
 
                 let texture_name_bytes = texture_name.get_bytes();
                 writer.append_u8_slice(texture_name_bytes);